@@ -0,0 +1,810 @@
+//! This module contains batch generation from job files.
+//!
+//! A job file lists one generation per line, either as JSONL or as CSV with a header row. Every
+//! job names its subcommand in `type` and accepts the options of that subcommand:
+//!
+//! ```text
+//! {"type": "t2i", "prompt": "A lighthouse at dawn.", "seed": 7, "output": "campaign/lighthouse.{ext}"}
+//! {"type": "i2i", "prompt": "Make it snowy.", "image": "inputs/street.jpg", "strength": 0.6}
+//! {"type": "cnet", "prompt": "A watercolor city.", "image": "inputs/city.png", "net": "canny-1.1"}
+//! ```
+//!
+//! Relative input image paths are resolved against the directory of the job file. Options a job
+//! leaves unset fall back to the configuration profile and then to the model defaults.
+
+use anyhow::{bail, Context, Result};
+use indicatif::{ProgressBar, ProgressStyle};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, VecDeque};
+use std::fs::File;
+use std::future::Future;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::{mpsc, Semaphore};
+use tokio::task::JoinSet;
+
+use crate::cli::{
+    ControlNet, Edit, ImageToImage, Postprocess, PreprocessArgs, Repaint, TextToImage,
+};
+use crate::client::Client;
+use crate::config::{CommandDefaults, Profile};
+use crate::report;
+use crate::request::GenerationRequest;
+use crate::utils::{random_seed, write_atomic};
+
+/// Enum representing the subcommand a job runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum JobKind {
+    /// Text-to-image generation.
+    #[serde(rename = "t2i")]
+    TextToImage,
+    /// Image-to-image generation.
+    #[serde(rename = "i2i")]
+    ImageToImage,
+    /// ControlNet generation.
+    #[serde(rename = "cnet")]
+    ControlNet,
+    /// Repainting (inpainting).
+    #[serde(rename = "paint")]
+    Repaint,
+    /// Instruction-based editing.
+    #[serde(rename = "edit")]
+    Edit,
+}
+
+impl JobKind {
+    /// Returns the name of the CLI subcommand matching this kind.
+    pub fn command(&self) -> &'static str {
+        match self {
+            Self::TextToImage => "t2i",
+            Self::ImageToImage => "i2i",
+            Self::ControlNet => "cnet",
+            Self::Repaint => "paint",
+            Self::Edit => "edit",
+        }
+    }
+}
+
+/// Struct representing one line of a job file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Job {
+    /// Subcommand of the job.
+    #[serde(rename = "type")]
+    pub kind: JobKind,
+    /// Identifier copied to the results file, e.g. an asset id.
+    pub id: Option<String>,
+    /// Text prompt.
+    pub prompt: String,
+    /// Model, for `t2i` and `i2i` jobs.
+    pub model: Option<String>,
+    /// Path to the input image.
+    pub image: Option<String>,
+    /// Path to the mask image, for `paint` jobs.
+    pub mask_image: Option<String>,
+    /// Seed. A random seed is picked when omitted.
+    pub seed: Option<usize>,
+    /// Output path template of the job, see [`crate::output`].
+    pub output: Option<String>,
+    /// Text input that will not guide the image generation.
+    pub negative_prompt: Option<String>,
+    /// Width of the image.
+    pub width: Option<usize>,
+    /// Height of the image.
+    pub height: Option<usize>,
+    /// Number of steps.
+    pub steps: Option<usize>,
+    /// Guidance scale.
+    pub guidance: Option<f64>,
+    /// Scheduler name.
+    pub scheduler: Option<String>,
+    /// Strength of the input image or conditioning.
+    pub strength: Option<f64>,
+    /// Image guidance of instruction-based editing.
+    pub image_guidance: Option<f64>,
+    /// Output format.
+    pub output_format: Option<String>,
+    /// ControlNet conditioning type.
+    pub net: Option<String>,
+}
+
+impl Job {
+    /// Returns the client sending the job, without its cache if the job leaves its seed random:
+    /// responses to random seeds are never requested again.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - The client of the run.
+    pub fn client(&self, client: &Client) -> Client {
+        match self.seed {
+            Some(_) => client.clone(),
+            None => client.clone().with_cache(None),
+        }
+    }
+
+    /// Builds the request of the job.
+    ///
+    /// # Arguments
+    ///
+    /// * `model` - Model used when the job names none.
+    /// * `profile` - Configuration profile providing defaults for unset options.
+    /// * `preprocess` - How input images are prepared before upload.
+    /// * `base` - Directory relative input image paths are resolved against.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the request, or an error if an input image is missing or unreadable.
+    pub fn request(
+        &self,
+        model: &str,
+        profile: &Profile,
+        preprocess: &PreprocessArgs,
+        base: &Path,
+    ) -> Result<GenerationRequest> {
+        let options = self.options();
+        let prompt = self.prompt.clone();
+        let seed = Some(self.seed.unwrap_or_else(random_seed));
+        let model = self.model.as_deref().unwrap_or(model);
+        let preprocess = preprocess.clone();
+        match self.kind {
+            JobKind::TextToImage => {
+                let mut cmd = TextToImage {
+                    prompt,
+                    prompt_file: None,
+                    negative_prompt: None,
+                    width: None,
+                    height: None,
+                    steps: None,
+                    seed,
+                    output_format: None,
+                };
+                cmd.apply_defaults(&options);
+                cmd.apply_defaults(&profile.t2i);
+                Ok(cmd.request(model))
+            }
+            JobKind::ImageToImage => {
+                let mut cmd = ImageToImage {
+                    prompt,
+                    prompt_file: None,
+                    negative_prompt: None,
+                    image: self.input("image", &self.image, base)?,
+                    strength: None,
+                    steps: None,
+                    output_format: None,
+                    seed,
+                    concurrency: 1,
+                    preprocess,
+                };
+                cmd.apply_defaults(&options);
+                cmd.apply_defaults(&profile.i2i);
+                cmd.request(model)
+            }
+            JobKind::ControlNet => {
+                let mut cmd = ControlNet {
+                    net: None,
+                    prompt,
+                    prompt_file: None,
+                    negative_prompt: None,
+                    image: self.input("image", &self.image, base)?,
+                    strength: None,
+                    width: None,
+                    height: None,
+                    steps: None,
+                    guidance: None,
+                    output_format: None,
+                    seed,
+                    scheduler: None,
+                    concurrency: 1,
+                    preprocess,
+                };
+                cmd.apply_defaults(&options);
+                cmd.apply_defaults(&profile.cnet);
+                cmd.request()
+            }
+            JobKind::Repaint => {
+                let mut cmd = Repaint {
+                    prompt,
+                    prompt_file: None,
+                    negative_prompt: None,
+                    image: self.input("image", &self.image, base)?,
+                    mask_image: Some(self.input("mask_image", &self.mask_image, base)?),
+                    region: Vec::new(),
+                    width: None,
+                    height: None,
+                    steps: None,
+                    scheduler: None,
+                    seed,
+                    strength: None,
+                    guidance: None,
+                    output_format: None,
+                    concurrency: 1,
+                    preprocess,
+                };
+                cmd.apply_defaults(&options);
+                cmd.apply_defaults(&profile.paint);
+                cmd.request()
+            }
+            JobKind::Edit => {
+                let mut cmd = Edit {
+                    prompt,
+                    prompt_file: None,
+                    negative_prompt: None,
+                    image: self.input("image", &self.image, base)?,
+                    guidance: None,
+                    steps: None,
+                    seed,
+                    scheduler: None,
+                    output_format: None,
+                    image_guidance: None,
+                    concurrency: 1,
+                    preprocess,
+                };
+                cmd.apply_defaults(&options);
+                cmd.apply_defaults(&profile.edit);
+                cmd.request()
+            }
+        }
+    }
+
+    /// Returns the generation options set by the job.
+    pub fn options(&self) -> CommandDefaults {
+        CommandDefaults {
+            negative_prompt: self.negative_prompt.clone(),
+            width: self.width,
+            height: self.height,
+            steps: self.steps,
+            guidance: self.guidance,
+            scheduler: self.scheduler.clone(),
+            strength: self.strength,
+            image_guidance: self.image_guidance,
+            output_format: self.output_format.clone(),
+            net: self.net.clone(),
+        }
+    }
+
+    /// Returns a short hash of the job, used to detect jobs edited between runs.
+    pub fn fingerprint(&self) -> String {
+        let json = serde_json::to_vec(self).unwrap_or_default();
+        Sha256::digest(json)[..8]
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    fn input(&self, field: &str, path: &Option<String>, base: &Path) -> Result<String> {
+        let Some(path) = path else {
+            bail!("`{}` jobs require `{}`", self.kind.command(), field);
+        };
+        Ok(base.join(path).to_string_lossy().into_owned())
+    }
+}
+
+/// Reads a job file, as CSV if its extension is `.csv` and as JSONL otherwise.
+///
+/// Blank lines and lines starting with `#` are skipped in JSONL files.
+///
+/// # Arguments
+///
+/// * `path` - Path to the job file.
+///
+/// # Returns
+///
+/// A `Result` containing the jobs, or an error naming the first invalid line.
+pub fn read_jobs(path: &Path) -> Result<Vec<Job>> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read the job file {}", path.display()))?;
+    let is_csv = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("csv"));
+    let jobs = if is_csv {
+        parse_csv(&text)
+    } else {
+        parse_jsonl(&text)
+    }
+    .with_context(|| format!("invalid job file {}", path.display()))?;
+    if jobs.is_empty() {
+        bail!("the job file {} contains no jobs", path.display());
+    }
+    Ok(jobs)
+}
+
+/// Parses JSONL jobs.
+///
+/// # Arguments
+///
+/// * `text` - The JSONL text.
+pub fn parse_jsonl(text: &str) -> Result<Vec<Job>> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .map(|(number, line)| {
+            serde_json::from_str(line).with_context(|| format!("line {}", number + 1))
+        })
+        .collect()
+}
+
+/// Parses CSV jobs. The header row names the job fields; empty cells leave options unset.
+///
+/// # Arguments
+///
+/// * `text` - The CSV text.
+pub fn parse_csv(text: &str) -> Result<Vec<Job>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(text.as_bytes());
+    reader
+        .deserialize::<Job>()
+        .enumerate()
+        .map(|(number, job)| job.with_context(|| format!("line {}", number + 2)))
+        .collect()
+}
+
+/// Struct representing one line of the results file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JobResult {
+    /// Position of the job in the job file, starting at 0.
+    pub index: usize,
+    /// Identifier of the job, if it has one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// Subcommand of the job.
+    #[serde(rename = "type")]
+    pub kind: JobKind,
+    /// Text prompt of the job.
+    pub prompt: String,
+    /// Whether the image was generated and saved.
+    pub ok: bool,
+    /// Path of the saved image.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    /// Seed of the generation.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<usize>,
+    /// Cost of the generation.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cost: Option<f64>,
+    /// Error message of a failed job.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Enum representing the progress of a job across runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    /// The job has not been started.
+    Pending,
+    /// The job was sent and its result not yet saved.
+    InFlight,
+    /// The image was generated and saved.
+    Done,
+    /// The job failed and is retried on resume.
+    Failed,
+}
+
+/// Struct representing the persisted state of one job.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JobState {
+    /// Fingerprint of the job options, so that edited jobs are run again on resume.
+    pub fingerprint: String,
+    /// Progress of the job.
+    pub status: JobStatus,
+    /// Number of times the job was started.
+    pub attempts: usize,
+    /// Path of the saved image.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    /// Seed of the generation.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<usize>,
+    /// Cost of the generation.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cost: Option<f64>,
+    /// Error message of the last failed attempt.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Struct representing the persisted state of a batch, one entry per job of the job file.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BatchState {
+    /// States of the jobs, in job file order.
+    pub jobs: Vec<JobState>,
+}
+
+impl BatchState {
+    /// Creates the state of a batch where every job is pending.
+    ///
+    /// # Arguments
+    ///
+    /// * `jobs` - The jobs of the batch.
+    pub fn new(jobs: &[Job]) -> Self {
+        Self {
+            jobs: jobs
+                .iter()
+                .map(|job| JobState {
+                    fingerprint: job.fingerprint(),
+                    status: JobStatus::Pending,
+                    attempts: 0,
+                    path: None,
+                    seed: None,
+                    cost: None,
+                    error: None,
+                })
+                .collect(),
+        }
+    }
+
+    /// Reads a state file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the state file.
+    pub fn open(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read the batch state {}", path.display()))?;
+        serde_json::from_str(&text)
+            .with_context(|| format!("invalid batch state {}", path.display()))
+    }
+
+    /// Writes the state file, see [`write_atomic`].
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the state file.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        write_atomic(path, &serde_json::to_vec_pretty(self)?)
+            .with_context(|| format!("failed to write the batch state {}", path.display()))
+    }
+
+    /// Prepares a previous state for resuming: completed jobs are kept, while failed,
+    /// interrupted, edited and new jobs become pending.
+    ///
+    /// Jobs are matched by fingerprint, which covers their `id`, rather than by position, so that
+    /// inserting or removing lines of the job file does not run the following jobs again. Each
+    /// previous entry is used at most once, in order, so duplicated jobs run once per copy.
+    ///
+    /// # Arguments
+    ///
+    /// * `jobs` - The jobs of the job file, possibly edited since the previous run.
+    pub fn resume(self, jobs: &[Job]) -> Self {
+        let mut previous = BTreeMap::<String, VecDeque<JobState>>::new();
+        for old in self.jobs {
+            previous
+                .entry(old.fingerprint.clone())
+                .or_default()
+                .push_back(old);
+        }
+        let mut state = Self::new(jobs);
+        for job in &mut state.jobs {
+            let old = previous
+                .get_mut(&job.fingerprint)
+                .and_then(VecDeque::pop_front);
+            if let Some(old) = old {
+                *job = JobState {
+                    status: match old.status {
+                        JobStatus::Done => JobStatus::Done,
+                        _ => JobStatus::Pending,
+                    },
+                    ..old
+                };
+            }
+        }
+        state
+    }
+
+    /// Returns the number of jobs with the given status.
+    ///
+    /// # Arguments
+    ///
+    /// * `status` - The status to count.
+    pub fn count(&self, status: JobStatus) -> usize {
+        self.jobs.iter().filter(|job| job.status == status).count()
+    }
+}
+
+/// Struct representing the outcome of a batch run.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Summary {
+    /// Number of jobs that produced an image.
+    pub succeeded: usize,
+    /// Number of jobs that failed.
+    pub failed: usize,
+    /// Number of jobs skipped because a previous run completed them.
+    pub skipped: usize,
+    /// Number of jobs left pending because the run was interrupted.
+    pub pending: usize,
+    /// Total cost reported by the API during this run.
+    pub cost: f64,
+}
+
+/// Struct representing everything a batch needs to run its jobs.
+#[derive(Debug, Clone)]
+pub struct Runner {
+    /// Client sending the requests.
+    pub client: Client,
+    /// Model used by jobs naming none.
+    pub model: String,
+    /// Configuration profile providing defaults.
+    pub profile: Profile,
+    /// How input images are prepared before upload.
+    pub preprocess: PreprocessArgs,
+    /// How generated images are finished and saved.
+    pub postprocess: Postprocess,
+    /// Directory relative input image paths are resolved against.
+    pub base: PathBuf,
+    /// Maximum number of jobs in flight.
+    pub concurrency: usize,
+}
+
+impl Runner {
+    /// Runs the jobs that are not done yet with bounded concurrency, showing a progress bar,
+    /// saving the batch state after every change and appending one line per finished job to the
+    /// results file.
+    ///
+    /// On Ctrl-C no further jobs are started and the run ends once the jobs in flight finish;
+    /// a second Ctrl-C stops immediately, leaving those jobs in flight in the state.
+    ///
+    /// # Arguments
+    ///
+    /// * `jobs` - The jobs of the batch.
+    /// * `state` - The state of the batch, with one entry per job.
+    /// * `state_path` - Path of the state file.
+    /// * `results` - The results JSONL file.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the summary, or an error if the state or results cannot be written.
+    /// Failing jobs are recorded instead of aborting the batch.
+    pub async fn run(
+        self,
+        jobs: Vec<Job>,
+        state: &mut BatchState,
+        state_path: &Path,
+        mut results: File,
+    ) -> Result<Summary> {
+        if state.jobs.len() != jobs.len() {
+            bail!(
+                "the batch state has {} jobs but the job file has {}",
+                state.jobs.len(),
+                jobs.len()
+            );
+        }
+        let mut summary = Summary {
+            skipped: state.count(JobStatus::Done),
+            ..Summary::default()
+        };
+        let progress = if report::quiet() {
+            ProgressBar::hidden()
+        } else {
+            ProgressBar::new((jobs.len() - summary.skipped) as u64)
+        };
+        progress.set_style(ProgressStyle::with_template(
+            "{bar:40.cyan/blue} {pos}/{len} jobs [{elapsed_precise}<{eta_precise}] {msg}",
+        )?);
+
+        let runner = Arc::new(self);
+        let (started, mut starts) = mpsc::unbounded_channel();
+        let pending = jobs
+            .into_iter()
+            .enumerate()
+            .filter(|(index, _)| state.jobs[*index].status != JobStatus::Done);
+        // The semaphore is closed on Ctrl-C, leaving jobs not yet started pending.
+        let (semaphore, mut tasks) = spawn_bounded(runner.concurrency, pending, |(index, job)| {
+            let runner = runner.clone();
+            let started = started.clone();
+            async move {
+                let _ = started.send(index);
+                let outcome = runner.run_job(index, &job).await;
+                (index, job, outcome)
+            }
+        });
+        drop(started);
+
+        let mut interrupted = false;
+        // A single listener, so that a Ctrl-C arriving while results are saved is not lost.
+        let ctrl_c = tokio::signal::ctrl_c();
+        tokio::pin!(ctrl_c);
+        loop {
+            // Start notifications are handled first so that a job is never marked in flight
+            // after its result was recorded.
+            let task = tokio::select! {
+                biased;
+                Some(index) = starts.recv() => {
+                    let job = &mut state.jobs[index];
+                    job.status = JobStatus::InFlight;
+                    job.attempts += 1;
+                    state.save(state_path)?;
+                    continue;
+                }
+                task = tasks.join_next() => match task {
+                    Some(task) => task?,
+                    None => break,
+                },
+                _ = &mut ctrl_c => {
+                    if interrupted {
+                        tasks.abort_all();
+                        break;
+                    }
+                    interrupted = true;
+                    ctrl_c.set(tokio::signal::ctrl_c());
+                    semaphore.close();
+                    progress.suspend(|| {
+                        eprintln!(
+                            "Interrupted, finishing the jobs in flight. Press Ctrl-C again to stop now."
+                        )
+                    });
+                    continue;
+                }
+            };
+            let Some((index, job, outcome)) = task else {
+                continue;
+            };
+            let entry = &mut state.jobs[index];
+            let result = match outcome {
+                Ok((path, seed, cost)) => {
+                    summary.succeeded += 1;
+                    summary.cost += cost.unwrap_or(0.0);
+                    entry.status = JobStatus::Done;
+                    entry.path = Some(path.clone());
+                    entry.seed = seed;
+                    entry.cost = cost;
+                    entry.error = None;
+                    JobResult {
+                        index,
+                        id: job.id,
+                        kind: job.kind,
+                        prompt: job.prompt,
+                        ok: true,
+                        path: Some(path),
+                        seed,
+                        cost,
+                        error: None,
+                    }
+                }
+                Err(err) => {
+                    summary.failed += 1;
+                    progress.suspend(|| {
+                        eprintln!(
+                            "Job {}{} failed: {:#}",
+                            index,
+                            job.id
+                                .as_ref()
+                                .map(|id| format!(" ({})", id))
+                                .unwrap_or_default(),
+                            err
+                        )
+                    });
+                    entry.status = JobStatus::Failed;
+                    entry.error = Some(format!("{:#}", err));
+                    JobResult {
+                        index,
+                        id: job.id,
+                        kind: job.kind,
+                        prompt: job.prompt,
+                        ok: false,
+                        path: None,
+                        seed: job.seed,
+                        cost: None,
+                        error: entry.error.clone(),
+                    }
+                }
+            };
+            state.save(state_path)?;
+            writeln!(results, "{}", serde_json::to_string(&result)?)?;
+            results.flush()?;
+            progress.set_message(format!("{} failed", summary.failed));
+            progress.inc(1);
+        }
+        state.save(state_path)?;
+        progress.finish_and_clear();
+        summary.pending = state.jobs.len() - summary.skipped - summary.succeeded - summary.failed;
+        Ok(summary)
+    }
+
+    /// Builds, sends and saves one job.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - Position of the job, substituted for `{index}` in its output path.
+    /// * `job` - The job.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the path, seed and cost of the saved image.
+    pub(crate) async fn run_job(
+        self: &Arc<Self>,
+        index: usize,
+        job: &Job,
+    ) -> Result<(PathBuf, Option<usize>, Option<f64>)> {
+        let runner = self.clone();
+        let task = job.clone();
+        let request = tokio::task::spawn_blocking(move || {
+            task.request(
+                &runner.model,
+                &runner.profile,
+                &runner.preprocess,
+                &runner.base,
+            )
+        })
+        .await??;
+
+        let response = job.client(&self.client).send(&request).await?;
+        let (seed, cost) = (response.seed.or(request.seed()), response.cost);
+
+        let runner = self.clone();
+        let output = job.output.clone();
+        let saved = tokio::task::spawn_blocking(move || {
+            runner
+                .postprocess
+                .save(&request, &response, output.as_deref(), index)
+        })
+        .await??;
+        Ok((saved.path, seed, cost))
+    }
+}
+
+/// Spawns one task per item that waits for one of `concurrency` permits before running, so that
+/// at most `concurrency` tasks run at the same time and the next one starts as soon as one ends.
+///
+/// # Arguments
+///
+/// * `concurrency` - Maximum number of tasks running at the same time.
+/// * `items` - The items, one per task.
+/// * `run` - Builds the future run for an item once its task holds a permit.
+///
+/// # Returns
+///
+/// The semaphore, whose closing leaves the tasks not started yet with a `None` output, and the
+/// tasks.
+pub fn spawn_bounded<I, T, F, Fut>(
+    concurrency: usize,
+    items: impl IntoIterator<Item = I>,
+    run: F,
+) -> (Arc<Semaphore>, JoinSet<Option<T>>)
+where
+    F: Fn(I) -> Fut,
+    Fut: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    let semaphore = Arc::new(Semaphore::new(concurrency.max(1)));
+    let mut tasks = JoinSet::new();
+    for item in items {
+        // Futures do nothing until polled, so the work only starts once a permit is held.
+        let future = run(item);
+        let semaphore = semaphore.clone();
+        tasks.spawn(async move {
+            let _permit = semaphore.acquire_owned().await.ok()?;
+            Some(future.await)
+        });
+    }
+    (semaphore, tasks)
+}
+
+/// Returns the default results path of a job file, e.g. `jobs.results.jsonl` for `jobs.jsonl`.
+///
+/// # Arguments
+///
+/// * `jobs` - Path to the job file.
+pub fn results_path(jobs: &Path) -> PathBuf {
+    let stem = jobs
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "jobs".to_string());
+    jobs.with_file_name(format!("{}.results.jsonl", stem))
+}
+
+/// Returns the default state path of a job file, e.g. `jobs.state.json` for `jobs.jsonl`.
+///
+/// # Arguments
+///
+/// * `jobs` - Path to the job file.
+pub fn state_path(jobs: &Path) -> PathBuf {
+    let stem = jobs
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "jobs".to_string());
+    jobs.with_file_name(format!("{}.state.json", stem))
+}
@@ -0,0 +1,294 @@
+//! This module contains the local response cache of the client.
+//!
+//! When a [`Cache`] is set on a [`crate::client::Client`], every response to a request with a
+//! seed is stored on disk, and an identical request later returns the stored response instead of
+//! paying for the same image again. Requests are identified by a SHA-256 hash of their endpoint
+//! and canonical JSON body, in which input images are replaced by their own hash:
+//!
+//! ```text
+//! {"body":{"image":"sha256:1f0c…","model":"…","prompt":"…","seed":42,…},"url":"…/image-to-image"}
+//! ```
+//!
+//! Each entry is stored as `{key}.{created}.json`, where `{created}` is the time it was stored in
+//! seconds since the Unix epoch, so that entries are aged without reading them. Entries expire
+//! after a time to live, and the least recently used ones are removed once the cache grows past
+//! its size limit. Cached responses report a cost of 0, since nothing is billed.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::client::PreparedRequest;
+use crate::response::ToImageResponse;
+use crate::utils::write_atomic;
+
+/// Default size limit of a cache, 512 MiB.
+pub const DEFAULT_MAX_BYTES: u64 = 512 * 1024 * 1024;
+
+/// Default time to live of cache entries, 30 days.
+pub const DEFAULT_TTL: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// Fields of request bodies holding base64 input images.
+const IMAGE_FIELDS: [&str; 2] = ["image", "mask_image"];
+
+/// Struct representing an on-disk cache of API responses, one JSON file per request.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cache {
+    /// Directory holding the entries.
+    pub dir: PathBuf,
+    /// Total size of the entries above which the least recently used ones are removed.
+    pub max_bytes: u64,
+    /// Time after which entries expire, or `None` to keep them until evicted.
+    pub ttl: Option<Duration>,
+}
+
+/// Struct representing a stored response, with the request it answers.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entry {
+    /// Canonical request, see [`Cache::canonical`].
+    request: serde_json::Value,
+    /// The response.
+    response: ToImageResponse,
+}
+
+/// Struct representing the contents of a cache, as printed by `getimg cache stats`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct CacheStats {
+    /// Directory holding the entries.
+    pub dir: PathBuf,
+    /// Number of entries.
+    pub entries: usize,
+    /// Total size of the entries in bytes.
+    pub bytes: u64,
+    /// Number of expired entries, removed on the next write.
+    pub expired: usize,
+    /// Age of the oldest entry in seconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub oldest: Option<u64>,
+    /// Age of the newest entry in seconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub newest: Option<u64>,
+}
+
+impl Cache {
+    /// Creates a cache in a directory with the default size limit and time to live.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - Directory holding the entries, created on the first write.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            max_bytes: DEFAULT_MAX_BYTES,
+            ttl: Some(DEFAULT_TTL),
+        }
+    }
+
+    /// Returns the canonical form of a request: its URL and body, with input images replaced by
+    /// their SHA-256 hash. Headers, and therefore the API key, are left out.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The prepared request.
+    pub fn canonical(request: &PreparedRequest) -> serde_json::Value {
+        let mut body = request.body.clone();
+        if let Some(fields) = body.as_object_mut() {
+            for name in IMAGE_FIELDS {
+                if let Some(serde_json::Value::String(image)) = fields.get_mut(name) {
+                    *image = format!("sha256:{}", hex(&Sha256::digest(image.as_bytes())));
+                }
+            }
+        }
+        // Objects are serialized with sorted keys, so equal requests give equal documents.
+        serde_json::json!({ "url": request.url, "body": body })
+    }
+
+    /// Returns the key of a request, or `None` if it has no seed and would give a different
+    /// image every time.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The prepared request.
+    pub fn key(request: &PreparedRequest) -> Option<String> {
+        request.body.get("seed")?.as_u64()?;
+        let canonical = serde_json::to_vec(&Self::canonical(request)).ok()?;
+        Some(hex(&Sha256::digest(canonical)))
+    }
+
+    /// Returns the stored response to a request, if any and not expired. Unreadable entries are
+    /// treated as missing.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The prepared request.
+    pub fn get(&self, request: &PreparedRequest) -> Option<ToImageResponse> {
+        let (path, created) = self.find(&Self::key(request)?)?;
+        if self.is_expired(created) {
+            let _ = std::fs::remove_file(&path);
+            return None;
+        }
+        let entry = serde_json::from_slice::<Entry>(&std::fs::read(&path).ok()?).ok()?;
+        // The modification time orders entries for eviction, so a hit marks the entry as used.
+        if let Ok(file) = std::fs::File::options().append(true).open(&path) {
+            let _ = file.set_modified(SystemTime::now());
+        }
+        Some(ToImageResponse {
+            cost: Some(0.0),
+            ..entry.response
+        })
+    }
+
+    /// Stores the response to a request with a seed, then removes expired entries and the least
+    /// recently used ones above the size limit.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The prepared request.
+    /// * `response` - The response of the API.
+    pub fn put(&self, request: &PreparedRequest, response: &ToImageResponse) -> Result<()> {
+        let Some(key) = Self::key(request) else {
+            return Ok(());
+        };
+        std::fs::create_dir_all(&self.dir)
+            .with_context(|| format!("failed to create {}", self.dir.display()))?;
+        let entry = Entry {
+            request: Self::canonical(request),
+            response: response.clone(),
+        };
+        let stale = self.find(&key);
+        let path = self.path(&key, now());
+        write_atomic(&path, &serde_json::to_vec(&entry)?)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+        if let Some((stale, _)) = stale.filter(|(stale, _)| *stale != path) {
+            let _ = std::fs::remove_file(stale);
+        }
+        self.prune()
+    }
+
+    /// Removes expired entries, then the least recently used ones until the cache fits its size
+    /// limit.
+    pub fn prune(&self) -> Result<()> {
+        let mut files = Vec::new();
+        for (path, size, created, used) in self.files()? {
+            if self.is_expired(created) {
+                std::fs::remove_file(&path)
+                    .with_context(|| format!("failed to remove {}", path.display()))?;
+            } else {
+                files.push((path, size, used));
+            }
+        }
+        let mut total = files.iter().map(|(_, size, _)| size).sum::<u64>();
+        files.sort_by_key(|(_, _, used)| *used);
+        for (path, size, _) in files {
+            if total <= self.max_bytes {
+                break;
+            }
+            std::fs::remove_file(&path)
+                .with_context(|| format!("failed to remove {}", path.display()))?;
+            total -= size;
+        }
+        Ok(())
+    }
+
+    /// Returns the number, size and ages of the entries.
+    pub fn stats(&self) -> Result<CacheStats> {
+        let mut stats = CacheStats {
+            dir: self.dir.clone(),
+            ..CacheStats::default()
+        };
+        let now = now();
+        for (_, size, created, _) in self.files()? {
+            let age = now.saturating_sub(created);
+            stats.entries += 1;
+            stats.bytes += size;
+            stats.expired += usize::from(self.is_expired(created));
+            stats.oldest = Some(stats.oldest.map_or(age, |oldest| oldest.max(age)));
+            stats.newest = Some(stats.newest.map_or(age, |newest| newest.min(age)));
+        }
+        Ok(stats)
+    }
+
+    /// Removes every entry.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the statistics of the removed entries.
+    pub fn clear(&self) -> Result<CacheStats> {
+        let stats = self.stats()?;
+        for (path, _, _, _) in self.files()? {
+            std::fs::remove_file(&path)
+                .with_context(|| format!("failed to remove {}", path.display()))?;
+        }
+        Ok(stats)
+    }
+
+    /// Returns the path of the entry with a key, stored at a time.
+    fn path(&self, key: &str, created: u64) -> PathBuf {
+        self.dir.join(format!("{}.{}.json", key, created))
+    }
+
+    /// Returns the path and creation time of the entry with a key, if any.
+    fn find(&self, key: &str) -> Option<(PathBuf, u64)> {
+        std::fs::read_dir(&self.dir)
+            .ok()?
+            .flatten()
+            .find_map(|entry| {
+                let path = entry.path();
+                let (name, created) = parse_name(&path)?;
+                (name == key).then_some((path, created))
+            })
+    }
+
+    /// Returns whether an entry stored at a time has expired.
+    fn is_expired(&self, created: u64) -> bool {
+        self.ttl
+            .is_some_and(|ttl| now().saturating_sub(created) > ttl.as_secs())
+    }
+
+    /// Lists the entries with their size, creation time in seconds since the Unix epoch as encoded
+    /// in their name, and last use.
+    fn files(&self) -> Result<Vec<(PathBuf, u64, u64, SystemTime)>> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+        let entries = std::fs::read_dir(&self.dir)
+            .with_context(|| format!("failed to read {}", self.dir.display()))?;
+        let mut files = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            let path = entry.path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "json")
+            {
+                let metadata = entry.metadata()?;
+                // Entries named otherwise count as created at the epoch, so that they expire first.
+                let created = parse_name(&path).map_or(0, |(_, created)| created);
+                files.push((path, metadata.len(), created, metadata.modified()?));
+            }
+        }
+        Ok(files)
+    }
+}
+
+/// Returns the key and creation time encoded in the name of an entry.
+fn parse_name(path: &Path) -> Option<(&str, u64)> {
+    let stem = path.file_name()?.to_str()?.strip_suffix(".json")?;
+    let (key, created) = stem.split_once('.')?;
+    Some((key, created.parse().ok()?))
+}
+
+/// Returns the current time in seconds since the Unix epoch.
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+}
+
+/// Formats bytes as lowercase hexadecimal digits.
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
@@ -1,9 +1,81 @@
 //! This module contains the CLI functionalities for interacting with the GetImg API.
 
+#[cfg(feature = "cli")]
+use crate::batch::{self, BatchState, Job, JobKind, JobStatus, Runner};
+#[cfg(feature = "cli")]
+use crate::cache;
+#[cfg(feature = "cli")]
+use crate::client::{Client, CONTROLNET_MODEL, INPAINT_MODEL, INSTRUCT_MODEL};
+#[cfg(feature = "cli")]
+use crate::config::{CommandDefaults, Profile};
+#[cfg(feature = "cli")]
+use crate::defaults::{self, ModelDefaults};
+#[cfg(feature = "cli")]
+use crate::inputs::{self, Input};
+#[cfg(feature = "cli")]
+use crate::mask::{Mask, Region};
+#[cfg(feature = "cli")]
+use crate::metadata::{self, GenerationParams};
+#[cfg(feature = "cli")]
+use crate::output::{
+    escape, render, OutputFields, OutputSettings, DEFAULT_TEMPLATE, INPUTS_TEMPLATE,
+    PIPELINE_TEMPLATE, STDOUT_TEMPLATE, SWEEP_TEMPLATE,
+};
+#[cfg(feature = "cli")]
+use crate::pipeline::{self, PipelineManifest, Var};
+#[cfg(feature = "cli")]
+use crate::preprocess::{self, Fit, Preprocessor};
+#[cfg(feature = "cli")]
+use crate::preview::{self, Protocol};
+#[cfg(feature = "cli")]
+use crate::provenance::{self, Manifest};
+#[cfg(feature = "cli")]
+use crate::repl::{self, Outcome, Session};
+#[cfg(feature = "cli")]
+use crate::report::{dry_run, status, DryRun, ErrorKind, OutputRecord, Report};
+#[cfg(feature = "cli")]
+use crate::request::{
+    ControlNetRequest, EditImageRequest, GenerationRequest, ImageToImageRequest,
+    RepaintImageRequest, SeedMode, TextToImageRequest,
+};
+#[cfg(feature = "cli")]
+use crate::response::ToImageResponse;
+#[cfg(feature = "cli")]
+use crate::sanitize;
+#[cfg(feature = "cli")]
+use crate::sweep::{self, Axis, CellRecord, SweepManifest};
+#[cfg(feature = "cli")]
+use crate::utils::random_seed;
+#[cfg(feature = "cli")]
+use crate::watch::{self, WatchState, Watcher};
+#[cfg(feature = "cli")]
+use crate::watermark::{Payload, Watermarker};
+#[cfg(feature = "cli")]
+use anyhow::{anyhow, bail, Context};
+#[cfg(feature = "cli")]
+use base64::engine::general_purpose::STANDARD;
+#[cfg(feature = "cli")]
+use base64::Engine;
 #[cfg(feature = "cli")]
 use clap::builder::styling::{AnsiColor, Effects, Styles};
 #[cfg(feature = "cli")]
-use clap::{Args, Parser, Subcommand};
+use clap::builder::{PossibleValue, PossibleValuesParser, StringValueParser, TypedValueParser};
+#[cfg(feature = "cli")]
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueHint};
+#[cfg(feature = "cli")]
+use clap_complete::Shell;
+#[cfg(feature = "cli")]
+use rustyline::error::ReadlineError;
+#[cfg(feature = "cli")]
+use rustyline::DefaultEditor;
+#[cfg(feature = "cli")]
+use std::io::{IsTerminal, Read, Write};
+#[cfg(feature = "cli")]
+use std::path::{Path, PathBuf};
+#[cfg(feature = "cli")]
+use std::sync::{Arc, OnceLock};
+#[cfg(feature = "cli")]
+use std::time::Instant;
 
 #[cfg(feature = "cli")]
 fn styles() -> Styles {
@@ -15,6 +87,30 @@ fn styles() -> Styles {
         .placeholder(AnsiColor::Green.on_default())
 }
 
+/// Parser of free-form values that offers known ones to shell completions and help without
+/// rejecting others, so values added to the API later can be passed before they are listed.
+#[cfg(feature = "cli")]
+#[derive(Debug, Clone, Copy)]
+struct SuggestedValues(&'static [&'static str]);
+
+#[cfg(feature = "cli")]
+impl TypedValueParser for SuggestedValues {
+    type Value = String;
+
+    fn parse_ref(
+        &self,
+        cmd: &clap::Command,
+        arg: Option<&clap::Arg>,
+        value: &std::ffi::OsStr,
+    ) -> Result<String, clap::Error> {
+        StringValueParser::new().parse_ref(cmd, arg, value)
+    }
+
+    fn possible_values(&self) -> Option<Box<dyn Iterator<Item = PossibleValue> + '_>> {
+        Some(Box::new(self.0.iter().copied().map(PossibleValue::new)))
+    }
+}
+
 #[cfg(feature = "cli")]
 #[derive(Parser, Debug, Clone)]
 #[command(
@@ -45,6 +141,18 @@ FUNCTIONALITIES:
   - Generate Image from Text: Generate an image from text using the GetImg API.
   - Generate Image from Another Image: Generate an image from another image using the GetImg API.
   - Generate Images using ControlNet Conditioning: Generate images using ControlNet conditioning with the GetImg API.
+  - Inspect Image: Print the generation parameters embedded in an image.
+  - Rerun Image: Regenerate an image from its embedded generation parameters.
+  - Detect Watermark: Recover the invisible watermark of a generated image.
+  - Batch: Run the generations listed in a JSONL or CSV job file.
+  - Sweep: Render a labeled XY grid comparing combinations of parameter values.
+  - Completions: Print the completion script of bash, zsh, fish, elvish or PowerShell.
+  - Man: Write roff man pages for getimg and every subcommand.
+  - Pipeline: Run multi-step YAML workflows whose steps build on earlier outputs.
+  - REPL: Iterate on an image interactively, keeping the model and settings between commands.
+  - Watch: Process every image dropped into a directory with the same parameters.
+  - View: Draw an image in the terminal with kitty, iTerm2 or sixel graphics, or colored blocks.
+  - Cache: Inspect or clear the local cache of responses to seeded requests.
 
 USAGE:
   getimg [OPTIONS] <COMMAND>
@@ -59,12 +167,69 @@ EXAMPLES:
   Generate an image from text:
     getimg t2i -p "A colorful sunset over the ocean." -w 512 -a 512 -s 5 -e 42 -o png -n "Disfigured, cartoon, blurry"
 
+  Generate an image from text with model defaults and a random seed:
+    getimg t2i -p "A colorful sunset over the ocean."
+
   Generate an image from another image:
     getimg i2i -p "Add a forest in the background." -i generated_image.png -s 6 -e 512 -o jpeg -f 0.5 -n "Disfigured, cartoon, blurry"
 
   Generate images using ControlNet conditioning:
     getimg cnet -p "A painting of a landscape." -i generated_image.png -f 1.0 -w 512 -a 512 -s 25 -g 7.5 -e 512 -c lms -o png -r canny-1.1 -n "Disfigured, cartoon, blurry"
 
+  Inspect the parameters of a generated image:
+    getimg inspect t2i.png --json
+
+  Reuse the stored image when the same request and seed were sent before:
+    getimg --cache t2i -p "A colorful sunset over the ocean." -e 42
+
+  Draw a generated image in the terminal:
+    getimg view t2i.png
+
+  Preview every saved image in the terminal, e.g. over SSH:
+    getimg --preview t2i -p "A colorful sunset over the ocean."
+
+  Print the outputs, seed and cost as JSON for scripts:
+    getimg --json t2i -p "A colorful sunset over the ocean." -e 42
+
+  Print the request as a curl command instead of sending it:
+    getimg --dry-run=curl t2i -p "A colorful sunset over the ocean." -e 42
+
+  Regenerate an image with a different seed:
+    getimg rerun t2i.png --seed 7 --steps 6
+
+  Generate four variations with consecutive seeds:
+    getimg --count 4 --output "{prompt_slug}-{index}.{ext}" t2i -p "A colorful sunset over the ocean." -e 42
+
+  Save outputs under a directory named after the command:
+    getimg --output-dir renders --output "{cmd}/{prompt_slug}-{seed}.{ext}" t2i -p "A colorful sunset over the ocean." -w 512 -a 512 -s 5 -e 42 -o png -n "blurry"
+
+  Detect the invisible watermark of an image:
+    getimg watermark detect t2i.png
+
+  Run a workflow with a different subject:
+    getimg pipeline run workflow.yaml --var subject="a castle on a hill"
+
+  Refine an image interactively, saving the transcript on exit:
+    getimg repl --transcript session.jsonl
+
+  Make every image of a folder snowy, 8 at a time, with the same seed:
+    getimg i2i -p "Make it snowy." -i sketches -f 0.6 -j 8
+
+  Stylize every sketch dropped into a folder, saving the outputs in sketches-out:
+    getimg watch sketches --cmd cnet -r canny-1.1 -p "A watercolor illustration."
+
+  Enable completions for the current bash session:
+    source <(getimg completions bash)
+
+  Run a job file with 8 generations in flight:
+    getimg batch jobs.jsonl -j 8
+
+  Continue an interrupted batch, retrying failed jobs:
+    getimg batch jobs.jsonl --resume
+
+  Compare steps and guidance with a fixed seed:
+    getimg sweep -x steps=15,25,40 -y guidance=5,7.5,10 cnet -p "A painting of a landscape." -i generated_image.png -r canny-1.1 -e 42
+
 For more information, visit: github.com/kevin-rs/getimg
 "#
 )]
@@ -75,6 +240,68 @@ pub struct Cli {
     /// Model to be used.
     #[clap(short, long)]
     pub model: Option<String>,
+    /// Configuration profile to use. Defaults to `GETIMG_PROFILE` or the configured default profile.
+    #[clap(long, global = true)]
+    pub profile: Option<String>,
+    /// Do not embed generation parameters in output images.
+    #[clap(long, global = true)]
+    pub no_metadata: bool,
+    /// Do not embed an invisible watermark in output images.
+    #[clap(long, global = true)]
+    pub no_watermark: bool,
+    /// Team id carried by the invisible watermark. Defaults to `GETIMG_TEAM_ID` or 0.
+    #[clap(long, global = true)]
+    pub team_id: Option<u16>,
+    /// Output path template using {cmd}, {model}, {seed}, {timestamp}, {prompt_slug}, {index} and {ext}.
+    /// Defaults to `GETIMG_OUTPUT` or `{cmd}-{timestamp}-{seed}.{ext}`; `-` writes the image to stdout.
+    #[clap(short = 'O', long, global = true)]
+    pub output: Option<String>,
+    /// Directory outputs are written to. Defaults to `GETIMG_OUTPUT_DIR` or the current directory.
+    #[clap(long, global = true, value_hint = ValueHint::DirPath)]
+    pub output_dir: Option<PathBuf>,
+    /// Number of variations generated concurrently by generation subcommands, saved with {index}.
+    #[clap(long, global = true, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    pub count: u32,
+    /// How the seeds of variations are chosen after the first one.
+    #[clap(long, global = true, value_enum, default_value_t = SeedMode::Sequential)]
+    pub seeds: SeedMode,
+    /// Print a single JSON object describing the outputs, seed, cost, model, endpoint, latency
+    /// and error of the command instead of progress messages.
+    #[clap(long, global = true)]
+    pub json: bool,
+    /// Print only the paths of saved images, and errors.
+    #[clap(short, long, global = true)]
+    pub quiet: bool,
+    /// Print the requests instead of sending them, as JSON or with `--dry-run=curl` as `curl`
+    /// commands. The API key is redacted and image data truncated.
+    #[clap(
+        long,
+        global = true,
+        value_enum,
+        value_name = "FORMAT",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "json"
+    )]
+    pub dry_run: Option<DryRun>,
+    /// Draw every saved image in the terminal, with the detected protocol or the given one.
+    #[clap(
+        long,
+        global = true,
+        value_enum,
+        value_name = "PROTOCOL",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "auto"
+    )]
+    pub preview: Option<Protocol>,
+    /// Reuse stored responses to requests with the same parameters and seed, and store new ones.
+    /// Also enabled by `GETIMG_CACHE=1` or `enabled` in the `cache` section of the profile.
+    #[clap(long, global = true, overrides_with = "no_cache")]
+    pub cache: bool,
+    /// Send every request to the API even if the cache is enabled by the profile.
+    #[clap(long, global = true)]
+    pub no_cache: bool,
     #[clap(subcommand)]
     pub cmd: Command,
 }
@@ -91,170 +318,2475 @@ pub enum Command {
     ImageToImage(ImageToImage),
     #[clap(name = "cnet")]
     ControlNet(ControlNet),
+    /// Print the generation parameters embedded in an image.
+    Inspect(Inspect),
+    /// Regenerate an image from its embedded generation parameters.
+    Rerun(Rerun),
+    /// Work with the invisible watermark of generated images.
+    Watermark(Watermark),
+    /// Run the generations listed in a JSONL or CSV job file.
+    Batch(Batch),
+    /// Compare settings by generating every combination of up to three parameter lists.
+    Sweep(Sweep),
+    /// Print the completion script of a shell.
+    Completions(Completions),
+    /// Write roff man pages for getimg and every subcommand.
+    Man(Man),
+    /// Start an interactive session chaining generations on the latest image.
+    Repl(Repl),
+    /// Run multi-step workflows whose steps build on earlier outputs.
+    Pipeline(Pipeline),
+    /// Process every image dropped into a directory with the same parameters.
+    Watch(Watch),
+    /// Draw an image in the terminal.
+    View(View),
+    /// Inspect or clear the local response cache.
+    Cache(Cache),
+}
+
+#[cfg(feature = "cli")]
+impl Command {
+    /// Returns whether the command sends requests to the API and therefore needs an API key.
+    pub fn uses_api(&self) -> bool {
+        !matches!(
+            self,
+            Command::Inspect(_)
+                | Command::Watermark(_)
+                | Command::Completions(_)
+                | Command::Man(_)
+                | Command::View(_)
+                | Command::Cache(_)
+        )
+    }
+
+    /// Returns the name of the subcommand, e.g. `t2i`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Command::Edit(_) => "edit",
+            Command::Repaint(_) => "paint",
+            Command::TextToImage(_) => "t2i",
+            Command::ImageToImage(_) => "i2i",
+            Command::ControlNet(_) => "cnet",
+            Command::Inspect(_) => "inspect",
+            Command::Rerun(_) => "rerun",
+            Command::Watermark(_) => "watermark",
+            Command::Batch(_) => "batch",
+            Command::Sweep(_) => "sweep",
+            Command::Completions(_) => "completions",
+            Command::Man(_) => "man",
+            Command::Repl(_) => "repl",
+            Command::Pipeline(_) => "pipeline",
+            Command::Watch(_) => "watch",
+            Command::View(_) => "view",
+            Command::Cache(_) => "cache",
+        }
+    }
 }
 
 #[cfg(feature = "cli")]
 #[derive(Args, Debug, Clone)]
 pub struct Edit {
-    /// Text prompt for generating the edited image.
-    #[clap(short, long)]
+    /// Text prompt for generating the edited image, or `-` to read it from stdin.
+    #[clap(
+        short,
+        long,
+        required_unless_present = "prompt_file",
+        default_value = "",
+        hide_default_value = true
+    )]
     pub prompt: String,
+    /// File holding the text prompt, or `-` for stdin.
+    #[clap(long, conflicts_with = "prompt", value_hint = ValueHint::FilePath)]
+    pub prompt_file: Option<PathBuf>,
     /// Text input that will not guide the image generation.
     #[clap(short, long)]
-    pub negative_prompt: String,
-    /// Path to the input image file.
-    #[clap(short, long)]
+    pub negative_prompt: Option<String>,
+    /// Path to the input image file, `-` to read it from stdin, or a directory or glob pattern
+    /// whose images are all processed.
+    #[clap(short, long, value_hint = ValueHint::FilePath)]
     pub image: String,
-    /// Image guidance parameter.
+    /// Image guidance parameter. Defaults to the model's recommended value.
     #[clap(short, long)]
-    pub guidance: f64,
-    /// Number of steps for image generation.
+    pub guidance: Option<f64>,
+    /// Number of steps for image generation. Defaults to the model's recommended value.
     #[clap(short, long)]
-    pub steps: usize,
-    /// Seed parameter.
-    #[clap(short = 'e', long = "eed")]
-    pub seed: usize,
-    /// Scheduler parameter.
-    #[clap(short = 'c', long = "cheduler")]
-    pub scheduler: String,
-    /// Output format for the image.
-    #[clap(short, long)]
-    pub output_format: String,
-    /// Higher image guidance produces images that are closely linked to the source image.
-    #[clap(short = 'y', long = "yuidance")]
-    pub image_guidance: f64,
+    pub steps: Option<usize>,
+    /// Seed parameter. A random seed is picked and printed when omitted.
+    #[clap(short = 'e', long = "eed", visible_alias = "seed")]
+    pub seed: Option<usize>,
+    /// Scheduler parameter. Defaults to `euler`.
+    #[clap(short = 'c', long = "cheduler", visible_alias = "scheduler", value_parser = SuggestedValues(&defaults::SCHEDULERS))]
+    pub scheduler: Option<String>,
+    /// Output format for the image. Defaults to `jpeg`.
+    #[clap(short, long, value_parser = SuggestedValues(&defaults::OUTPUT_FORMATS))]
+    pub output_format: Option<String>,
+    /// Higher image guidance produces images that are closely linked to the source image. Defaults to 1.5.
+    #[clap(short = 'y', long = "yuidance", visible_alias = "image-guidance")]
+    pub image_guidance: Option<f64>,
+    /// Number of requests in flight at the same time for a directory or glob `--image`.
+    #[clap(short = 'j', long, default_value_t = 4)]
+    pub concurrency: usize,
+    #[clap(flatten)]
+    pub preprocess: PreprocessArgs,
 }
 
 #[cfg(feature = "cli")]
 #[derive(Args, Debug, Clone)]
 pub struct Repaint {
-    /// Text prompt for repainting the image.
-    #[clap(short, long)]
+    /// Text prompt for repainting the image, or `-` to read it from stdin.
+    #[clap(
+        short,
+        long,
+        required_unless_present = "prompt_file",
+        default_value = "",
+        hide_default_value = true
+    )]
     pub prompt: String,
+    /// File holding the text prompt, or `-` for stdin.
+    #[clap(long, conflicts_with = "prompt", value_hint = ValueHint::FilePath)]
+    pub prompt_file: Option<PathBuf>,
     /// Text input that will not guide the image generation.
     #[clap(short, long)]
-    pub negative_prompt: String,
-    /// Path to the input image file.
-    #[clap(short, long)]
+    pub negative_prompt: Option<String>,
+    /// Path to the input image file, `-` to read it from stdin, or a directory or glob pattern
+    /// whose images are all processed.
+    #[clap(short, long, value_hint = ValueHint::FilePath)]
     pub image: String,
-    /// Path to the mask image file.
-    #[clap(short, long)]
-    pub mask_image: String,
-    /// Width of the image.
-    #[clap(short, long)]
-    pub width: usize,
-    /// Height of the image.
-    #[clap(short = 'a', long = "hauteur")]
-    pub height: usize,
-    /// Number of steps for image generation.
+    /// Path to the mask image file, or a directory of masks mirroring a directory or glob
+    /// `--image`, whose images otherwise use the `<name>_mask` file next to them.
+    #[clap(short, long, value_hint = ValueHint::FilePath)]
+    pub mask_image: Option<String>,
+    /// Area to repaint as x,y,w,h in input image pixels. May be repeated.
+    #[clap(long)]
+    pub region: Vec<Region>,
+    /// Width of the image. Defaults to the width of the input image after preprocessing.
     #[clap(short, long)]
-    pub steps: usize,
-    /// Scheduler parameter.
-    #[clap(short = 'c', long = "cheduler")]
-    pub scheduler: String,
-    /// Seed parameter.
-    #[clap(short = 'e', long = "eed")]
-    pub seed: usize,
-    /// Strength parameter for image generation.
-    #[clap(short = 'f', long = "force")]
-    pub strength: f64,
-    /// Guidance parameter.
+    pub width: Option<usize>,
+    /// Height of the image. Defaults to the height of the input image after preprocessing.
+    #[clap(short = 'a', long = "hauteur", visible_alias = "height")]
+    pub height: Option<usize>,
+    /// Number of steps for image generation. Defaults to the model's recommended value.
     #[clap(short, long)]
-    pub guidance: f64,
-    /// Output format for the image.
+    pub steps: Option<usize>,
+    /// Scheduler parameter. Defaults to `euler`.
+    #[clap(short = 'c', long = "cheduler", visible_alias = "scheduler", value_parser = SuggestedValues(&defaults::SCHEDULERS))]
+    pub scheduler: Option<String>,
+    /// Seed parameter. A random seed is picked and printed when omitted.
+    #[clap(short = 'e', long = "eed", visible_alias = "seed")]
+    pub seed: Option<usize>,
+    /// Strength parameter for image generation. Defaults to 1.0.
+    #[clap(short = 'f', long = "force", visible_alias = "strength")]
+    pub strength: Option<f64>,
+    /// Guidance parameter. Defaults to the model's recommended value.
     #[clap(short, long)]
-    pub output_format: String,
+    pub guidance: Option<f64>,
+    /// Output format for the image. Defaults to `jpeg`.
+    #[clap(short, long, value_parser = SuggestedValues(&defaults::OUTPUT_FORMATS))]
+    pub output_format: Option<String>,
+    /// Number of requests in flight at the same time for a directory or glob `--image`.
+    #[clap(short = 'j', long, default_value_t = 4)]
+    pub concurrency: usize,
+    #[clap(flatten)]
+    pub preprocess: PreprocessArgs,
 }
 
 #[cfg(feature = "cli")]
 #[derive(Args, Debug, Clone)]
 pub struct TextToImage {
-    /// Text prompt for generating the image.
-    #[clap(short, long)]
+    /// Text prompt for generating the image, or `-` to read it from stdin.
+    #[clap(
+        short,
+        long,
+        required_unless_present = "prompt_file",
+        default_value = "",
+        hide_default_value = true
+    )]
     pub prompt: String,
+    /// File holding the text prompt, or `-` for stdin.
+    #[clap(long, conflicts_with = "prompt", value_hint = ValueHint::FilePath)]
+    pub prompt_file: Option<PathBuf>,
     /// Text input that will not guide the image generation.
     #[clap(short, long)]
-    pub negative_prompt: String,
-    /// Width of the image.
-    #[clap(short, long)]
-    pub width: usize,
-    /// Height of the image.
-    #[clap(short = 'a', long = "hauteur")]
-    pub height: usize,
-    /// Number of steps for image generation.
+    pub negative_prompt: Option<String>,
+    /// Width of the image. Defaults to the model's native size.
     #[clap(short, long)]
-    pub steps: usize,
-    /// Seed parameter.
-    #[clap(short = 'e', long = "eed")]
-    pub seed: usize,
-    /// Output format for the image.
+    pub width: Option<usize>,
+    /// Height of the image. Defaults to the model's native size.
+    #[clap(short = 'a', long = "hauteur", visible_alias = "height")]
+    pub height: Option<usize>,
+    /// Number of steps for image generation. Defaults to the model's recommended value.
     #[clap(short, long)]
-    pub output_format: String,
+    pub steps: Option<usize>,
+    /// Seed parameter. A random seed is picked and printed when omitted.
+    #[clap(short = 'e', long = "eed", visible_alias = "seed")]
+    pub seed: Option<usize>,
+    /// Output format for the image. Defaults to `jpeg`.
+    #[clap(short, long, value_parser = SuggestedValues(&defaults::OUTPUT_FORMATS))]
+    pub output_format: Option<String>,
 }
 
 #[cfg(feature = "cli")]
 #[derive(Args, Debug, Clone)]
 pub struct ImageToImage {
-    /// Text prompt for generating the image.
-    #[clap(short, long)]
+    /// Text prompt for generating the image, or `-` to read it from stdin.
+    #[clap(
+        short,
+        long,
+        required_unless_present = "prompt_file",
+        default_value = "",
+        hide_default_value = true
+    )]
     pub prompt: String,
+    /// File holding the text prompt, or `-` for stdin.
+    #[clap(long, conflicts_with = "prompt", value_hint = ValueHint::FilePath)]
+    pub prompt_file: Option<PathBuf>,
     /// Text input that will not guide the image generation.
     #[clap(short, long)]
-    pub negative_prompt: String,
-    /// Path to the input image file.
-    #[clap(short, long)]
+    pub negative_prompt: Option<String>,
+    /// Path to the input image file, `-` to read it from stdin, or a directory or glob pattern
+    /// whose images are all processed.
+    #[clap(short, long, value_hint = ValueHint::FilePath)]
     pub image: String,
-    /// Strength parameter for image generation.
-    #[clap(short = 'f', long = "force")]
-    pub strength: f64,
-    /// Number of steps for image generation.
-    #[clap(short, long)]
-    pub steps: usize,
-    /// Output format for the image.
+    /// Strength parameter for image generation. Defaults to 0.5.
+    #[clap(short = 'f', long = "force", visible_alias = "strength")]
+    pub strength: Option<f64>,
+    /// Number of steps for image generation. Defaults to the model's recommended value.
     #[clap(short, long)]
-    pub output_format: String,
-    /// Seed parameter.
-    #[clap(short = 'e', long = "eed")]
-    pub seed: usize,
+    pub steps: Option<usize>,
+    /// Output format for the image. Defaults to `jpeg`.
+    #[clap(short, long, value_parser = SuggestedValues(&defaults::OUTPUT_FORMATS))]
+    pub output_format: Option<String>,
+    /// Seed parameter. A random seed is picked and printed when omitted.
+    #[clap(short = 'e', long = "eed", visible_alias = "seed")]
+    pub seed: Option<usize>,
+    /// Number of requests in flight at the same time for a directory or glob `--image`.
+    #[clap(short = 'j', long, default_value_t = 4)]
+    pub concurrency: usize,
+    #[clap(flatten)]
+    pub preprocess: PreprocessArgs,
 }
 
 #[cfg(feature = "cli")]
 #[derive(Args, Debug, Clone)]
 pub struct ControlNet {
-    /// ControlNet conditioning type.
+    /// ControlNet conditioning type, e.g. `canny-1.1`. Required unless configured.
     #[clap(short = 'r', long)]
-    pub net: String,
-    /// Text prompt for generating the image.
-    #[clap(short, long)]
+    pub net: Option<String>,
+    /// Text prompt for generating the image, or `-` to read it from stdin.
+    #[clap(
+        short,
+        long,
+        required_unless_present = "prompt_file",
+        default_value = "",
+        hide_default_value = true
+    )]
     pub prompt: String,
+    /// File holding the text prompt, or `-` for stdin.
+    #[clap(long, conflicts_with = "prompt", value_hint = ValueHint::FilePath)]
+    pub prompt_file: Option<PathBuf>,
     /// Text input that will not guide the image generation.
     #[clap(short, long)]
-    pub negative_prompt: String,
-    /// Path to the input image file.
-    #[clap(short, long)]
+    pub negative_prompt: Option<String>,
+    /// Path to the input image file, `-` to read it from stdin, or a directory or glob pattern
+    /// whose images are all processed.
+    #[clap(short, long, value_hint = ValueHint::FilePath)]
     pub image: String,
-    /// Strength parameter for image generation.
-    #[clap(short = 'f', long = "force")]
-    pub strength: f64,
-    /// Width of the image.
+    /// Strength parameter for image generation. Defaults to 1.0.
+    #[clap(short = 'f', long = "force", visible_alias = "strength")]
+    pub strength: Option<f64>,
+    /// Width of the image. Defaults to the width of the input image after preprocessing.
+    #[clap(short, long)]
+    pub width: Option<usize>,
+    /// Height of the image. Defaults to the height of the input image after preprocessing.
+    #[clap(short = 'a', long = "hauteur", visible_alias = "height")]
+    pub height: Option<usize>,
+    /// Number of steps for image generation. Defaults to the model's recommended value.
+    #[clap(short, long)]
+    pub steps: Option<usize>,
+    /// Guidance parameter. Defaults to the model's recommended value.
+    #[clap(short, long)]
+    pub guidance: Option<f64>,
+    /// Output format for the image. Defaults to `jpeg`.
+    #[clap(short, long, value_parser = SuggestedValues(&defaults::OUTPUT_FORMATS))]
+    pub output_format: Option<String>,
+    /// Seed parameter. A random seed is picked and printed when omitted.
+    #[clap(short = 'e', long = "eed", visible_alias = "seed")]
+    pub seed: Option<usize>,
+    /// Scheduler parameter. Defaults to `euler`.
+    #[clap(short = 'c', long = "cheduler", visible_alias = "scheduler", value_parser = SuggestedValues(&defaults::SCHEDULERS))]
+    pub scheduler: Option<String>,
+    /// Number of requests in flight at the same time for a directory or glob `--image`.
+    #[clap(short = 'j', long, default_value_t = 4)]
+    pub concurrency: usize,
+    #[clap(flatten)]
+    pub preprocess: PreprocessArgs,
+}
+
+#[cfg(feature = "cli")]
+#[derive(Args, Debug, Clone)]
+pub struct Inspect {
+    /// Path to the generated image file, or `-` to read it from stdin.
+    #[clap(value_hint = ValueHint::FilePath)]
+    pub file: String,
+}
+
+#[cfg(feature = "cli")]
+#[derive(Args, Debug, Clone)]
+pub struct Cache {
+    #[clap(subcommand)]
+    pub cmd: CacheCommand,
+}
+
+#[cfg(feature = "cli")]
+#[derive(Subcommand, Debug, Clone)]
+pub enum CacheCommand {
+    /// Print the number, size and age of the cached responses.
+    Stats,
+    /// Remove every cached response.
+    Clear,
+}
+
+#[cfg(feature = "cli")]
+#[derive(Args, Debug, Clone)]
+pub struct View {
+    /// Path to the image file, or `-` to read it from stdin.
+    #[clap(value_hint = ValueHint::FilePath)]
+    pub file: String,
+    /// Protocol drawing the image. Detected from the terminal by default.
+    #[clap(long, value_enum, default_value_t = Protocol::Auto)]
+    pub protocol: Protocol,
+    /// Width of the image in character cells. Defaults to `COLUMNS` or 80.
+    #[clap(short, long)]
+    pub width: Option<u32>,
+}
+
+#[cfg(feature = "cli")]
+#[derive(Args, Debug, Clone)]
+pub struct Rerun {
+    /// Path to the generated image file, or `-` to read it from stdin.
+    #[clap(value_hint = ValueHint::FilePath)]
+    pub file: String,
+    /// Seed overriding the embedded one.
+    #[clap(long)]
+    pub seed: Option<usize>,
+    /// Number of steps overriding the embedded one.
+    #[clap(long)]
+    pub steps: Option<usize>,
+    /// Path to the input image file, required for image-based requests.
+    #[clap(short, long, value_hint = ValueHint::FilePath)]
+    pub image: Option<String>,
+    /// Path to the mask image file, required for `paint` requests.
+    #[clap(short, long, value_hint = ValueHint::FilePath)]
+    pub mask_image: Option<String>,
+    #[clap(flatten)]
+    pub preprocess: PreprocessArgs,
+}
+
+#[cfg(feature = "cli")]
+#[derive(Args, Debug, Clone)]
+pub struct Watermark {
+    #[clap(subcommand)]
+    pub cmd: WatermarkCommand,
+}
+
+#[cfg(feature = "cli")]
+#[derive(Subcommand, Debug, Clone)]
+pub enum WatermarkCommand {
+    /// Recover the team and generation ids embedded in an image.
+    Detect(WatermarkDetect),
+}
+
+#[cfg(feature = "cli")]
+#[derive(Args, Debug, Clone)]
+pub struct WatermarkDetect {
+    /// Path to the image file, possibly recompressed or resized, or `-` to read it from stdin.
+    #[clap(value_hint = ValueHint::FilePath)]
+    pub file: String,
+}
+
+#[cfg(feature = "cli")]
+#[derive(Args, Debug, Clone)]
+pub struct Batch {
+    /// Path to the job file, JSONL or CSV with one `t2i`, `i2i`, `cnet`, `paint` or `edit` job per line.
+    #[clap(value_hint = ValueHint::FilePath)]
+    pub file: PathBuf,
+    /// Number of jobs running at the same time.
+    #[clap(short = 'j', long, default_value_t = 4)]
+    pub concurrency: usize,
+    /// Path of the results JSONL file. Defaults to `<file stem>.results.jsonl` next to the job file.
+    #[clap(long, value_hint = ValueHint::FilePath)]
+    pub results: Option<PathBuf>,
+    /// Path of the batch state file. Defaults to `<file stem>.state.json` next to the job file.
+    #[clap(long, value_hint = ValueHint::FilePath)]
+    pub state: Option<PathBuf>,
+    /// Continue a previous run, skipping completed jobs and retrying failed ones.
+    #[clap(long, conflicts_with = "restart")]
+    pub resume: bool,
+    /// Start over even if a previous run completed some jobs.
+    #[clap(long)]
+    pub restart: bool,
+    #[clap(flatten)]
+    pub preprocess: PreprocessArgs,
+}
+
+#[cfg(feature = "cli")]
+#[derive(Args, Debug, Clone)]
+pub struct Sweep {
+    /// Parameter varied along columns, as `parameter=value,value,...`, e.g. `steps=4,8,12`.
+    #[clap(short = 'x', long = "x-axis")]
+    pub x: Axis,
+    /// Parameter varied along rows.
+    #[clap(short = 'y', long = "y-axis")]
+    pub y: Option<Axis>,
+    /// Parameter varied across grid panels.
+    #[clap(short = 'z', long = "z-axis")]
+    pub z: Option<Axis>,
+    /// Path of the grid image. Defaults to `{cmd}-sweep-{timestamp}.png` in the output directory;
+    /// the cells are saved in a directory of the same name and the manifest next to it as JSON.
+    #[clap(long, value_hint = ValueHint::FilePath)]
+    pub grid: Option<PathBuf>,
+    /// Longest side of each cell in the grid, in pixels, at least 32.
+    #[clap(long, default_value_t = 256, value_parser = clap::value_parser!(u32).range(32..))]
+    pub cell_size: u32,
+    /// Number of generations running at the same time.
+    #[clap(short = 'j', long, default_value_t = 4)]
+    pub concurrency: usize,
+    #[clap(subcommand)]
+    pub cmd: SweepCommand,
+}
+
+#[cfg(feature = "cli")]
+#[derive(Subcommand, Debug, Clone)]
+pub enum SweepCommand {
+    Edit(Edit),
+    #[clap(name = "paint")]
+    Repaint(Repaint),
+    #[clap(name = "t2i")]
+    TextToImage(TextToImage),
+    #[clap(name = "i2i")]
+    ImageToImage(ImageToImage),
+    #[clap(name = "cnet")]
+    ControlNet(ControlNet),
+}
+
+#[cfg(feature = "cli")]
+#[derive(Args, Debug, Clone)]
+pub struct Pipeline {
+    #[clap(subcommand)]
+    pub cmd: PipelineCommand,
+}
+
+#[cfg(feature = "cli")]
+#[derive(Subcommand, Debug, Clone)]
+pub enum PipelineCommand {
+    /// Run the steps of a YAML workflow in order, saving every output and a manifest.
+    Run(PipelineRun),
+}
+
+#[cfg(feature = "cli")]
+#[derive(Args, Debug, Clone)]
+pub struct PipelineRun {
+    /// Path to the workflow file, YAML or JSON.
+    #[clap(value_hint = ValueHint::FilePath)]
+    pub file: PathBuf,
+    /// Variable overriding one of the workflow, as `name=value`. May be repeated.
+    #[clap(long = "var")]
+    pub vars: Vec<Var>,
+    /// Path of the manifest. Defaults to `<name>-{timestamp}.json` in the output directory;
+    /// the step outputs are saved in a directory of the same name.
+    #[clap(long, value_hint = ValueHint::FilePath)]
+    pub manifest: Option<PathBuf>,
+    #[clap(flatten)]
+    pub preprocess: PreprocessArgs,
+}
+
+#[cfg(feature = "cli")]
+#[derive(Args, Debug, Clone)]
+pub struct Completions {
+    /// Shell the script is written for.
+    #[clap(value_enum)]
+    pub shell: Shell,
+}
+
+#[cfg(feature = "cli")]
+#[derive(Args, Debug, Clone)]
+pub struct Man {
+    /// Directory the pages are written to, created if missing.
+    #[clap(default_value = "man", value_hint = ValueHint::DirPath)]
+    pub dir: PathBuf,
+}
+
+#[cfg(feature = "cli")]
+#[derive(Args, Debug, Clone)]
+pub struct Repl {
+    /// Path the transcript of the session is written to as JSONL when it ends.
+    #[clap(long, value_hint = ValueHint::FilePath)]
+    pub transcript: Option<PathBuf>,
+    #[clap(flatten)]
+    pub preprocess: PreprocessArgs,
+}
+
+#[cfg(feature = "cli")]
+#[derive(Args, Debug, Clone)]
+pub struct Watch {
+    /// Directory watched for new image files.
+    #[clap(value_hint = ValueHint::DirPath)]
+    pub dir: PathBuf,
+    /// Subcommand run on every image.
+    #[clap(long = "cmd", value_name = "COMMAND", value_parser = PossibleValuesParser::new(watch::COMMANDS))]
+    pub kind: String,
+    /// Text prompt applied to every image.
+    #[clap(short, long)]
+    pub prompt: String,
+    /// Text input that will not guide the image generation.
     #[clap(short, long)]
-    pub width: usize,
-    /// Height of the image.
-    #[clap(short = 'a', long = "hauteur")]
-    pub height: usize,
-    /// Number of steps for image generation.
+    pub negative_prompt: Option<String>,
+    /// ControlNet conditioning type, e.g. `canny-1.1`, for `cnet`.
+    #[clap(short = 'r', long)]
+    pub net: Option<String>,
+    /// Path to the mask image file applied to every image, required for `paint`.
+    #[clap(short, long, value_hint = ValueHint::FilePath)]
+    pub mask_image: Option<String>,
+    /// Strength parameter for image generation. Defaults to 0.5 for `i2i` and 1.0 for `cnet`
+    /// and `paint`.
+    #[clap(short = 'f', long = "force", visible_alias = "strength")]
+    pub strength: Option<f64>,
+    /// Width of the images for `cnet` and `paint`. Defaults to the width of each input image
+    /// after preprocessing.
     #[clap(short, long)]
-    pub steps: usize,
-    /// Guidance parameter.
+    pub width: Option<usize>,
+    /// Height of the images for `cnet` and `paint`. Defaults to the height of each input image
+    /// after preprocessing.
+    #[clap(short = 'a', long = "hauteur", visible_alias = "height")]
+    pub height: Option<usize>,
+    /// Number of steps for image generation. Defaults to the model's recommended value.
     #[clap(short, long)]
-    pub guidance: f64,
-    /// Output format for the image.
+    pub steps: Option<usize>,
+    /// Guidance parameter. Defaults to the model's recommended value.
     #[clap(short, long)]
-    pub output_format: String,
-    /// Seed parameter.
-    #[clap(short = 'e', long = "eed")]
-    pub seed: usize,
-    /// Scheduler parameter.
-    #[clap(short = 'c', long = "cheduler")]
-    pub scheduler: String,
+    pub guidance: Option<f64>,
+    /// Image guidance of `edit`. Defaults to 1.5.
+    #[clap(short = 'y', long = "yuidance", visible_alias = "image-guidance")]
+    pub image_guidance: Option<f64>,
+    /// Output format for the images. Defaults to `jpeg`.
+    #[clap(short, long, value_parser = SuggestedValues(&defaults::OUTPUT_FORMATS))]
+    pub output_format: Option<String>,
+    /// Seed used for every image. A random seed is picked for each image when omitted.
+    #[clap(short = 'e', long = "eed", visible_alias = "seed")]
+    pub seed: Option<usize>,
+    /// Scheduler parameter. Defaults to `euler`.
+    #[clap(short = 'c', long = "cheduler", visible_alias = "scheduler", value_parser = SuggestedValues(&defaults::SCHEDULERS))]
+    pub scheduler: Option<String>,
+    /// Directory the outputs are saved in, named after their input. Defaults to `<dir>-out`
+    /// next to the watched directory.
+    #[clap(long, value_hint = ValueHint::DirPath)]
+    pub out: Option<PathBuf>,
+    /// Path of the file recording processed images. Defaults to `.getimg-watch.json` in the
+    /// output directory.
+    #[clap(long, value_hint = ValueHint::FilePath)]
+    pub state: Option<PathBuf>,
+    /// Process the images not processed yet, then exit instead of watching for new ones.
+    #[clap(long)]
+    pub once: bool,
+    #[clap(flatten)]
+    pub preprocess: PreprocessArgs,
+}
+
+#[cfg(feature = "cli")]
+#[derive(Args, Debug, Clone)]
+pub struct PreprocessArgs {
+    /// Upload input images as-is, without resizing or re-encoding them.
+    #[clap(long)]
+    pub no_preprocess: bool,
+    /// How input images are fitted to the target dimensions.
+    #[clap(long, value_enum, default_value_t = Fit::Crop)]
+    pub fit: Fit,
+    /// Round input dimensions to a multiple of this value.
+    #[clap(long, default_value_t = 64)]
+    pub snap: u32,
+    /// Longest side allowed for input images.
+    #[clap(long, default_value_t = 1024)]
+    pub max_side: u32,
+    /// Upload input images with their EXIF, XMP, ICC profile and comment metadata.
+    #[clap(long)]
+    pub keep_metadata: bool,
+}
+
+#[cfg(feature = "cli")]
+impl PreprocessArgs {
+    /// Loads an input image, strips its private metadata unless `--keep-metadata` was given,
+    /// preprocesses it unless `--no-preprocess` was given, and encodes it as base64.
+    ///
+    /// # Arguments
+    ///
+    /// * `image_path` - Path to the input image file.
+    /// * `width` - Target width, if the command has one.
+    /// * `height` - Target height, if the command has one.
+    pub fn load_and_encode(
+        &self,
+        image_path: &str,
+        width: Option<usize>,
+        height: Option<usize>,
+    ) -> anyhow::Result<String> {
+        let bytes = read_input(image_path)?;
+        if self.keep_metadata {
+            return self.encode(&bytes, width, height);
+        }
+        let sanitized = sanitize::strip(&bytes)?;
+        if !sanitized.removed.is_empty() {
+            status!(
+                "Removed from {}: {}",
+                if image_path == STDIN {
+                    "stdin"
+                } else {
+                    image_path
+                },
+                sanitized.removed.join(", ")
+            );
+        }
+        self.encode(&sanitized.data, width, height)
+    }
+
+    /// Encodes in-memory image bytes, preprocessing them unless `--no-preprocess` was given.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - The encoded image.
+    /// * `width` - Target width, if the command has one.
+    /// * `height` - Target height, if the command has one.
+    pub fn encode(
+        &self,
+        bytes: &[u8],
+        width: Option<usize>,
+        height: Option<usize>,
+    ) -> anyhow::Result<String> {
+        if self.no_preprocess {
+            return Ok(STANDARD.encode(bytes));
+        }
+        Ok(self.preprocessor(width, height).process(bytes)?.to_base64())
+    }
+
+    /// Resolves the dimensions of a request built from an input image, deriving omitted sides
+    /// from the image the same way preprocessing resizes it.
+    ///
+    /// # Arguments
+    ///
+    /// * `image_path` - Path to the input image file.
+    /// * `width` - Requested width, if any.
+    /// * `height` - Requested height, if any.
+    pub fn dimensions(
+        &self,
+        image_path: &str,
+        width: Option<usize>,
+        height: Option<usize>,
+    ) -> anyhow::Result<(usize, usize)> {
+        if let (Some(width), Some(height)) = (width, height) {
+            return Ok((width, height));
+        }
+        let image = preprocess::decode(&read_input(image_path)?)?;
+        if self.no_preprocess {
+            return Ok((
+                width.unwrap_or(image.width() as usize),
+                height.unwrap_or(image.height() as usize),
+            ));
+        }
+        let (width, height) = self
+            .preprocessor(width, height)
+            .target_dimensions(image.width(), image.height());
+        Ok((width as usize, height as usize))
+    }
+
+    fn preprocessor(&self, width: Option<usize>, height: Option<usize>) -> Preprocessor {
+        Preprocessor {
+            width: width.map(|width| width as u32),
+            height: height.map(|height| height as u32),
+            max_side: Some(self.max_side),
+            multiple_of: self.snap,
+            fit: self.fit,
+            ..Preprocessor::default()
+        }
+    }
+}
+
+#[cfg(feature = "cli")]
+impl Edit {
+    /// Builds the request body, loading and encoding the input image.
+    pub fn request(&self) -> anyhow::Result<GenerationRequest> {
+        let defaults = ModelDefaults::for_model(INSTRUCT_MODEL);
+        Ok(GenerationRequest::Edit(EditImageRequest {
+            model: INSTRUCT_MODEL.to_string(),
+            prompt: self.prompt.clone(),
+            negative_prompt: self.negative_prompt.clone(),
+            image: self.preprocess.load_and_encode(&self.image, None, None)?,
+            image_guidance: self.image_guidance.unwrap_or(defaults::IMAGE_GUIDANCE),
+            steps: self.steps.unwrap_or(defaults.steps),
+            guidance: self.guidance.unwrap_or(defaults.guidance),
+            seed: seed_or_random(self.seed),
+            scheduler: scheduler_or_default(&self.scheduler, &defaults),
+            output_format: output_format_or_default(&self.output_format),
+        }))
+    }
+}
+
+#[cfg(feature = "cli")]
+impl Repaint {
+    /// Builds the request body, loading and encoding the input image and mask.
+    ///
+    /// Omitted dimensions follow the input image.
+    pub fn request(&self) -> anyhow::Result<GenerationRequest> {
+        let defaults = ModelDefaults::for_model(INPAINT_MODEL);
+        let (width, height) = self
+            .preprocess
+            .dimensions(&self.image, self.width, self.height)?;
+        Ok(GenerationRequest::Repaint(RepaintImageRequest {
+            model: INPAINT_MODEL.to_string(),
+            prompt: self.prompt.clone(),
+            negative_prompt: self.negative_prompt.clone(),
+            image: self
+                .preprocess
+                .load_and_encode(&self.image, Some(width), Some(height))?,
+            mask_image: self.preprocess.encode(
+                &self.mask()?.to_png()?,
+                Some(width),
+                Some(height),
+            )?,
+            strength: Some(self.strength.unwrap_or(defaults::STRENGTH)),
+            width,
+            height,
+            steps: self.steps.unwrap_or(defaults.steps),
+            guidance: self.guidance.unwrap_or(defaults.guidance),
+            seed: seed_or_random(self.seed),
+            scheduler: scheduler_or_default(&self.scheduler, &defaults),
+            output_format: output_format_or_default(&self.output_format),
+        }))
+    }
+
+    /// Builds the mask from `--mask-image` and `--region`, validated against the input image.
+    pub fn mask(&self) -> anyhow::Result<Mask> {
+        if self.mask_image.is_none() && self.region.is_empty() {
+            bail!("--mask-image or --region is required");
+        }
+        let image = preprocess::decode(&read_input(&self.image)?)?;
+        let mask = match &self.mask_image {
+            Some(path) => Mask::open(path)?,
+            None => Mask::new(image.width(), image.height()),
+        };
+        let mask = self
+            .region
+            .iter()
+            .fold(mask, |mask, region| mask.region(*region));
+        mask.validate(image.width(), image.height())?;
+        Ok(mask)
+    }
+}
+
+#[cfg(feature = "cli")]
+impl TextToImage {
+    /// Builds the request body for the given model.
+    pub fn request(&self, model: &str) -> GenerationRequest {
+        let defaults = ModelDefaults::for_model(model);
+        GenerationRequest::TextToImage(TextToImageRequest {
+            prompt: self.prompt.clone(),
+            model: model.to_string(),
+            negative_prompt: self.negative_prompt.clone(),
+            width: self.width.unwrap_or(defaults.width),
+            height: self.height.unwrap_or(defaults.height),
+            steps: self.steps.unwrap_or(defaults.steps),
+            output_format: output_format_or_default(&self.output_format),
+            seed: Some(seed_or_random(self.seed)),
+        })
+    }
+}
+
+#[cfg(feature = "cli")]
+impl ImageToImage {
+    /// Builds the request body for the given model, loading and encoding the input image.
+    pub fn request(&self, model: &str) -> anyhow::Result<GenerationRequest> {
+        let defaults = ModelDefaults::for_model(model);
+        Ok(GenerationRequest::ImageToImage(ImageToImageRequest {
+            model: model.to_string(),
+            prompt: self.prompt.clone(),
+            negative_prompt: self.negative_prompt.clone(),
+            image: self.preprocess.load_and_encode(&self.image, None, None)?,
+            strength: Some(self.strength.unwrap_or(defaults::IMAGE_TO_IMAGE_STRENGTH)),
+            steps: self.steps.unwrap_or(defaults.steps),
+            output_format: output_format_or_default(&self.output_format),
+            seed: Some(seed_or_random(self.seed)),
+        }))
+    }
+}
+
+#[cfg(feature = "cli")]
+impl ControlNet {
+    /// Builds the request body, loading and encoding the conditioning image.
+    ///
+    /// Omitted dimensions follow the conditioning image.
+    pub fn request(&self) -> anyhow::Result<GenerationRequest> {
+        let defaults = ModelDefaults::for_model(CONTROLNET_MODEL);
+        let (width, height) = self
+            .preprocess
+            .dimensions(&self.image, self.width, self.height)?;
+        Ok(GenerationRequest::ControlNet(ControlNetRequest {
+            controlnet: self
+                .net
+                .clone()
+                .ok_or_else(|| anyhow!("the ControlNet type is required, pass it with --net"))?,
+            model: CONTROLNET_MODEL.to_string(),
+            prompt: self.prompt.clone(),
+            negative_prompt: self.negative_prompt.clone(),
+            image: self
+                .preprocess
+                .load_and_encode(&self.image, Some(width), Some(height))?,
+            strength: self.strength.unwrap_or(defaults::STRENGTH),
+            width,
+            height,
+            steps: self.steps.unwrap_or(defaults.steps),
+            guidance: self.guidance.unwrap_or(defaults.guidance),
+            seed: seed_or_random(self.seed),
+            scheduler: scheduler_or_default(&self.scheduler, &defaults),
+            output_format: output_format_or_default(&self.output_format),
+        }))
+    }
+}
+
+#[cfg(feature = "cli")]
+impl Edit {
+    /// Reads the prompt from `--prompt-file`, or from stdin when the prompt is `-`.
+    pub fn read_prompt(&mut self) -> anyhow::Result<()> {
+        read_prompt(&mut self.prompt, &self.prompt_file, Some(&self.image))
+    }
+
+    /// Fills options left unset on the command line from configured defaults.
+    pub fn apply_defaults(&mut self, defaults: &CommandDefaults) {
+        self.negative_prompt = self
+            .negative_prompt
+            .take()
+            .or(defaults.negative_prompt.clone());
+        self.guidance = self.guidance.or(defaults.guidance);
+        self.steps = self.steps.or(defaults.steps);
+        self.scheduler = self.scheduler.take().or(defaults.scheduler.clone());
+        self.output_format = self.output_format.take().or(defaults.output_format.clone());
+        self.image_guidance = self.image_guidance.or(defaults.image_guidance);
+    }
+}
+
+#[cfg(feature = "cli")]
+impl Repaint {
+    /// Reads the prompt from `--prompt-file`, or from stdin when the prompt is `-`.
+    pub fn read_prompt(&mut self) -> anyhow::Result<()> {
+        read_prompt(&mut self.prompt, &self.prompt_file, Some(&self.image))
+    }
+
+    /// Fills options left unset on the command line from configured defaults.
+    pub fn apply_defaults(&mut self, defaults: &CommandDefaults) {
+        self.negative_prompt = self
+            .negative_prompt
+            .take()
+            .or(defaults.negative_prompt.clone());
+        self.width = self.width.or(defaults.width);
+        self.height = self.height.or(defaults.height);
+        self.steps = self.steps.or(defaults.steps);
+        self.scheduler = self.scheduler.take().or(defaults.scheduler.clone());
+        self.strength = self.strength.or(defaults.strength);
+        self.guidance = self.guidance.or(defaults.guidance);
+        self.output_format = self.output_format.take().or(defaults.output_format.clone());
+    }
+}
+
+#[cfg(feature = "cli")]
+impl TextToImage {
+    /// Reads the prompt from `--prompt-file`, or from stdin when the prompt is `-`.
+    pub fn read_prompt(&mut self) -> anyhow::Result<()> {
+        read_prompt(&mut self.prompt, &self.prompt_file, None)
+    }
+
+    /// Fills options left unset on the command line from configured defaults.
+    pub fn apply_defaults(&mut self, defaults: &CommandDefaults) {
+        self.negative_prompt = self
+            .negative_prompt
+            .take()
+            .or(defaults.negative_prompt.clone());
+        self.width = self.width.or(defaults.width);
+        self.height = self.height.or(defaults.height);
+        self.steps = self.steps.or(defaults.steps);
+        self.output_format = self.output_format.take().or(defaults.output_format.clone());
+    }
+}
+
+#[cfg(feature = "cli")]
+impl ImageToImage {
+    /// Reads the prompt from `--prompt-file`, or from stdin when the prompt is `-`.
+    pub fn read_prompt(&mut self) -> anyhow::Result<()> {
+        read_prompt(&mut self.prompt, &self.prompt_file, Some(&self.image))
+    }
+
+    /// Fills options left unset on the command line from configured defaults.
+    pub fn apply_defaults(&mut self, defaults: &CommandDefaults) {
+        self.negative_prompt = self
+            .negative_prompt
+            .take()
+            .or(defaults.negative_prompt.clone());
+        self.strength = self.strength.or(defaults.strength);
+        self.steps = self.steps.or(defaults.steps);
+        self.output_format = self.output_format.take().or(defaults.output_format.clone());
+    }
+}
+
+#[cfg(feature = "cli")]
+impl ControlNet {
+    /// Reads the prompt from `--prompt-file`, or from stdin when the prompt is `-`.
+    pub fn read_prompt(&mut self) -> anyhow::Result<()> {
+        read_prompt(&mut self.prompt, &self.prompt_file, Some(&self.image))
+    }
+
+    /// Fills options left unset on the command line from configured defaults.
+    pub fn apply_defaults(&mut self, defaults: &CommandDefaults) {
+        self.net = self.net.take().or(defaults.net.clone());
+        self.negative_prompt = self
+            .negative_prompt
+            .take()
+            .or(defaults.negative_prompt.clone());
+        self.strength = self.strength.or(defaults.strength);
+        self.width = self.width.or(defaults.width);
+        self.height = self.height.or(defaults.height);
+        self.steps = self.steps.or(defaults.steps);
+        self.guidance = self.guidance.or(defaults.guidance);
+        self.output_format = self.output_format.take().or(defaults.output_format.clone());
+        self.scheduler = self.scheduler.take().or(defaults.scheduler.clone());
+    }
+}
+
+/// Returns the given seed, or picks and prints a random one.
+#[cfg(feature = "cli")]
+fn seed_or_random(seed: Option<usize>) -> usize {
+    seed.unwrap_or_else(|| {
+        let seed = random_seed();
+        status!("Using random seed {}.", seed);
+        seed
+    })
+}
+
+#[cfg(feature = "cli")]
+fn scheduler_or_default(scheduler: &Option<String>, defaults: &ModelDefaults) -> String {
+    scheduler
+        .clone()
+        .unwrap_or_else(|| defaults.scheduler.to_string())
+}
+
+#[cfg(feature = "cli")]
+fn output_format_or_default(output_format: &Option<String>) -> String {
+    output_format
+        .clone()
+        .unwrap_or_else(|| defaults::OUTPUT_FORMAT.to_string())
+}
+
+/// Path standing for stdin in prompt and image options.
+#[cfg(feature = "cli")]
+pub const STDIN: &str = "-";
+
+/// Bytes read from stdin, kept because stdin can only be read once.
+#[cfg(feature = "cli")]
+static STDIN_BYTES: OnceLock<Vec<u8>> = OnceLock::new();
+
+/// Reads an input file, or stdin when the path is `-`.
+///
+/// # Arguments
+///
+/// * `path` - Path to the file, or `-`.
+#[cfg(feature = "cli")]
+pub fn read_input(path: &str) -> anyhow::Result<Vec<u8>> {
+    if path != STDIN {
+        return std::fs::read(path).with_context(|| format!("failed to read {}", path));
+    }
+    if let Some(bytes) = STDIN_BYTES.get() {
+        return Ok(bytes.clone());
+    }
+    let mut bytes = Vec::new();
+    std::io::stdin()
+        .read_to_end(&mut bytes)
+        .context("failed to read stdin")?;
+    Ok(STDIN_BYTES.get_or_init(|| bytes).clone())
+}
+
+/// Replaces the prompt with the contents of `--prompt-file`, or of stdin when either is `-`.
+///
+/// # Arguments
+///
+/// * `prompt` - The `--prompt` value.
+/// * `prompt_file` - The `--prompt-file` value.
+/// * `image` - The `--image` value, which cannot read stdin as well.
+#[cfg(feature = "cli")]
+fn read_prompt(
+    prompt: &mut String,
+    prompt_file: &Option<PathBuf>,
+    image: Option<&str>,
+) -> anyhow::Result<()> {
+    let path = match prompt_file {
+        Some(path) => path.to_string_lossy().into_owned(),
+        None if prompt == STDIN => STDIN.to_string(),
+        None => return Ok(()),
+    };
+    if path == STDIN && image == Some(STDIN) {
+        bail!("the prompt and the input image cannot both be read from stdin");
+    }
+    let text = String::from_utf8(read_input(&path)?)
+        .with_context(|| format!("the prompt in {} is not valid UTF-8", path))?;
+    let text = text.trim();
+    if text.is_empty() {
+        bail!("the prompt read from {} is empty", path);
+    }
+    *prompt = text.to_string();
+    Ok(())
+}
+
+#[cfg(feature = "cli")]
+impl Inspect {
+    /// Prints the embedded parameters as a table, and records them in the report.
+    ///
+    /// # Arguments
+    ///
+    /// * `json` - Whether the report is printed instead of the table.
+    /// * `report` - The report of the command.
+    pub fn run(&self, json: bool, report: &mut Report) -> anyhow::Result<()> {
+        let bytes = read_input(&self.file)?;
+        let Some(params) = metadata::read(&bytes)? else {
+            let Some(parameters) = metadata::read_parameters(&bytes)? else {
+                bail!("{} carries no generation parameters", self.file);
+            };
+            if !json {
+                println!("{}", parameters);
+            }
+            report.details = Some(serde_json::json!({ "parameters": parameters }));
+            return Ok(());
+        };
+
+        report.request(&params.request);
+        report.seed = params.seed;
+        report.cost = params.cost;
+        report.details = Some(serde_json::to_value(&params)?);
+        if !json {
+            print_params(&params);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "cli")]
+impl Cache {
+    /// Runs the cache subcommand, and records the statistics of the cache in the report.
+    ///
+    /// # Arguments
+    ///
+    /// * `cache` - The cache of the selected profile.
+    /// * `json` - Whether the report is printed instead of a table.
+    /// * `report` - The report of the command.
+    pub fn run(&self, cache: &cache::Cache, json: bool, report: &mut Report) -> anyhow::Result<()> {
+        let megabytes = |bytes: u64| bytes as f64 / (1024.0 * 1024.0);
+        match self.cmd {
+            CacheCommand::Stats => {
+                let stats = cache.stats()?;
+                report.details = Some(serde_json::json!({
+                    "stats": stats,
+                    "max_bytes": cache.max_bytes,
+                    "ttl_secs": cache.ttl.map(|ttl| ttl.as_secs()),
+                }));
+                if json {
+                    return Ok(());
+                }
+                let age = |age: Option<u64>| age.map(format_age).unwrap_or_else(|| "-".to_string());
+                println!("{:<16} {}", "Directory", stats.dir.display());
+                println!(
+                    "{:<16} {} ({} expired)",
+                    "Entries", stats.entries, stats.expired
+                );
+                println!(
+                    "{:<16} {:.1} MB of {:.1} MB",
+                    "Size",
+                    megabytes(stats.bytes),
+                    megabytes(cache.max_bytes)
+                );
+                println!(
+                    "{:<16} {}",
+                    "Time to live",
+                    age(cache.ttl.map(|ttl| ttl.as_secs()))
+                );
+                println!("{:<16} {}", "Oldest", age(stats.oldest));
+                println!("{:<16} {}", "Newest", age(stats.newest));
+            }
+            CacheCommand::Clear => {
+                let stats = cache.clear()?;
+                report.details = Some(serde_json::json!({ "stats": stats }));
+                status!(
+                    "Removed {} cached responses ({:.1} MB) from {}.",
+                    stats.entries,
+                    megabytes(stats.bytes),
+                    stats.dir.display()
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Formats a duration in seconds with its largest unit, e.g. `3d` or `45m`.
+#[cfg(feature = "cli")]
+fn format_age(seconds: u64) -> String {
+    match seconds {
+        0..60 => format!("{}s", seconds),
+        60..3600 => format!("{}m", seconds / 60),
+        3600..86400 => format!("{}h", seconds / 3600),
+        _ => format!("{}d", seconds / 86400),
+    }
+}
+
+#[cfg(feature = "cli")]
+impl View {
+    /// Draws the image on stdout, and records its size and the protocol in the report.
+    ///
+    /// # Arguments
+    ///
+    /// * `report` - The report of the command.
+    pub fn run(&self, report: &mut Report) -> anyhow::Result<()> {
+        let bytes = read_input(&self.file)?;
+        let protocol = self.protocol.resolve();
+        let columns = self.width.unwrap_or_else(preview::columns);
+        preview::show(&bytes, protocol, columns, &mut std::io::stdout().lock())
+            .with_context(|| format!("failed to view {}", self.file))?;
+        report.details = Some(serde_json::json!({
+            "file": self.file,
+            "protocol": format!("{:?}", protocol).to_lowercase(),
+            "columns": columns,
+        }));
+        Ok(())
+    }
+}
+
+#[cfg(feature = "cli")]
+fn print_params(params: &GenerationParams) {
+    let optional = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+    let rows = [
+        ("Command", params.request.command().to_string()),
+        ("Prompt", params.prompt.clone()),
+        ("Negative prompt", optional(params.negative_prompt.clone())),
+        ("Model", params.model.clone()),
+        ("Seed", optional(params.seed.map(|seed| seed.to_string()))),
+        ("Steps", params.steps.to_string()),
+        (
+            "Guidance",
+            optional(params.guidance.map(|guidance| guidance.to_string())),
+        ),
+        ("Scheduler", optional(params.scheduler.clone())),
+        (
+            "Size",
+            optional(
+                params
+                    .width
+                    .zip(params.height)
+                    .map(|(width, height)| format!("{}x{}", width, height)),
+            ),
+        ),
+        ("Endpoint", params.endpoint.clone()),
+        ("Cost", optional(params.cost.map(|cost| cost.to_string()))),
+    ];
+    for (name, value) in rows {
+        println!("{:<16} {}", name, value);
+    }
+}
+
+#[cfg(feature = "cli")]
+impl Rerun {
+    /// Rebuilds the request embedded in the image, applying overrides and reloading input images.
+    pub fn request(&self) -> anyhow::Result<GenerationRequest> {
+        let params = metadata::read(&read_input(&self.file)?)?
+            .ok_or_else(|| anyhow!("{} carries no getimg generation parameters", self.file))?;
+        let mut request = params.request;
+        if let Some(seed) = self.seed.or(params.seed) {
+            request.set_seed(seed);
+        }
+        if let Some(steps) = self.steps {
+            request.set_steps(steps);
+        }
+
+        let command = request.command();
+        let (width, height) = request.dimensions().unzip();
+        let load = |path: &Option<String>, flag: &str| match path {
+            Some(path) => self.preprocess.load_and_encode(path, width, height),
+            None => bail!("rerunning a `{}` request requires {}", command, flag),
+        };
+        match &mut request {
+            GenerationRequest::TextToImage(_) => {}
+            GenerationRequest::ImageToImage(r) => r.image = load(&self.image, "--image")?,
+            GenerationRequest::ControlNet(r) => r.image = load(&self.image, "--image")?,
+            GenerationRequest::Edit(r) => r.image = load(&self.image, "--image")?,
+            GenerationRequest::Repaint(r) => {
+                r.image = load(&self.image, "--image")?;
+                r.mask_image = load(&self.mask_image, "--mask-image")?;
+            }
+        }
+        Ok(request)
+    }
+}
+
+#[cfg(feature = "cli")]
+impl Watermark {
+    /// Runs the watermark subcommand.
+    ///
+    /// # Arguments
+    ///
+    /// * `json` - Whether the report is printed instead of a table.
+    /// * `report` - The report of the command.
+    pub fn run(&self, json: bool, report: &mut Report) -> anyhow::Result<()> {
+        match &self.cmd {
+            WatermarkCommand::Detect(cmd) => cmd.run(json, report),
+        }
+    }
+}
+
+#[cfg(feature = "cli")]
+impl WatermarkDetect {
+    /// Prints the watermark payload found in the image, and records it in the report.
+    ///
+    /// # Arguments
+    ///
+    /// * `json` - Whether the report is printed instead of a table.
+    /// * `report` - The report of the command.
+    pub fn run(&self, json: bool, report: &mut Report) -> anyhow::Result<()> {
+        let bytes = read_input(&self.file)?;
+        let Some(detection) = Watermarker::default().detect_bytes(&bytes)? else {
+            bail!("no getimg watermark found in {}", self.file);
+        };
+        report.details = Some(serde_json::json!({
+            "team_id": detection.payload.team_id,
+            "generation_id": format!("{:08x}", detection.payload.generation_id),
+            "confidence": detection.confidence,
+        }));
+        if json {
+            return Ok(());
+        }
+        println!("{:<16} {}", "Team", detection.payload.team_id);
+        println!(
+            "{:<16} {:08x}",
+            "Generation", detection.payload.generation_id
+        );
+        println!("{:<16} {:.2}", "Confidence", detection.confidence);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "cli")]
+impl Pipeline {
+    /// Runs the pipeline subcommand.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - Client sending the requests.
+    /// * `model` - Model of steps naming none.
+    /// * `profile` - Configuration profile providing defaults.
+    /// * `postprocess` - How generated images are finished and saved.
+    /// * `report` - The report of the command, receiving one output per step.
+    pub async fn run(
+        &self,
+        client: Client,
+        model: &str,
+        profile: Profile,
+        postprocess: Postprocess,
+        report: &mut Report,
+    ) -> anyhow::Result<()> {
+        match &self.cmd {
+            PipelineCommand::Run(cmd) => cmd.run(client, model, profile, postprocess, report).await,
+        }
+    }
+}
+
+#[cfg(feature = "cli")]
+impl PipelineRun {
+    /// Runs the workflow, then saves the manifest and prints the total cost.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - Client sending the requests.
+    /// * `model` - Model of steps naming none.
+    /// * `profile` - Configuration profile providing defaults.
+    /// * `postprocess` - How generated images are finished and saved.
+    /// * `report` - The report of the command, receiving one output per step.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` that is the error of the failing step, if any.
+    pub async fn run(
+        &self,
+        client: Client,
+        model: &str,
+        profile: Profile,
+        postprocess: Postprocess,
+        report: &mut Report,
+    ) -> anyhow::Result<()> {
+        let mut workflow = pipeline::Pipeline::open(&self.file)?;
+        for var in &self.vars {
+            workflow.vars.insert(var.name.clone(), var.value.clone());
+        }
+        workflow.validate()?;
+        let name = workflow.name.clone().unwrap_or_default();
+
+        let runner = Runner {
+            client,
+            model: model.to_string(),
+            profile,
+            preprocess: self.preprocess.clone(),
+            postprocess,
+            base: self
+                .file
+                .parent()
+                .map(Path::to_path_buf)
+                .unwrap_or_default(),
+            concurrency: 1,
+        };
+
+        if dry_run().is_some() {
+            let requests = workflow.requests(&runner).await?;
+            let skipped = workflow.steps.len() - requests.len();
+            if skipped > 0 {
+                eprintln!(
+                    "Skipping {} steps that upscale or use the output of an earlier step.",
+                    skipped
+                );
+            }
+            for (_, request) in &requests {
+                report.prepare(&runner.client, request)?;
+            }
+            return Ok(());
+        }
+
+        // The empty file reserves the name of the manifest and its directory until the end.
+        let manifest_path = match &self.manifest {
+            Some(path) => path.clone(),
+            None => {
+                OutputSettings {
+                    template: PIPELINE_TEMPLATE.to_string(),
+                    dir: runner.postprocess.output.dir.clone(),
+                }
+                .create(&OutputFields {
+                    cmd: name.clone(),
+                    model: model.to_string(),
+                    seed: None,
+                    timestamp: std::time::SystemTime::now(),
+                    prompt: String::new(),
+                    index: 0,
+                    ext: "json".to_string(),
+                })?
+                .0
+            }
+        };
+        let dir = manifest_path.with_extension("");
+
+        status!(
+            "Running pipeline {} with {} steps...",
+            name,
+            workflow.steps.len()
+        );
+        let mut manifest = PipelineManifest::default();
+        let started = Instant::now();
+        let result = workflow.run(&runner, &dir, &mut manifest).await;
+        report.latency_ms = Some(started.elapsed().as_millis() as u64);
+        for (index, step) in manifest.steps.iter().enumerate() {
+            report.output(OutputRecord {
+                index,
+                path: step.path.clone(),
+                seed: step.seed,
+                cost: step.cost,
+                error: step.error.clone(),
+            });
+        }
+
+        std::fs::write(&manifest_path, serde_json::to_vec_pretty(&manifest)?)
+            .with_context(|| format!("failed to write {}", manifest_path.display()))?;
+        status!("Manifest saved as: {}", manifest_path.display());
+        report.details = Some(serde_json::json!({
+            "dir": manifest.dir,
+            "manifest": manifest_path,
+        }));
+        match result {
+            // Keep the category of the error unless earlier steps produced something.
+            Err(err) if manifest.steps.len() > 1 => {
+                Err(ErrorKind::Incomplete.wrap(err.context(format!(
+                    "{} of {} steps completed",
+                    manifest.steps.len() - 1,
+                    workflow.steps.len()
+                ))))
+            }
+            Err(err) => Err(err),
+            Ok(()) => {
+                status!(
+                    "Pipeline completed with {} steps, total cost {:.4}.",
+                    manifest.steps.len(),
+                    manifest.cost().unwrap_or(0.0)
+                );
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(feature = "cli")]
+impl Completions {
+    /// Writes the completion script to stdout.
+    pub fn run(&self) -> anyhow::Result<()> {
+        self.write(&mut std::io::stdout().lock())
+    }
+
+    /// Writes the completion script.
+    ///
+    /// # Arguments
+    ///
+    /// * `out` - Where the script is written.
+    pub fn write(&self, out: &mut dyn Write) -> anyhow::Result<()> {
+        let mut cmd = Cli::command();
+        let name = cmd.get_name().to_string();
+        // The generator panics on write errors, so the script is rendered in memory first.
+        let mut script = Vec::new();
+        clap_complete::generate(self.shell, &mut cmd, name, &mut script);
+        out.write_all(&script)
+            .context("failed to write the completion script")?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "cli")]
+impl Man {
+    /// Writes one man page for `getimg` and one for each of its subcommands, named after the
+    /// subcommand, e.g. `getimg-t2i.1`, and records the directory in the report.
+    ///
+    /// # Arguments
+    ///
+    /// * `report` - The report of the command.
+    pub fn run(&self, report: &mut Report) -> anyhow::Result<()> {
+        std::fs::create_dir_all(&self.dir)
+            .with_context(|| format!("failed to create {}", self.dir.display()))?;
+        clap_mangen::generate_to(Cli::command(), &self.dir)
+            .with_context(|| format!("failed to write man pages to {}", self.dir.display()))?;
+        report.details = Some(serde_json::json!({ "dir": self.dir }));
+        status!("Man pages written to {}.", self.dir.display());
+        Ok(())
+    }
+}
+
+#[cfg(feature = "cli")]
+impl Repl {
+    /// Reads lines until `:quit` or Ctrl-D, running each in the session, then saves the line
+    /// history and the transcript.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - Client sending the requests.
+    /// * `model` - Model used unless the session sets one.
+    /// * `profile` - Configuration profile providing defaults.
+    /// * `postprocess` - How generated images are finished and saved.
+    /// * `report` - The report of the command, receiving the generated images and the transcript.
+    pub async fn run(
+        &self,
+        client: Client,
+        model: &str,
+        profile: Profile,
+        postprocess: Postprocess,
+        report: &mut Report,
+    ) -> anyhow::Result<()> {
+        let mut session =
+            Session::new(client, model, profile, self.preprocess.clone(), postprocess);
+        let mut editor = DefaultEditor::new()?;
+        let history = repl::history_path();
+        if let Some(path) = &history {
+            // A missing history file is expected on the first session.
+            let _ = editor.load_history(path);
+        }
+        status!(
+            "getimg {} with {}. Type :help for commands and Ctrl-D to quit.",
+            env!("CARGO_PKG_VERSION"),
+            model
+        );
+        loop {
+            let line = match editor.readline("getimg> ") {
+                Ok(line) => line,
+                Err(ReadlineError::Interrupted) => continue,
+                Err(ReadlineError::Eof) => break,
+                Err(err) => return Err(err.into()),
+            };
+            if !line.trim().is_empty() {
+                editor.add_history_entry(line.as_str())?;
+            }
+            match session.execute(&line).await {
+                Ok(Outcome::Generated(output)) => report.output(output),
+                Ok(Outcome::Quit) => break,
+                Ok(Outcome::Done) => {}
+                Err(err) => eprintln!("Error: {:#}", err),
+            }
+        }
+
+        if let Some(path) = &history {
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            editor
+                .save_history(path)
+                .with_context(|| format!("failed to write {}", path.display()))?;
+        }
+        if let Some(path) = &self.transcript {
+            session.save(path)?;
+            status!("Transcript saved as: {}", path.display());
+        }
+        report.details = Some(serde_json::json!({ "transcript": session.transcript }));
+        Ok(())
+    }
+}
+
+#[cfg(feature = "cli")]
+impl Batch {
+    /// Runs the job file and prints a summary.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - Client sending the requests.
+    /// * `model` - Model used by jobs naming none.
+    /// * `profile` - Configuration profile providing defaults.
+    /// * `postprocess` - How generated images are finished and saved.
+    /// * `report` - The report of the command, receiving the finished jobs.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` that is an error if any job failed.
+    pub async fn run(
+        &self,
+        client: Client,
+        model: &str,
+        profile: Profile,
+        postprocess: Postprocess,
+        report: &mut Report,
+    ) -> anyhow::Result<()> {
+        let jobs = batch::read_jobs(&self.file)?;
+        if dry_run().is_some() {
+            let base = self.file.parent().unwrap_or(Path::new(""));
+            for job in &jobs {
+                let request = job.request(model, &profile, &self.preprocess, base)?;
+                report.prepare(&client, &request)?;
+            }
+            return Ok(());
+        }
+        let total = jobs.len();
+        let results = self
+            .results
+            .clone()
+            .unwrap_or_else(|| batch::results_path(&self.file));
+        let state_path = self
+            .state
+            .clone()
+            .unwrap_or_else(|| batch::state_path(&self.file));
+        let mut state = if self.resume {
+            if !state_path.exists() {
+                bail!(
+                    "no batch state found at {}, run without --resume to start the batch",
+                    state_path.display()
+                );
+            }
+            BatchState::open(&state_path)?.resume(&jobs)
+        } else {
+            if !self.restart && state_path.exists() {
+                let done = BatchState::open(&state_path)?.count(JobStatus::Done);
+                if done > 0 {
+                    bail!(
+                        "{} records {} completed jobs, pass --resume to skip them or --restart to run them again",
+                        state_path.display(),
+                        done
+                    );
+                }
+            }
+            BatchState::new(&jobs)
+        };
+        let results_file = std::fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(self.resume)
+            .truncate(!self.resume)
+            .open(&results)
+            .with_context(|| format!("failed to open {}", results.display()))?;
+        let done = state.count(JobStatus::Done);
+        if done > 0 {
+            status!("Skipping {} jobs completed by a previous run.", done);
+        }
+        status!(
+            "Running {} jobs from {}...",
+            total - done,
+            self.file.display()
+        );
+        let runner = Runner {
+            client,
+            model: model.to_string(),
+            profile,
+            preprocess: self.preprocess.clone(),
+            postprocess,
+            base: self
+                .file
+                .parent()
+                .map(Path::to_path_buf)
+                .unwrap_or_default(),
+            concurrency: self.concurrency,
+        };
+        let started = Instant::now();
+        let summary = runner
+            .run(jobs, &mut state, &state_path, results_file)
+            .await?;
+        report.model = Some(model.to_string());
+        report.latency_ms = Some(started.elapsed().as_millis() as u64);
+        for (index, job) in state.jobs.iter().enumerate() {
+            if matches!(job.status, JobStatus::Done | JobStatus::Failed) {
+                report.output(OutputRecord {
+                    index,
+                    path: job.path.clone(),
+                    seed: job.seed,
+                    cost: job.cost,
+                    error: job.error.clone(),
+                });
+            }
+        }
+        report.cost = Some(summary.cost);
+        report.details = Some(serde_json::json!({
+            "succeeded": summary.succeeded,
+            "failed": summary.failed,
+            "skipped": summary.skipped,
+            "pending": summary.pending,
+            "results": results,
+            "state": state_path,
+        }));
+        status!(
+            "{} succeeded, {} failed, total cost {:.4}. Results written to {}.",
+            summary.succeeded,
+            summary.failed,
+            summary.cost,
+            results.display()
+        );
+        if summary.pending > 0 {
+            return Err(ErrorKind::Incomplete.wrap(anyhow!(
+                "batch interrupted with {} jobs not done, run again with --resume to continue",
+                summary.pending
+            )));
+        }
+        if summary.failed > 0 {
+            return Err(ErrorKind::Incomplete.wrap(anyhow!(
+                "{} of {} jobs failed, see {} and run again with --resume to retry them",
+                summary.failed,
+                total,
+                results.display()
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "cli")]
+impl Watch {
+    /// Returns the job sent for every image, whose `image` is set by the watcher.
+    pub fn job(&self) -> anyhow::Result<Job> {
+        let kind: JobKind = serde_json::from_value(serde_json::Value::from(self.kind.as_str()))?;
+        if kind == JobKind::Repaint && self.mask_image.is_none() {
+            bail!("--cmd paint requires --mask-image");
+        }
+        Ok(Job {
+            kind,
+            id: None,
+            prompt: self.prompt.clone(),
+            model: None,
+            image: None,
+            mask_image: self.mask_image.clone(),
+            seed: self.seed,
+            output: None,
+            negative_prompt: self.negative_prompt.clone(),
+            width: self.width,
+            height: self.height,
+            steps: self.steps,
+            guidance: self.guidance,
+            scheduler: self.scheduler.clone(),
+            strength: self.strength,
+            image_guidance: self.image_guidance,
+            output_format: self.output_format.clone(),
+            net: self.net.clone(),
+        })
+    }
+
+    /// Processes the images of the directory not processed yet, then every new one until
+    /// Ctrl-C, and prints a summary.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - Client sending the requests.
+    /// * `model` - Model of `i2i` requests.
+    /// * `profile` - Configuration profile providing defaults.
+    /// * `postprocess` - How generated images are finished and saved.
+    /// * `report` - The report of the command, receiving one output per processed image.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` that is an error if any image failed.
+    pub async fn run(
+        &self,
+        client: Client,
+        model: &str,
+        profile: Profile,
+        postprocess: Postprocess,
+        report: &mut Report,
+    ) -> anyhow::Result<()> {
+        if !self.dir.is_dir() {
+            bail!("{} is not a directory", self.dir.display());
+        }
+        let job = self.job()?;
+        let out = self
+            .out
+            .clone()
+            .unwrap_or_else(|| watch::out_dir(&self.dir));
+        let state_path = self
+            .state
+            .clone()
+            .unwrap_or_else(|| out.join(watch::STATE_FILE));
+        let watcher = Watcher {
+            runner: Arc::new(Runner {
+                client,
+                model: model.to_string(),
+                profile,
+                preprocess: self.preprocess.clone(),
+                postprocess: Postprocess {
+                    // Every job names its output in the output directory.
+                    output: OutputSettings {
+                        dir: None,
+                        ..postprocess.output
+                    },
+                    ..postprocess
+                },
+                base: PathBuf::new(),
+                concurrency: 1,
+            }),
+            job,
+            dir: self.dir.clone(),
+            out: out.clone(),
+            state_path: state_path.clone(),
+        };
+        let mut state = WatchState::open(&state_path)?;
+
+        if dry_run().is_some() {
+            let runner = &watcher.runner;
+            for file in watcher.pending(&state)? {
+                let request = watcher.job(&file).request(
+                    model,
+                    &runner.profile,
+                    &runner.preprocess,
+                    &runner.base,
+                )?;
+                report.prepare(&runner.client, &request)?;
+            }
+            return Ok(());
+        }
+
+        let started = Instant::now();
+        let records = watcher.run(&mut state, self.once).await?;
+        report.model = Some(model.to_string());
+        report.latency_ms = Some(started.elapsed().as_millis() as u64);
+        let failed = records
+            .iter()
+            .filter(|record| record.error.is_some())
+            .count();
+        let processed = records.len();
+        for record in records {
+            report.output(record);
+        }
+        report.details = Some(serde_json::json!({
+            "dir": self.dir,
+            "out": out,
+            "state": state_path,
+            "processed": processed,
+            "failed": failed,
+        }));
+        status!(
+            "{} images processed, {} failed. Outputs saved in {}.",
+            processed - failed,
+            failed,
+            out.display()
+        );
+        if failed > 0 {
+            return Err(ErrorKind::Incomplete.wrap(anyhow!(
+                "{} of {} images failed, restart the watcher to retry them",
+                failed,
+                processed
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "cli")]
+impl Command {
+    /// Returns whether the user chose the seeds of the requests of the command, so that their
+    /// responses are worth caching: responses to random seeds are never requested again. Commands
+    /// running jobs decide for each job instead, see [`Job::client`].
+    ///
+    /// # Arguments
+    ///
+    /// * `count` - Number of variations of each request.
+    /// * `seeds` - How the seeds of variations are chosen.
+    pub fn chooses_seeds(&self, count: usize, seeds: SeedMode) -> bool {
+        let variations = count == 1 || seeds == SeedMode::Sequential;
+        match self {
+            Command::Edit(cmd) => cmd.seed.is_some() && variations,
+            Command::Repaint(cmd) => cmd.seed.is_some() && variations,
+            Command::TextToImage(cmd) => cmd.seed.is_some() && variations,
+            Command::ImageToImage(cmd) => cmd.seed.is_some() && variations,
+            Command::ControlNet(cmd) => cmd.seed.is_some() && variations,
+            // The seed is the embedded one unless overridden.
+            Command::Rerun(_) => variations,
+            Command::Sweep(cmd) => {
+                cmd.cmd.seed().is_some()
+                    || [Some(&cmd.x), cmd.y.as_ref(), cmd.z.as_ref()]
+                        .into_iter()
+                        .flatten()
+                        .any(|axis| axis.name == "seed")
+            }
+            _ => true,
+        }
+    }
+
+    /// Returns the images of an `i2i`, `edit`, `cnet` or `paint` command whose `--image` names a
+    /// directory or glob pattern, with the command itself.
+    pub fn inputs(&self) -> anyhow::Result<Option<(SweepCommand, Vec<Input>)>> {
+        let cmd = match self {
+            Command::Edit(cmd) => SweepCommand::Edit(cmd.clone()),
+            Command::Repaint(cmd) => SweepCommand::Repaint(cmd.clone()),
+            Command::ImageToImage(cmd) => SweepCommand::ImageToImage(cmd.clone()),
+            Command::ControlNet(cmd) => SweepCommand::ControlNet(cmd.clone()),
+            _ => return Ok(None),
+        };
+        let Some(image) = cmd.image() else {
+            return Ok(None);
+        };
+        // Only `paint` pairs masks with its images, the other commands process them as inputs.
+        let skip_masks = matches!(cmd, SweepCommand::Repaint(_));
+        Ok(inputs::expand(image, skip_masks)?.map(|inputs| (cmd, inputs)))
+    }
+}
+
+#[cfg(feature = "cli")]
+impl SweepCommand {
+    /// Returns the input image of image-based commands.
+    pub fn image(&self) -> Option<&str> {
+        match self {
+            SweepCommand::Edit(cmd) => Some(&cmd.image),
+            SweepCommand::Repaint(cmd) => Some(&cmd.image),
+            SweepCommand::TextToImage(_) => None,
+            SweepCommand::ImageToImage(cmd) => Some(&cmd.image),
+            SweepCommand::ControlNet(cmd) => Some(&cmd.image),
+        }
+    }
+
+    /// Reads the prompt from `--prompt-file`, or from stdin when the prompt is `-`, once for
+    /// every request built from the command.
+    pub fn read_prompt(&mut self) -> anyhow::Result<()> {
+        let prompt_file = match self {
+            SweepCommand::Edit(cmd) => {
+                cmd.read_prompt()?;
+                &mut cmd.prompt_file
+            }
+            SweepCommand::Repaint(cmd) => {
+                cmd.read_prompt()?;
+                &mut cmd.prompt_file
+            }
+            SweepCommand::TextToImage(cmd) => {
+                cmd.read_prompt()?;
+                &mut cmd.prompt_file
+            }
+            SweepCommand::ImageToImage(cmd) => {
+                cmd.read_prompt()?;
+                &mut cmd.prompt_file
+            }
+            SweepCommand::ControlNet(cmd) => {
+                cmd.read_prompt()?;
+                &mut cmd.prompt_file
+            }
+        };
+        *prompt_file = None;
+        Ok(())
+    }
+
+    /// Returns the command run on one image of a folder or glob input with a given seed. The
+    /// mask of `paint` is paired with the image, see [`inputs::mask_for`], unless `--mask-image`
+    /// names a single file.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The image.
+    /// * `seed` - Seed of the request.
+    pub fn with_input(&self, input: &Input, seed: usize) -> anyhow::Result<Self> {
+        let image = input.path.to_string_lossy().into_owned();
+        let mut cmd = self.clone();
+        match &mut cmd {
+            SweepCommand::Edit(cmd) => (cmd.image, cmd.seed) = (image, Some(seed)),
+            SweepCommand::Repaint(cmd) => {
+                let masks = cmd.mask_image.as_deref().map(Path::new);
+                if masks.is_none_or(Path::is_dir) {
+                    match inputs::mask_for(input, masks) {
+                        Some(mask) => cmd.mask_image = Some(mask.to_string_lossy().into_owned()),
+                        None if cmd.region.is_empty() => bail!(
+                            "no mask found for {}, add {} next to it or pass --mask-image",
+                            input.path.display(),
+                            input
+                                .path
+                                .with_file_name(format!(
+                                    "{}{}.png",
+                                    input.path.file_stem().unwrap_or_default().to_string_lossy(),
+                                    inputs::MASK_SUFFIXES[0]
+                                ))
+                                .display()
+                        ),
+                        None => cmd.mask_image = None,
+                    }
+                }
+                (cmd.image, cmd.seed) = (image, Some(seed));
+            }
+            SweepCommand::TextToImage(cmd) => cmd.seed = Some(seed),
+            SweepCommand::ImageToImage(cmd) => (cmd.image, cmd.seed) = (image, Some(seed)),
+            SweepCommand::ControlNet(cmd) => (cmd.image, cmd.seed) = (image, Some(seed)),
+        }
+        Ok(cmd)
+    }
+
+    /// Returns the number of images of a folder or glob input processed at the same time.
+    pub fn concurrency(&self) -> usize {
+        match self {
+            SweepCommand::Edit(cmd) => cmd.concurrency,
+            SweepCommand::Repaint(cmd) => cmd.concurrency,
+            SweepCommand::TextToImage(_) => 1,
+            SweepCommand::ImageToImage(cmd) => cmd.concurrency,
+            SweepCommand::ControlNet(cmd) => cmd.concurrency,
+        }
+        .max(1)
+    }
+
+    /// Returns the name of the command, e.g. `i2i`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            SweepCommand::Edit(_) => "edit",
+            SweepCommand::Repaint(_) => "paint",
+            SweepCommand::TextToImage(_) => "t2i",
+            SweepCommand::ImageToImage(_) => "i2i",
+            SweepCommand::ControlNet(_) => "cnet",
+        }
+    }
+
+    /// Returns the seed given with `--seed`.
+    pub fn seed(&self) -> Option<usize> {
+        match self {
+            SweepCommand::Edit(cmd) => cmd.seed,
+            SweepCommand::Repaint(cmd) => cmd.seed,
+            SweepCommand::TextToImage(cmd) => cmd.seed,
+            SweepCommand::ImageToImage(cmd) => cmd.seed,
+            SweepCommand::ControlNet(cmd) => cmd.seed,
+        }
+    }
+
+    /// Builds the base request of the sweep, filling unset options from the profile.
+    pub fn request(&self, model: &str, profile: &Profile) -> anyhow::Result<GenerationRequest> {
+        match self.clone() {
+            SweepCommand::Edit(mut cmd) => {
+                cmd.read_prompt()?;
+                cmd.apply_defaults(&profile.edit);
+                cmd.request()
+            }
+            SweepCommand::Repaint(mut cmd) => {
+                cmd.read_prompt()?;
+                cmd.apply_defaults(&profile.paint);
+                cmd.request()
+            }
+            SweepCommand::TextToImage(mut cmd) => {
+                cmd.read_prompt()?;
+                cmd.apply_defaults(&profile.t2i);
+                Ok(cmd.request(model))
+            }
+            SweepCommand::ImageToImage(mut cmd) => {
+                cmd.read_prompt()?;
+                cmd.apply_defaults(&profile.i2i);
+                cmd.request(model)
+            }
+            SweepCommand::ControlNet(mut cmd) => {
+                cmd.read_prompt()?;
+                cmd.apply_defaults(&profile.cnet);
+                cmd.request()
+            }
+        }
+    }
+}
+
+/// Struct representing an image-based command run on every image of a directory or glob pattern.
+#[cfg(feature = "cli")]
+#[derive(Debug, Clone)]
+pub struct InputRun {
+    /// The command, whose `--image` is replaced by each image.
+    pub cmd: SweepCommand,
+    /// The images.
+    pub inputs: Vec<Input>,
+    /// Number of variations generated for each image.
+    pub count: usize,
+    /// How the seeds of variations are chosen.
+    pub seeds: SeedMode,
+}
+
+#[cfg(feature = "cli")]
+impl InputRun {
+    /// Generates every image and variation with at most `-j` requests in flight, starting the
+    /// next one as soon as one finishes, and saves the outputs at the relative paths of their
+    /// inputs under a directory named by the output template, `{cmd}-{timestamp}` by default. Every image uses the same seed, the given one or a random one, and its
+    /// variations follow `--seeds`.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - Client sending the requests.
+    /// * `model` - Model of `i2i` requests.
+    /// * `profile` - Configuration profile providing defaults.
+    /// * `postprocess` - How generated images are finished and saved.
+    /// * `report` - The report of the command, receiving one output per image and variation.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` that is an error if any image failed.
+    pub async fn run(
+        mut self,
+        client: Client,
+        model: &str,
+        profile: Profile,
+        postprocess: Postprocess,
+        report: &mut Report,
+    ) -> anyhow::Result<()> {
+        if postprocess.output.is_stdout() {
+            bail!("--output - writes a single image to stdout, save the images of a directory or glob to files instead");
+        }
+        self.cmd.read_prompt()?;
+        let seed = self.cmd.seed().unwrap_or_else(random_seed);
+        // A custom template names the directory, so its extension is dropped.
+        let template = match postprocess.output.template.as_str() {
+            DEFAULT_TEMPLATE => INPUTS_TEMPLATE,
+            template => template.trim_end_matches(".{ext}"),
+        };
+        let root = render(
+            template,
+            &OutputFields {
+                cmd: self.cmd.name().to_string(),
+                model: model.to_string(),
+                seed: Some(seed),
+                timestamp: std::time::SystemTime::now(),
+                prompt: String::new(),
+                index: 0,
+                ext: String::new(),
+            },
+        )?;
+        let root = match &postprocess.output.dir {
+            Some(dir) => dir.join(root),
+            None => PathBuf::from(root),
+        };
+
+        status!(
+            "Processing {} images with seed {}{}...",
+            self.inputs.len(),
+            seed,
+            match self.count {
+                1 => String::new(),
+                count => format!(", {} variations each", count),
+            }
+        );
+        if dry_run().is_some() {
+            for input in &self.inputs {
+                let request = self
+                    .cmd
+                    .with_input(input, seed)
+                    .and_then(|cmd| cmd.request(model, &profile));
+                match request {
+                    Ok(request) => {
+                        for request in request.variations(self.count, self.seeds) {
+                            report.prepare(&client, &request)?;
+                        }
+                    }
+                    Err(err) => eprintln!("{} failed: {:#}", input.relative.display(), err),
+                }
+            }
+            return Ok(());
+        }
+
+        let total = self.inputs.len() * self.count;
+        let (count, seeds) = (self.count, self.seeds);
+        let cmd = Arc::new(self.cmd.clone());
+        let profile = Arc::new(profile);
+        let postprocess = Arc::new(Postprocess {
+            // Every output is named under `root`, which already holds the output directory.
+            output: OutputSettings {
+                dir: None,
+                ..postprocess.output
+            },
+            ..postprocess
+        });
+        let started = Instant::now();
+        let variations =
+            (0..total).map(|position| (position, self.inputs[position / count].clone()));
+        let (_, mut tasks) =
+            batch::spawn_bounded(self.cmd.concurrency(), variations, |(position, input)| {
+                let (cmd, profile, postprocess) =
+                    (cmd.clone(), profile.clone(), postprocess.clone());
+                let (mut client, model, root) = (client.clone(), model.to_string(), root.clone());
+                async move {
+                    let relative = input.relative.clone();
+                    let mut record = OutputRecord {
+                        index: position,
+                        seed: Some(seed),
+                        ..OutputRecord::default()
+                    };
+                    let result = async {
+                        // Requests are built once their turn comes, so that only the images in
+                        // flight are held in memory.
+                        let request = tokio::task::spawn_blocking(move || {
+                            let request =
+                                cmd.with_input(&input, seed)?.request(&model, &profile)?;
+                            anyhow::Ok(
+                                request
+                                    .variations(count, seeds)
+                                    .swap_remove(position % count),
+                            )
+                        })
+                        .await??;
+                        record.seed = request.seed();
+                        let response = client.send(&request).await?;
+                        let output = format!(
+                            "{}/{}{}.{{ext}}",
+                            escape(&root.to_string_lossy()),
+                            escape(&relative.with_extension("").to_string_lossy()),
+                            match count {
+                                1 => String::new(),
+                                _ => format!("-{}", position % count),
+                            }
+                        );
+                        let (seed, cost) = (response.seed.or(request.seed()), response.cost);
+                        let saved = tokio::task::spawn_blocking(move || {
+                            postprocess.save(&request, &response, Some(&output), position)
+                        })
+                        .await??;
+                        anyhow::Ok((saved.path, seed, cost))
+                    }
+                    .await;
+                    match result {
+                        Ok((path, seed, cost)) => {
+                            (record.path, record.seed, record.cost) = (Some(path), seed, cost);
+                            (record, None)
+                        }
+                        Err(err) => {
+                            eprintln!("{} failed: {:#}", relative.display(), err);
+                            record.error = Some(format!("{:#}", err));
+                            (record, Some(err))
+                        }
+                    }
+                }
+            });
+        // Outputs are reported in the order of the images, whatever order they finish in.
+        let mut records = (0..total).map(|_| None).collect::<Vec<_>>();
+        let mut errors = Vec::new();
+        while let Some(task) = tasks.join_next().await {
+            // The semaphore is never closed, so every task runs.
+            let Some((record, error)) = task? else {
+                continue;
+            };
+            errors.extend(error);
+            let index = record.index;
+            records[index] = Some(record);
+        }
+        for record in records.into_iter().flatten() {
+            report.output(record);
+        }
+        report.model = Some(model.to_string());
+        report.latency_ms = Some(started.elapsed().as_millis() as u64);
+        report.details = Some(serde_json::json!({
+            "dir": root,
+            "images": self.inputs.len(),
+            "failed": errors.len(),
+        }));
+        if errors.len() == total {
+            // Every image failed, most likely for the same reason, so its category is kept.
+            return Err(errors
+                .remove(0)
+                .context(format!("all {} images failed", total)));
+        }
+        status!(
+            "{} images saved in {}.",
+            total - errors.len(),
+            root.display()
+        );
+        if !errors.is_empty() {
+            return Err(ErrorKind::Incomplete.wrap(anyhow!(
+                "{} of {} images failed",
+                errors.len(),
+                total
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "cli")]
+impl Sweep {
+    /// Generates every combination of the axes with the seed of the base request, then saves the
+    /// cells, the grid and the manifest.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - Client sending the requests.
+    /// * `model` - Model of `t2i` and `i2i` requests.
+    /// * `profile` - Configuration profile providing defaults.
+    /// * `postprocess` - How generated images are finished and saved.
+    /// * `report` - The report of the command, receiving the cells.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` that is an error if any combination failed.
+    pub async fn run(
+        &self,
+        client: Client,
+        model: &str,
+        profile: Profile,
+        postprocess: Postprocess,
+        report: &mut Report,
+    ) -> anyhow::Result<()> {
+        let base = self.cmd.request(model, &profile)?;
+        report.request(&base);
+        let axes = [Some(self.x.clone()), self.y.clone(), self.z.clone()]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>();
+        let cells = sweep::cells(&base, &axes)?;
+        if dry_run().is_some() {
+            for cell in &cells {
+                report.prepare(&client, &cell.request)?;
+            }
+            return Ok(());
+        }
+
+        // The empty file reserves the name of the grid and its directory until the end.
+        let grid_path = match &self.grid {
+            Some(path) => path.clone(),
+            None => {
+                OutputSettings {
+                    template: SWEEP_TEMPLATE.to_string(),
+                    dir: postprocess.output.dir.clone(),
+                }
+                .create(&OutputFields::new(&base, base.seed(), &[]))?
+                .0
+            }
+        };
+        let cells_dir = grid_path.with_extension("");
+        let cell_postprocess = Postprocess {
+            output: OutputSettings {
+                template: format!("{}/{{index}}.{{ext}}", escape(&cells_dir.to_string_lossy())),
+                dir: None,
+            },
+            ..postprocess
+        };
+
+        status!(
+            "Sweeping {} combinations{}...",
+            cells.len(),
+            base.seed()
+                .map(|seed| format!(" with seed {}", seed))
+                .unwrap_or_default()
+        );
+        let total = cells.len();
+        let cell_postprocess = Arc::new(cell_postprocess);
+        let started = Instant::now();
+        let (_, mut tasks) = batch::spawn_bounded(self.concurrency, cells, |cell| {
+            let (mut client, postprocess) = (client.clone(), cell_postprocess.clone());
+            async move {
+                let result = async {
+                    let response = client.send(&cell.request).await?;
+                    let (request, index) = (cell.request.clone(), cell.index);
+                    // A cell that fails to decode or save is recorded like a failed request, so
+                    // that the grid and manifest still cover the cells already paid for.
+                    tokio::task::spawn_blocking(move || {
+                        let image = image::load_from_memory(&STANDARD.decode(&response.image)?)?;
+                        let saved = postprocess.save(&request, &response, None, index)?;
+                        anyhow::Ok((response, image, saved))
+                    })
+                    .await?
+                }
+                .await;
+                (cell, result)
+            }
+        });
+        // Cells are reported in grid order, whatever order they finish in.
+        let mut results = (0..total).map(|_| None).collect::<Vec<_>>();
+        while let Some(task) = tasks.join_next().await {
+            // The semaphore is never closed, so every task runs.
+            let Some((cell, result)) = task? else {
+                continue;
+            };
+            let index = cell.index;
+            results[index] = Some((cell, result));
+        }
+        let mut images = vec![None; total];
+        let mut records = Vec::with_capacity(total);
+        for (cell, result) in results.into_iter().flatten() {
+            let mut record = CellRecord {
+                index: cell.index,
+                values: cell.values.clone(),
+                seed: cell.request.seed(),
+                path: None,
+                cost: None,
+                error: None,
+            };
+            match result {
+                Ok((response, image, saved)) => {
+                    images[cell.index] = Some(image);
+                    record.seed = response.seed.or(record.seed);
+                    record.path = Some(saved.path);
+                    record.cost = response.cost;
+                }
+                Err(err) => {
+                    let values = cell
+                        .values
+                        .iter()
+                        .map(|(name, value)| format!("{}={}", name, value))
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    eprintln!("Combination {} ({}) failed: {:#}", cell.index, values, err);
+                    record.error = Some(format!("{:#}", err));
+                }
+            }
+            report.output(OutputRecord {
+                index: record.index,
+                path: record.path.clone(),
+                seed: record.seed,
+                cost: record.cost,
+                error: record.error.clone(),
+            });
+            records.push(record);
+        }
+        report.latency_ms = Some(started.elapsed().as_millis() as u64);
+
+        sweep::render_grid(&axes, &images, self.cell_size).save(&grid_path)?;
+        status!("Grid saved as: {}", grid_path.display());
+        let manifest_path = grid_path.with_extension("json");
+        let failed = records
+            .iter()
+            .filter(|record| record.error.is_some())
+            .count();
+        let manifest = SweepManifest {
+            grid: grid_path,
+            axes,
+            request: base.without_images(),
+            cells: records,
+        };
+        std::fs::write(&manifest_path, serde_json::to_vec_pretty(&manifest)?)?;
+        status!("Manifest saved as: {}", manifest_path.display());
+        report.details = Some(serde_json::json!({
+            "grid": manifest.grid,
+            "manifest": manifest_path,
+        }));
+        if failed > 0 {
+            return Err(ErrorKind::Incomplete.wrap(anyhow!(
+                "{} of {} combinations failed",
+                failed,
+                manifest.cells.len()
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Struct representing how generated images are finished and saved: watermarking, metadata,
+/// provenance labelling and output naming.
+#[cfg(feature = "cli")]
+#[derive(Debug, Clone)]
+pub struct Postprocess {
+    /// Whether generation parameters are embedded.
+    pub metadata: bool,
+    /// Whether an invisible watermark is embedded.
+    pub watermark: bool,
+    /// Team id carried by the watermark.
+    pub team_id: u16,
+    /// Where and under which names outputs are saved.
+    pub output: OutputSettings,
+}
+
+/// Struct representing an image saved by [`Postprocess::save`].
+#[cfg(feature = "cli")]
+#[derive(Debug, Clone)]
+pub struct Saved {
+    /// Path of the saved image.
+    pub path: PathBuf,
+    /// Watermark payload embedded in the image, if any.
+    pub payload: Option<Payload>,
+}
+
+#[cfg(feature = "cli")]
+impl Postprocess {
+    /// Decodes, finishes and saves a generated image.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The request sent to the API.
+    /// * `response` - The response of the API.
+    /// * `output` - Output template overriding the configured one, if any.
+    /// * `index` - Position of the output within the run, substituted for `{index}`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the saved image.
+    pub fn save(
+        &self,
+        request: &GenerationRequest,
+        response: &ToImageResponse,
+        output: Option<&str>,
+        index: usize,
+    ) -> anyhow::Result<Saved> {
+        let mut image = STANDARD.decode(&response.image)?;
+        let mut payload = None;
+        if self.watermark {
+            let embedded = Payload::from_output(self.team_id, &image);
+            image = Watermarker::default().embed_bytes(&image, embedded)?;
+            payload = Some(embedded);
+        }
+        if self.metadata {
+            image = metadata::embed(&image, &GenerationParams::new(request, response))?;
+        }
+        image = provenance::label(&image, &Manifest::new(request))?;
+        let settings = match output {
+            Some(template) => OutputSettings {
+                template: template.to_string(),
+                ..self.output.clone()
+            },
+            None => self.output.clone(),
+        };
+        let fields = OutputFields {
+            index,
+            ..OutputFields::new(request, response.seed, &image)
+        };
+        if settings.is_stdout() {
+            let mut stdout = std::io::stdout().lock();
+            stdout.write_all(&image)?;
+            stdout.flush()?;
+            return Ok(Saved {
+                path: PathBuf::from(STDOUT_TEMPLATE),
+                payload,
+            });
+        }
+        let (path, mut file) = settings.create(&fields)?;
+        if let Err(err) = file.write_all(&image) {
+            // Free the claimed name rather than leave an empty image behind.
+            let _ = std::fs::remove_file(&path);
+            return Err(err).with_context(|| format!("failed to write {}", path.display()));
+        }
+        status!("Image saved as: {}", path.display());
+        // Previews are drawn on stderr, keeping stdout to paths and JSON for scripts.
+        if let Some(protocol) = preview::preview().filter(|_| std::io::stderr().is_terminal()) {
+            let mut stderr = std::io::stderr().lock();
+            if let Err(err) = preview::show(&image, protocol, preview::columns(), &mut stderr) {
+                eprintln!("Failed to preview {}: {:#}", path.display(), err);
+            }
+        }
+        Ok(Saved { path, payload })
+    }
 }
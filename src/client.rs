@@ -1,16 +1,31 @@
 use anyhow::Result;
 use reqwest::header;
 use reqwest::Client as ReqClient;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::error::Error;
 use std::fmt;
+use tokio::task::JoinSet;
 
+use crate::cache::Cache;
 use crate::request::{
-    ControlNetRequest, EditImageRequest, ImageToImageRequest, RepaintImageRequest,
-    TextToImageRequest,
+    ControlNetRequest, EditImageRequest, GenerationRequest, ImageToImageRequest,
+    RepaintImageRequest, SeedMode, TextToImageRequest,
 };
 use crate::response::ToImageResponse;
 
 // Constants
-pub(crate) const BASE_URL: &str = "https://api.getimg.ai/v1";
+/// Default base URL of the GetImg API.
+pub const BASE_URL: &str = "https://api.getimg.ai/v1";
+
+/// Model used by the ControlNet endpoint.
+pub const CONTROLNET_MODEL: &str = "stable-diffusion-v1-5";
+
+/// Model used by the inpainting endpoint.
+pub const INPAINT_MODEL: &str = "stable-diffusion-v1-5-inpainting";
+
+/// Model used by the instruction-based editing endpoint.
+pub const INSTRUCT_MODEL: &str = "instruct-pix2pix";
 
 /// GetImg API client structure.
 #[derive(Clone)]
@@ -26,6 +41,12 @@ pub struct Client {
 
     /// API URL for GetImg.
     pub api_url: &'static str,
+
+    /// Base URL replacing `api_url`, see [`Client::with_base_url`].
+    base_url: Option<String>,
+
+    /// Cache of the responses to requests with a seed, see [`Client::with_cache`].
+    cache: Option<Cache>,
 }
 
 impl Client {
@@ -57,9 +78,52 @@ impl Client {
             api_key: api_key.to_owned(),
             model: model.to_owned(),
             api_url: BASE_URL,
+            base_url: None,
+            cache: None,
         }
     }
 
+    /// Returns the client sending its requests to another base URL, e.g. a proxy or a local
+    /// stand-in for the API.
+    ///
+    /// # Arguments
+    ///
+    /// * `base_url` - URL the endpoints are appended to, without a trailing slash.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use getimg::client::Client;
+    ///
+    /// let client = Client::new("your_api_key", "your_model").with_base_url("http://localhost:8080");
+    /// assert_eq!(client.base_url(), "http://localhost:8080");
+    /// ```
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = Some(base_url.into());
+        self
+    }
+
+    /// Returns the base URL requests are sent to.
+    pub fn base_url(&self) -> &str {
+        self.base_url.as_deref().unwrap_or(self.api_url)
+    }
+
+    /// Returns the client storing responses to requests with a seed in a cache, or sending every
+    /// request for `None`.
+    ///
+    /// # Arguments
+    ///
+    /// * `cache` - The cache, see [`crate::cache`].
+    pub fn with_cache(mut self, cache: Option<Cache>) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Returns the cache of the client, if any.
+    pub fn cache(&self) -> Option<&Cache> {
+        self.cache.as_ref()
+    }
+
     /// Generates an image based on a text prompt.
     ///
     /// # Arguments
@@ -113,18 +177,8 @@ impl Client {
             seed,
         };
 
-        let response = self
-            .client
-            .post(format!("{}/latent-consistency/text-to-image", self.api_url))
-            .header(header::ACCEPT, "application/json")
-            .header(header::AUTHORIZATION, format!("Bearer {}", self.api_key))
-            .header(header::CONTENT_TYPE, "application/json")
-            .json(&request_body)
-            .send()
-            .await?;
-
-        let result = response.json::<ToImageResponse>().await?;
-        Ok(result)
+        self.send(&GenerationRequest::TextToImage(request_body))
+            .await
     }
 
     /// Generates an image based on an image prompt.
@@ -180,21 +234,8 @@ impl Client {
             seed: Some(seed),
         };
 
-        let response = self
-            .client
-            .post(format!(
-                "{}/latent-consistency/image-to-image",
-                self.api_url
-            ))
-            .header(header::ACCEPT, "application/json")
-            .header(header::AUTHORIZATION, format!("Bearer {}", self.api_key))
-            .header(header::CONTENT_TYPE, "application/json")
-            .json(&request_body)
-            .send()
-            .await?;
-
-        let result = response.json::<ToImageResponse>().await?;
-        Ok(result)
+        self.send(&GenerationRequest::ImageToImage(request_body))
+            .await
     }
 
     /// Generates an image using the ControlNet endpoint.
@@ -251,7 +292,7 @@ impl Client {
     ) -> Result<ToImageResponse> {
         let request_body = ControlNetRequest {
             controlnet: controlnet.to_string(),
-            model: "stable-diffusion-v1-5".to_string(),
+            model: CONTROLNET_MODEL.to_string(),
             prompt: prompt.to_string(),
             negative_prompt: Some(negative_prompt.to_string()),
             image: image.to_string(),
@@ -265,18 +306,8 @@ impl Client {
             output_format: output_format.to_string(),
         };
 
-        let response = self
-            .client
-            .post(format!("{}/stable-diffusion/controlnet", self.api_url))
-            .header(header::ACCEPT, "application/json")
-            .header(header::AUTHORIZATION, format!("Bearer {}", self.api_key))
-            .header(header::CONTENT_TYPE, "application/json")
-            .json(&request_body)
-            .send()
-            .await?;
-
-        let result = response.json::<ToImageResponse>().await?;
-        Ok(result)
+        self.send(&GenerationRequest::ControlNet(request_body))
+            .await
     }
 
     /// Generates a repainted image using the GetImg API.
@@ -316,7 +347,7 @@ impl Client {
         output_format: &str,
     ) -> Result<ToImageResponse> {
         let request_body = RepaintImageRequest {
-            model: "stable-diffusion-v1-5-inpainting".to_string(),
+            model: INPAINT_MODEL.to_string(),
             prompt: prompt.to_string(),
             negative_prompt: negative_prompt.map(|s| s.to_string()),
             image: image_data.to_string(),
@@ -331,18 +362,7 @@ impl Client {
             output_format: output_format.to_string(),
         };
 
-        let response = self
-            .client
-            .post(format!("{}/stable-diffusion/inpaint", self.api_url))
-            .header(header::ACCEPT, "application/json")
-            .header(header::AUTHORIZATION, format!("Bearer {}", self.api_key))
-            .header(header::CONTENT_TYPE, "application/json")
-            .json(&request_body)
-            .send()
-            .await?;
-
-        let result = response.json::<ToImageResponse>().await?;
-        Ok(result)
+        self.send(&GenerationRequest::Repaint(request_body)).await
     }
 
     /// Generates an edited image using the GetImg API.
@@ -376,7 +396,7 @@ impl Client {
         output_format: &str,
     ) -> Result<ToImageResponse> {
         let request_body = EditImageRequest {
-            model: "instruct-pix2pix".to_string(),
+            model: INSTRUCT_MODEL.to_string(),
             prompt: prompt.to_string(),
             negative_prompt: negative_prompt.map(|s| s.to_string()),
             image: image_data.to_string(),
@@ -388,21 +408,355 @@ impl Client {
             output_format: output_format.to_string(),
         };
 
-        let response = self
-            .client
-            .post(format!("{}/stable-diffusion/instruct", self.api_url))
-            .header(header::ACCEPT, "application/json")
-            .header(header::AUTHORIZATION, format!("Bearer {}", self.api_key))
-            .header(header::CONTENT_TYPE, "application/json")
-            .json(&request_body)
-            .send()
+        self.send(&GenerationRequest::Edit(request_body)).await
+    }
+
+    /// Sends a generation request to its endpoint.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The request body, tagged with the endpoint it targets.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the generated image response or an error if the request fails.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use getimg::client::Client;
+    /// use getimg::request::{GenerationRequest, TextToImageRequest};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::new("your_api_key", "your_model");
+    ///     let request = GenerationRequest::TextToImage(TextToImageRequest {
+    ///         prompt: "Rusty crab on the beach".to_string(),
+    ///         model: client.model.clone(),
+    ///         negative_prompt: None,
+    ///         width: 512,
+    ///         height: 512,
+    ///         steps: 4,
+    ///         output_format: "jpeg".to_string(),
+    ///         seed: Some(512),
+    ///     });
+    ///     match client.send(&request).await {
+    ///         Ok(content) => println!("Generated Content: {:?}", content),
+    ///         Err(err) => eprintln!("Error: {:?}", err),
+    ///     }
+    /// }
+    /// ```
+    pub async fn send(&mut self, request: &GenerationRequest) -> Result<ToImageResponse> {
+        let mut prepared = self.prepare(request)?;
+        if let Some(cache) = self.cache.clone() {
+            // The cache works on files, so it is kept off the threads driving the requests.
+            let cached;
+            (prepared, cached) = tokio::task::spawn_blocking(move || {
+                let cached = cache.get(&prepared);
+                (prepared, cached)
+            })
             .await?;
+            if let Some(response) = cached {
+                return Ok(response);
+            }
+        }
+        let mut builder = self.client.post(&prepared.url);
+        for (name, value) in &prepared.headers {
+            builder = builder.header(name, value);
+        }
 
+        let response = builder.json(&prepared.body).send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(ApiError::new(status.as_u16(), &body).into());
+        }
         let result = response.json::<ToImageResponse>().await?;
+        if let Some(cache) = self.cache.clone() {
+            let response = result.clone();
+            // The image is paid for, so failing to cache it must not lose it.
+            let _ = tokio::task::spawn_blocking(move || cache.put(&prepared, &response)).await;
+        }
         Ok(result)
     }
+
+    /// Builds the HTTP request [`Client::send`] would send, without sending it.
+    ///
+    /// The request carries the API key and full image data; use [`PreparedRequest::redacted`]
+    /// before showing it.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The request to prepare.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the method, URL, headers and JSON body of the request.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use getimg::client::Client;
+    /// use getimg::request::{GenerationRequest, TextToImageRequest};
+    ///
+    /// let client = Client::new("your_api_key", "lcm-realistic-vision-v5-1");
+    /// let request = GenerationRequest::TextToImage(TextToImageRequest {
+    ///     prompt: "Rusty crab on the beach".to_string(),
+    ///     model: client.model.clone(),
+    ///     negative_prompt: None,
+    ///     width: 512,
+    ///     height: 512,
+    ///     steps: 4,
+    ///     output_format: "jpeg".to_string(),
+    ///     seed: Some(512),
+    /// });
+    /// let prepared = client.prepare(&request).unwrap().redacted();
+    /// assert_eq!(
+    ///     prepared.url,
+    ///     "https://api.getimg.ai/v1/latent-consistency/text-to-image"
+    /// );
+    /// assert_eq!(prepared.headers["Authorization"], "Bearer ***");
+    /// println!("{}", prepared.to_curl());
+    /// ```
+    pub fn prepare(&self, request: &GenerationRequest) -> Result<PreparedRequest> {
+        let body = match request {
+            GenerationRequest::TextToImage(body) => serde_json::to_value(body)?,
+            GenerationRequest::ImageToImage(body) => serde_json::to_value(body)?,
+            GenerationRequest::ControlNet(body) => serde_json::to_value(body)?,
+            GenerationRequest::Repaint(body) => serde_json::to_value(body)?,
+            GenerationRequest::Edit(body) => serde_json::to_value(body)?,
+        };
+        let headers = [
+            (header::ACCEPT, "application/json".to_string()),
+            (header::AUTHORIZATION, format!("Bearer {}", self.api_key)),
+            (header::CONTENT_TYPE, "application/json".to_string()),
+        ];
+        Ok(PreparedRequest {
+            method: "POST".to_string(),
+            url: format!("{}/{}", self.base_url(), request.endpoint()),
+            headers: headers
+                .into_iter()
+                .map(|(name, value)| (title_case(name.as_str()), value))
+                .collect(),
+            body,
+        })
+    }
+
+    /// Sends several requests concurrently.
+    ///
+    /// # Arguments
+    ///
+    /// * `requests` - The requests to send.
+    ///
+    /// # Returns
+    ///
+    /// The result of each request, in the order of `requests`.
+    pub async fn send_all(&self, requests: &[GenerationRequest]) -> Vec<Result<ToImageResponse>> {
+        let mut tasks = JoinSet::new();
+        for (index, request) in requests.iter().cloned().enumerate() {
+            let mut client = self.clone();
+            tasks.spawn(async move { (index, client.send(&request).await) });
+        }
+        let mut results = (0..requests.len()).map(|_| None).collect::<Vec<_>>();
+        while let Some(task) = tasks.join_next().await {
+            // Tasks are never aborted, so a join error can only be a panic to propagate.
+            let (index, result) =
+                task.unwrap_or_else(|err| std::panic::resume_unwind(err.into_panic()));
+            results[index] = Some(result);
+        }
+        results.into_iter().flatten().collect()
+    }
+
+    /// Generates variations of a request that differ only in their seed, sending them concurrently.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The request to vary.
+    /// * `count` - Number of variations.
+    /// * `mode` - How seeds are chosen, see [`GenerationRequest::variations`].
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `count` responses in seed order, or the first error.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use getimg::client::Client;
+    /// use getimg::request::{GenerationRequest, SeedMode, TextToImageRequest};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let client = Client::new("your_api_key", "lcm-realistic-vision-v5-1");
+    ///     let request = GenerationRequest::TextToImage(TextToImageRequest {
+    ///         prompt: "Rusty crab on the beach".to_string(),
+    ///         model: client.model.clone(),
+    ///         negative_prompt: None,
+    ///         width: 512,
+    ///         height: 512,
+    ///         steps: 4,
+    ///         output_format: "jpeg".to_string(),
+    ///         seed: Some(512),
+    ///     });
+    ///     let responses = client
+    ///         .send_variations(&request, 4, SeedMode::Sequential)
+    ///         .await
+    ///         .unwrap();
+    ///     assert_eq!(responses.len(), 4);
+    /// }
+    /// ```
+    pub async fn send_variations(
+        &self,
+        request: &GenerationRequest,
+        count: usize,
+        mode: SeedMode,
+    ) -> Result<Vec<ToImageResponse>> {
+        self.send_all(&request.variations(count, mode))
+            .await
+            .into_iter()
+            .collect()
+    }
+}
+
+/// Struct representing an HTTP request to the API, built by [`Client::prepare`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PreparedRequest {
+    /// HTTP method.
+    pub method: String,
+    /// Full URL of the endpoint.
+    pub url: String,
+    /// Headers, by name.
+    pub headers: BTreeMap<String, String>,
+    /// JSON body.
+    pub body: serde_json::Value,
+}
+
+impl PreparedRequest {
+    /// Returns a copy that is safe to show: the API key is masked and base64 image data is
+    /// truncated to its first characters.
+    pub fn redacted(&self) -> Self {
+        let mut redacted = self.clone();
+        if let Some(authorization) = redacted.headers.get_mut("Authorization") {
+            *authorization = "Bearer ***".to_string();
+        }
+        if let serde_json::Value::Object(fields) = &mut redacted.body {
+            for value in fields.values_mut() {
+                if let serde_json::Value::String(text) = value {
+                    if let Some(truncated) = truncate_base64(text) {
+                        *text = truncated;
+                    }
+                }
+            }
+        }
+        redacted
+    }
+
+    /// Returns an equivalent `curl` command, reading the API key from `$GETIMG_API_KEY`.
+    pub fn to_curl(&self) -> String {
+        let mut command = format!("curl -X {} {}", self.method, shell_quote(&self.url));
+        for (name, value) in &self.headers {
+            let header = if name == "Authorization" {
+                "\"Authorization: Bearer $GETIMG_API_KEY\"".to_string()
+            } else {
+                shell_quote(&format!("{}: {}", name, value))
+            };
+            command.push_str(&format!(" \\\n  -H {}", header));
+        }
+        command.push_str(&format!(
+            " \\\n  -d {}",
+            shell_quote(&self.body.to_string())
+        ));
+        command
+    }
+}
+
+/// Number of characters of base64 data kept by [`PreparedRequest::redacted`].
+const BASE64_PREFIX: usize = 32;
+
+/// Truncates long base64 data, returning `None` for other text.
+fn truncate_base64(text: &str) -> Option<String> {
+    let base64 = text.len() > 4 * BASE64_PREFIX
+        && text
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || matches!(byte, b'+' | b'/' | b'='));
+    base64.then(|| {
+        format!(
+            "{}...({} more characters)",
+            &text[..BASE64_PREFIX],
+            text.len() - BASE64_PREFIX
+        )
+    })
+}
+
+/// Quotes a string for POSIX shells.
+fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+/// Capitalizes every word of a header name, e.g. `Content-Type`.
+fn title_case(name: &str) -> String {
+    name.split('-')
+        .map(|word| {
+            let mut chars = word.chars();
+            chars
+                .next()
+                .map(|first| first.to_ascii_uppercase().to_string() + chars.as_str())
+                .unwrap_or_default()
+        })
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Error returned when the API answers a request with an unsuccessful status.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiError {
+    /// HTTP status code of the response.
+    pub status: u16,
+    /// Error message reported by the API.
+    pub message: String,
+}
+
+impl ApiError {
+    /// Builds an error from the status and body of a response.
+    ///
+    /// The message is taken from `{"error": {"message": ...}}` or `{"error": ...}` bodies, and
+    /// falls back to the raw body.
+    ///
+    /// # Arguments
+    ///
+    /// * `status` - HTTP status code of the response.
+    /// * `body` - Body of the response.
+    pub fn new(status: u16, body: &str) -> Self {
+        let json = serde_json::from_str::<serde_json::Value>(body).ok();
+        let error = json.as_ref().and_then(|json| json.get("error"));
+        let message = match error {
+            Some(serde_json::Value::String(message)) => message.clone(),
+            Some(error) => match error.get("message").and_then(|message| message.as_str()) {
+                Some(message) => message.to_string(),
+                None => error.to_string(),
+            },
+            None => body.trim().to_string(),
+        };
+        Self { status, message }
+    }
 }
 
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.message.is_empty() {
+            write!(f, "the API answered with status {}", self.status)
+        } else {
+            write!(
+                f,
+                "the API answered with status {}: {}",
+                self.status, self.message
+            )
+        }
+    }
+}
+
+impl Error for ApiError {}
+
 /// Custom Debug trait implementation for Client struct.
 ///
 /// This implementation hides the API key from being exposed in debug output.
@@ -0,0 +1,553 @@
+//! This module contains the configuration file support of the CLI.
+//!
+//! Settings are read from the user configuration at `~/.config/getimg/config.toml` (or
+//! `$XDG_CONFIG_HOME/getimg/config.toml`, or the file named by `GETIMG_CONFIG`) and from the
+//! nearest project-local `.getimg.toml`. Both files hold named profiles; the selected profile of
+//! the project file takes precedence over the one of the user file, field by field.
+//!
+//! A project file comes with the checked-out repository, so it may not set where the API key
+//! comes from or is sent to, see [`SENSITIVE_SETTINGS`], unless its directory is listed in the
+//! `trusted_projects` of the user file.
+//!
+//! ```toml
+//! default_profile = "work"
+//!
+//! [profiles.work]
+//! api_key_cmd = "pass show getimg/work"
+//! model = "lcm-realistic-vision-v5-1"
+//! team_id = 42
+//!
+//! [profiles.work.output]
+//! template = "{cmd}/{prompt_slug}-{seed}.{ext}"
+//! dir = "renders"
+//!
+//! [profiles.work.cache]
+//! enabled = true
+//! max_mb = 1024
+//!
+//! [profiles.work.t2i]
+//! width = 768
+//! negative_prompt = "blurry, watermark"
+//! ```
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+use crate::cache::Cache;
+
+/// Name of the profile used when none is selected.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// File name of project-local configuration files.
+pub const PROJECT_FILE: &str = ".getimg.toml";
+
+/// Settings ignored in untrusted project files, since they run commands, read secrets or choose
+/// the host the API key is sent to.
+pub const SENSITIVE_SETTINGS: [&str; 6] = [
+    "api_key",
+    "api_key_env",
+    "api_key_file",
+    "api_key_cmd",
+    "base_url",
+    "cache.dir",
+];
+
+/// Struct representing the contents of a configuration file.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    /// Profile used when neither `--profile` nor `GETIMG_PROFILE` is given.
+    pub default_profile: Option<String>,
+    /// Named profiles.
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+    /// Directories whose project file may set [`SENSITIVE_SETTINGS`]. A leading `~/` refers to
+    /// the home directory. Only read from the user file.
+    #[serde(default)]
+    pub trusted_projects: Vec<PathBuf>,
+}
+
+impl ConfigFile {
+    /// Parses the TOML contents of a configuration file.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The TOML document.
+    pub fn parse(text: &str) -> Result<Self> {
+        Ok(toml::from_str(text)?)
+    }
+
+    /// Reads and parses a configuration file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the TOML file.
+    pub fn open(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("invalid configuration in {}", path.display()))
+    }
+
+    /// Returns whether this user file trusts a project file.
+    ///
+    /// # Arguments
+    ///
+    /// * `project` - Path to the project file.
+    pub fn trusts(&self, project: &Path) -> bool {
+        let Some(dir) = project
+            .parent()
+            .and_then(|dir| std::fs::canonicalize(dir).ok())
+        else {
+            return false;
+        };
+        self.trusted_projects
+            .iter()
+            .filter_map(|trusted| std::fs::canonicalize(expand_home(trusted)).ok())
+            .any(|trusted| trusted == dir)
+    }
+
+    /// Removes the [`SENSITIVE_SETTINGS`] of every profile, and the trusted projects.
+    ///
+    /// # Returns
+    ///
+    /// The names of the removed settings, without duplicates.
+    pub fn restrict(&mut self) -> Vec<&'static str> {
+        self.trusted_projects.clear();
+        let mut removed = Vec::new();
+        for profile in self.profiles.values_mut() {
+            let set = [
+                profile.api_key.take().is_some(),
+                profile.api_key_env.take().is_some(),
+                profile.api_key_file.take().is_some(),
+                profile.api_key_cmd.take().is_some(),
+                profile.base_url.take().is_some(),
+                profile.cache.dir.take().is_some(),
+            ];
+            for (name, set) in SENSITIVE_SETTINGS.into_iter().zip(set) {
+                if set && !removed.contains(&name) {
+                    removed.push(name);
+                }
+            }
+        }
+        removed
+    }
+}
+
+/// Struct representing a named set of settings.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    /// API key. Prefer `api_key_env` to keep secrets out of configuration files.
+    pub api_key: Option<String>,
+    /// Name of the environment variable holding the API key.
+    pub api_key_env: Option<String>,
+    /// Path to a file holding the API key. A leading `~/` refers to the home directory.
+    pub api_key_file: Option<PathBuf>,
+    /// Shell command printing the API key, e.g. `pass show getimg`.
+    pub api_key_cmd: Option<String>,
+    /// Base URL of the API.
+    pub base_url: Option<String>,
+    /// Default model.
+    pub model: Option<String>,
+    /// Team id carried by the invisible watermark.
+    pub team_id: Option<u16>,
+    /// Output settings.
+    #[serde(default)]
+    pub output: OutputConfig,
+    /// Response cache settings.
+    #[serde(default)]
+    pub cache: CacheConfig,
+    /// Defaults of the `t2i` subcommand.
+    #[serde(default)]
+    pub t2i: CommandDefaults,
+    /// Defaults of the `i2i` subcommand.
+    #[serde(default)]
+    pub i2i: CommandDefaults,
+    /// Defaults of the `cnet` subcommand.
+    #[serde(default)]
+    pub cnet: CommandDefaults,
+    /// Defaults of the `paint` subcommand.
+    #[serde(default)]
+    pub paint: CommandDefaults,
+    /// Defaults of the `edit` subcommand.
+    #[serde(default)]
+    pub edit: CommandDefaults,
+}
+
+impl Profile {
+    /// Overlays `other` on this profile, keeping the values of `other` where both are set.
+    pub fn merge(self, other: Profile) -> Profile {
+        Profile {
+            api_key: other.api_key.or(self.api_key),
+            api_key_env: other.api_key_env.or(self.api_key_env),
+            api_key_file: other.api_key_file.or(self.api_key_file),
+            api_key_cmd: other.api_key_cmd.or(self.api_key_cmd),
+            base_url: other.base_url.or(self.base_url),
+            model: other.model.or(self.model),
+            team_id: other.team_id.or(self.team_id),
+            output: OutputConfig {
+                template: other.output.template.or(self.output.template),
+                dir: other.output.dir.or(self.output.dir),
+            },
+            cache: CacheConfig {
+                enabled: other.cache.enabled.or(self.cache.enabled),
+                dir: other.cache.dir.or(self.cache.dir),
+                max_mb: other.cache.max_mb.or(self.cache.max_mb),
+                ttl_hours: other.cache.ttl_hours.or(self.cache.ttl_hours),
+            },
+            t2i: self.t2i.merge(other.t2i),
+            i2i: self.i2i.merge(other.i2i),
+            cnet: self.cnet.merge(other.cnet),
+            paint: self.paint.merge(other.paint),
+            edit: self.edit.merge(other.edit),
+        }
+    }
+
+    /// Returns the API key of the profile, trying `api_key_env`, `api_key_file`, `api_key_cmd`
+    /// and `api_key` in order.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the key if one is configured, or an error if a configured key file
+    /// cannot be read or the key command fails.
+    pub fn api_key(&self) -> Result<Option<String>> {
+        if let Some(key) = self
+            .api_key_env
+            .as_ref()
+            .and_then(|name| env::var(name).ok())
+            .filter(|key| !key.trim().is_empty())
+        {
+            return Ok(Some(key.trim().to_string()));
+        }
+        if let Some(path) = &self.api_key_file {
+            return read_key_file(path).map(Some);
+        }
+        if let Some(command) = &self.api_key_cmd {
+            return run_key_command(command).map(Some);
+        }
+        Ok(self.api_key.clone().filter(|key| !key.trim().is_empty()))
+    }
+}
+
+/// Resolves the API key, trying in order the `--api-key` flag, `GETIMG_API_KEY`,
+/// `GETIMG_API_KEY_FILE` and the key sources of the selected profile.
+///
+/// # Arguments
+///
+/// * `flag` - The key given on the command line, if any.
+/// * `config` - The resolved configuration.
+///
+/// # Returns
+///
+/// A `Result` containing the key, or an error listing the ways to provide one.
+pub fn resolve_api_key(flag: Option<String>, config: &Config) -> Result<String> {
+    let key = match flag.filter(|key| !key.trim().is_empty()) {
+        Some(key) => Some(key),
+        None => match env::var("GETIMG_API_KEY")
+            .ok()
+            .filter(|key| !key.trim().is_empty())
+        {
+            Some(key) => Some(key),
+            None => match env::var_os("GETIMG_API_KEY_FILE") {
+                Some(path) => Some(read_key_file(Path::new(&path))?),
+                None => config.profile.api_key()?,
+            },
+        },
+    };
+    key.map(|key| key.trim().to_string()).ok_or_else(|| {
+        let config_file = config
+            .sources
+            .last()
+            .cloned()
+            .or_else(user_path)
+            .map(|path| path.display().to_string())
+            .unwrap_or_else(|| "~/.config/getimg/config.toml".to_string());
+        anyhow!(
+            concat!(
+                "no GetImg API key found. Provide one with any of:\n",
+                "  --api-key <KEY>\n",
+                "  export GETIMG_API_KEY=<KEY>\n",
+                "  export GETIMG_API_KEY_FILE=<path to a file holding the key>\n",
+                "  api_key_env, api_key_file or api_key_cmd (e.g. \"pass show getimg\") ",
+                "in [profiles.{}] of {}\n",
+                "API keys can be created at https://dashboard.getimg.ai/api-keys"
+            ),
+            config.profile_name,
+            config_file
+        )
+    })
+}
+
+/// Replaces a leading `~/` of a path with the home directory.
+fn expand_home(path: &Path) -> PathBuf {
+    match path.strip_prefix("~") {
+        Ok(rest) => dirs::home_dir()
+            .map(|home| home.join(rest))
+            .unwrap_or_else(|| path.to_path_buf()),
+        Err(_) => path.to_path_buf(),
+    }
+}
+
+fn read_key_file(path: &Path) -> Result<String> {
+    let path = expand_home(path);
+    let key = std::fs::read_to_string(&path)
+        .with_context(|| format!("failed to read the API key file {}", path.display()))?;
+    let key = key.trim();
+    if key.is_empty() {
+        bail!("the API key file {} is empty", path.display());
+    }
+    Ok(key.to_string())
+}
+
+fn run_key_command(command: &str) -> Result<String> {
+    let output = if cfg!(windows) {
+        Command::new("cmd").args(["/C", command]).output()
+    } else {
+        Command::new("sh").args(["-c", command]).output()
+    }
+    .with_context(|| format!("failed to run the API key command `{}`", command))?;
+    if !output.status.success() {
+        bail!(
+            "the API key command `{}` failed with {}: {}",
+            command,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    // Password managers commonly print the secret on the first line followed by metadata.
+    let stdout = String::from_utf8(output.stdout)?;
+    match stdout.lines().next().map(str::trim) {
+        Some(key) if !key.is_empty() => Ok(key.to_string()),
+        _ => bail!("the API key command `{}` printed nothing", command),
+    }
+}
+
+/// Struct representing the output settings of a profile.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OutputConfig {
+    /// Output path template, see [`crate::output`].
+    pub template: Option<String>,
+    /// Directory outputs are written to.
+    pub dir: Option<PathBuf>,
+}
+
+/// Struct representing the response cache settings of a profile, see [`crate::cache`].
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CacheConfig {
+    /// Whether responses are cached without `--cache`.
+    pub enabled: Option<bool>,
+    /// Directory of the cache. Defaults to `GETIMG_CACHE_DIR` or `getimg/responses` in the
+    /// user cache directory.
+    pub dir: Option<PathBuf>,
+    /// Size limit of the cache in megabytes.
+    pub max_mb: Option<u64>,
+    /// Time to live of cached responses in hours, 0 to keep them until evicted.
+    pub ttl_hours: Option<u64>,
+}
+
+impl CacheConfig {
+    /// Returns the cache described by these settings.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the cache, or an error if no directory is configured and there is
+    /// no user cache directory.
+    pub fn cache(&self) -> Result<Cache> {
+        let dir = self
+            .dir
+            .clone()
+            .or_else(|| env::var_os("GETIMG_CACHE_DIR").map(Into::into))
+            .or_else(|| dirs::cache_dir().map(|dir| dir.join("getimg").join("responses")))
+            .ok_or_else(|| {
+                anyhow!("no cache directory found, set dir in [profiles.<name>.cache]")
+            })?;
+        let mut cache = Cache::new(dir);
+        if let Some(max_mb) = self.max_mb {
+            cache.max_bytes = max_mb * 1024 * 1024;
+        }
+        match self.ttl_hours {
+            Some(0) => cache.ttl = None,
+            Some(hours) => cache.ttl = Some(Duration::from_secs(hours * 60 * 60)),
+            None => {}
+        }
+        Ok(cache)
+    }
+}
+
+/// Struct representing the defaults of a generation subcommand.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CommandDefaults {
+    /// Text input that will not guide the image generation.
+    pub negative_prompt: Option<String>,
+    /// Width of the image.
+    pub width: Option<usize>,
+    /// Height of the image.
+    pub height: Option<usize>,
+    /// Number of steps.
+    pub steps: Option<usize>,
+    /// Guidance scale.
+    pub guidance: Option<f64>,
+    /// Scheduler name.
+    pub scheduler: Option<String>,
+    /// Strength of the input image or conditioning.
+    pub strength: Option<f64>,
+    /// Image guidance of instruction-based editing.
+    pub image_guidance: Option<f64>,
+    /// Output format.
+    pub output_format: Option<String>,
+    /// ControlNet conditioning type.
+    pub net: Option<String>,
+}
+
+impl CommandDefaults {
+    /// Overlays `other` on these defaults, keeping the values of `other` where both are set.
+    pub fn merge(self, other: CommandDefaults) -> CommandDefaults {
+        CommandDefaults {
+            negative_prompt: other.negative_prompt.or(self.negative_prompt),
+            width: other.width.or(self.width),
+            height: other.height.or(self.height),
+            steps: other.steps.or(self.steps),
+            guidance: other.guidance.or(self.guidance),
+            scheduler: other.scheduler.or(self.scheduler),
+            strength: other.strength.or(self.strength),
+            image_guidance: other.image_guidance.or(self.image_guidance),
+            output_format: other.output_format.or(self.output_format),
+            net: other.net.or(self.net),
+        }
+    }
+}
+
+/// Struct representing the resolved configuration.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Config {
+    /// Name of the selected profile.
+    pub profile_name: String,
+    /// The selected profile, merged from the user and project files.
+    pub profile: Profile,
+    /// Configuration files that were read, user file first.
+    pub sources: Vec<PathBuf>,
+}
+
+impl Config {
+    /// Loads the user and project configuration files and selects a profile.
+    ///
+    /// # Arguments
+    ///
+    /// * `profile` - Profile given with `--profile`. Falls back to `GETIMG_PROFILE`, then to the
+    ///   `default_profile` of the project and user files, then to `default`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the configuration, or an error if a file is malformed or an
+    /// explicitly selected profile does not exist.
+    pub fn load(profile: Option<&str>) -> Result<Self> {
+        let user =
+            user_path().filter(|path| path.is_file() || env::var_os("GETIMG_CONFIG").is_some());
+        let project = project_path(&env::current_dir()?);
+        let profile = profile
+            .map(str::to_string)
+            .or_else(|| env::var("GETIMG_PROFILE").ok());
+
+        let mut files = [user, project.clone()]
+            .into_iter()
+            .flatten()
+            .map(|path| Ok((ConfigFile::open(&path)?, path)))
+            .collect::<Result<Vec<_>>>()?;
+        if let Some(project) = project {
+            let trusted = files.len() > 1 && files[0].0.trusts(&project);
+            let (file, _) = files.last_mut().expect("the project file was read");
+            if !trusted {
+                let removed = file.restrict();
+                if !removed.is_empty() {
+                    eprintln!(
+                        "Ignoring {} in {}, add its directory to trusted_projects in the user \
+                         configuration to use them.",
+                        removed.join(", "),
+                        project.display()
+                    );
+                }
+            }
+        }
+        let mut config = Self::from_files(
+            &files
+                .iter()
+                .map(|(file, _)| file.clone())
+                .collect::<Vec<_>>(),
+            profile.as_deref(),
+        )
+        .map_err(|err| {
+            let paths = files
+                .iter()
+                .map(|(_, path)| path.display().to_string())
+                .collect::<Vec<_>>();
+            anyhow!("{} (searched: {})", err, paths.join(", "))
+        })?;
+        config.sources = files.into_iter().map(|(_, path)| path).collect();
+        Ok(config)
+    }
+
+    /// Selects and merges a profile from parsed configuration files.
+    ///
+    /// # Arguments
+    ///
+    /// * `files` - The configuration files, lowest precedence first.
+    /// * `profile` - The explicitly selected profile, if any.
+    pub fn from_files(files: &[ConfigFile], profile: Option<&str>) -> Result<Self> {
+        let explicit = profile.is_some();
+        let profile_name = profile
+            .map(str::to_string)
+            .or_else(|| {
+                files
+                    .iter()
+                    .rev()
+                    .find_map(|file| file.default_profile.clone())
+            })
+            .unwrap_or_else(|| DEFAULT_PROFILE.to_string());
+
+        let found = files
+            .iter()
+            .filter_map(|file| file.profiles.get(&profile_name).cloned())
+            .collect::<Vec<_>>();
+        if found.is_empty() && (explicit || profile_name != DEFAULT_PROFILE) {
+            bail!("profile `{}` is not defined", profile_name);
+        }
+
+        Ok(Self {
+            profile: found.into_iter().fold(Profile::default(), Profile::merge),
+            profile_name,
+            sources: Vec::new(),
+        })
+    }
+}
+
+/// Returns the path of the user configuration file.
+pub fn user_path() -> Option<PathBuf> {
+    if let Some(path) = env::var_os("GETIMG_CONFIG") {
+        return Some(path.into());
+    }
+    let config_dir = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| dirs::home_dir().map(|home| home.join(".config")))?;
+    Some(config_dir.join("getimg").join("config.toml"))
+}
+
+/// Returns the nearest project configuration file in `start` or its ancestors.
+///
+/// # Arguments
+///
+/// * `start` - The directory to start searching from.
+pub fn project_path(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .map(|dir| dir.join(PROJECT_FILE))
+        .find(|path| path.is_file())
+}
@@ -0,0 +1,77 @@
+//! This module contains default generation parameters for the model families served by the GetImg API.
+
+/// Struct representing the parameters used when a request leaves them unspecified.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelDefaults {
+    /// Width of generated images.
+    pub width: usize,
+    /// Height of generated images.
+    pub height: usize,
+    /// Number of denoising steps.
+    pub steps: usize,
+    /// Guidance scale.
+    pub guidance: f64,
+    /// Scheduler name.
+    pub scheduler: &'static str,
+}
+
+impl ModelDefaults {
+    /// Returns the defaults suited to a model.
+    ///
+    /// Latent consistency models (`lcm-*`) need only a few steps, and SDXL-based models
+    /// (names containing `xl`) were trained at 1024x1024 rather than 512x512.
+    ///
+    /// # Arguments
+    ///
+    /// * `model` - The model name, e.g. `lcm-realistic-vision-v5-1`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use getimg::defaults::ModelDefaults;
+    ///
+    /// let defaults = ModelDefaults::for_model("lcm-realistic-vision-v5-1");
+    /// assert_eq!((defaults.width, defaults.height, defaults.steps), (512, 512, 4));
+    /// ```
+    pub fn for_model(model: &str) -> Self {
+        let model = model.to_lowercase();
+        let side = if model.contains("xl") { 1024 } else { 512 };
+        let (steps, guidance) = if model.starts_with("lcm-") {
+            (4, 1.0)
+        } else if model.contains("xl") {
+            (30, 7.5)
+        } else {
+            (25, 7.5)
+        };
+        Self {
+            width: side,
+            height: side,
+            steps,
+            guidance,
+            scheduler: "euler",
+        }
+    }
+}
+
+/// Output format requested when none is given, matching the API default.
+pub const OUTPUT_FORMAT: &str = "jpeg";
+
+/// Strength of image-to-image generation when none is given.
+pub const IMAGE_TO_IMAGE_STRENGTH: f64 = 0.5;
+
+/// Strength of ControlNet conditioning and repainting when none is given.
+pub const STRENGTH: f64 = 1.0;
+
+/// Image guidance of instruction-based editing when none is given.
+pub const IMAGE_GUIDANCE: f64 = 1.5;
+
+/// Largest seed accepted by the API.
+pub const MAX_SEED: usize = 2_147_483_647;
+
+/// Schedulers known to the API, suggested by shell completions and help. Other values are passed
+/// through to the API, which validates them.
+pub const SCHEDULERS: [&str; 6] = ["euler", "euler_a", "lms", "ddim", "dpmsolver++", "pndm"];
+
+/// Output formats known to the API, suggested by shell completions and help. Other values are
+/// passed through to the API, which validates them.
+pub const OUTPUT_FORMATS: [&str; 2] = ["jpeg", "png"];
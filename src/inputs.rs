@@ -0,0 +1,175 @@
+//! This module contains folder and glob inputs of the image-based commands.
+//!
+//! The `--image` of `i2i`, `edit`, `cnet` and `paint` may name a directory, whose images are
+//! collected recursively, or a glob pattern such as `sketches/**/*.png`. Every image is then
+//! processed with the same options, and its output is saved at the same relative path under the
+//! output directory of the run:
+//!
+//! ```text
+//! sketches/cabin.png          ->  i2i-20240401-123000/cabin.jpg
+//! sketches/coast/harbor.jpg   ->  i2i-20240401-123000/coast/harbor.jpg
+//! ```
+//!
+//! Masks of `paint` are paired with images by name: `cabin_mask.png` or `cabin.mask.png` next to
+//! `cabin.png`, or `cabin.png` at the same relative path when `--mask-image` names a directory.
+//! Mask files are never processed as inputs of `paint` themselves; the other commands process
+//! every image.
+
+use anyhow::{bail, Context, Result};
+use std::path::{Component, Path, PathBuf};
+
+use crate::watch::{is_image, IMAGE_EXTENSIONS};
+
+/// Suffixes of the file stems of masks stored next to their image.
+pub const MASK_SUFFIXES: [&str; 2] = ["_mask", ".mask"];
+
+/// Struct representing one image of a folder or glob input.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Input {
+    /// Path to the image.
+    pub path: PathBuf,
+    /// Path of the image relative to the directory or to the fixed part of the glob pattern.
+    pub relative: PathBuf,
+}
+
+/// Returns whether an `--image` value is a glob pattern rather than a path.
+///
+/// # Arguments
+///
+/// * `image` - The value of `--image`.
+pub fn is_pattern(image: &str) -> bool {
+    image.contains(['*', '?', '['])
+}
+
+/// Returns whether a path names a mask stored next to its image, e.g. `cabin_mask.png`.
+///
+/// # Arguments
+///
+/// * `path` - The path.
+pub fn is_mask(path: &Path) -> bool {
+    path.file_stem().is_some_and(|stem| {
+        let stem = stem.to_string_lossy();
+        MASK_SUFFIXES.iter().any(|suffix| stem.ends_with(suffix))
+    })
+}
+
+/// Lists the images of a folder or glob input, in path order.
+///
+/// # Arguments
+///
+/// * `image` - The value of `--image`.
+/// * `skip_masks` - Whether to leave out masks stored next to their image, see [`is_mask`].
+///
+/// # Returns
+///
+/// A `Result` containing the images, `None` if `image` names a single file or stdin, or an error
+/// if nothing matches.
+pub fn expand(image: &str, skip_masks: bool) -> Result<Option<Vec<Input>>> {
+    let path = Path::new(image);
+    let mut inputs = if path.is_dir() {
+        let mut files = Vec::new();
+        collect(path, skip_masks, &mut files)?;
+        files
+            .into_iter()
+            .map(|file| Input {
+                relative: file.strip_prefix(path).unwrap_or(&file).to_path_buf(),
+                path: file,
+            })
+            .collect::<Vec<_>>()
+    } else if is_pattern(image) && !path.exists() {
+        let root = fixed_prefix(path);
+        let mut inputs = Vec::new();
+        for file in glob::glob(image).with_context(|| format!("invalid pattern {}", image))? {
+            let file = file?;
+            if file.is_file() && is_image(&file) && !(skip_masks && is_mask(&file)) {
+                inputs.push(Input {
+                    relative: file
+                        .strip_prefix(&root)
+                        .map(Path::to_path_buf)
+                        .unwrap_or_else(|_| file.file_name().unwrap_or_default().into()),
+                    path: file,
+                });
+            }
+        }
+        inputs
+    } else {
+        return Ok(None);
+    };
+    if inputs.is_empty() {
+        bail!("no images found in {}", image);
+    }
+    inputs.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(Some(inputs))
+}
+
+/// Returns the mask paired with an image.
+///
+/// # Arguments
+///
+/// * `input` - The image.
+/// * `masks` - Directory mirroring the inputs with one mask per image, or `None` to look for
+///   masks with one of [`MASK_SUFFIXES`] next to the image.
+///
+/// # Returns
+///
+/// The path of the first existing candidate, if any.
+pub fn mask_for(input: &Input, masks: Option<&Path>) -> Option<PathBuf> {
+    let stem = input.path.file_stem()?.to_string_lossy().into_owned();
+    let candidates = match masks {
+        Some(dir) => {
+            let dir = dir.join(input.relative.parent().unwrap_or(Path::new("")));
+            IMAGE_EXTENSIONS
+                .iter()
+                .map(|extension| dir.join(format!("{}.{}", stem, extension)))
+                .collect::<Vec<_>>()
+        }
+        None => MASK_SUFFIXES
+            .iter()
+            .flat_map(|suffix| {
+                let stem = &stem;
+                IMAGE_EXTENSIONS.iter().map(move |extension| {
+                    input
+                        .path
+                        .with_file_name(format!("{}{}.{}", stem, suffix, extension))
+                })
+            })
+            .collect(),
+    };
+    candidates.into_iter().find(|candidate| candidate.is_file())
+}
+
+/// Collects the images of a directory and its subdirectories, skipping hidden entries, and masks
+/// if `skip_masks` is set.
+fn collect(dir: &Path, skip_masks: bool, files: &mut Vec<PathBuf>) -> Result<()> {
+    let entries =
+        std::fs::read_dir(dir).with_context(|| format!("failed to read {}", dir.display()))?;
+    for entry in entries {
+        let path = entry?.path();
+        let hidden = path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+        if hidden {
+            continue;
+        }
+        if path.is_dir() {
+            collect(&path, skip_masks, files)?;
+        } else if is_image(&path) && !(skip_masks && is_mask(&path)) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Returns the leading components of a glob pattern that contain no wildcard.
+fn fixed_prefix(pattern: &Path) -> PathBuf {
+    let mut prefix = PathBuf::new();
+    for component in pattern.components() {
+        if let Component::Normal(part) = component {
+            if is_pattern(&part.to_string_lossy()) {
+                break;
+            }
+        }
+        prefix.push(component);
+    }
+    prefix
+}
@@ -1,10 +1,40 @@
 #![cfg_attr(docsrs, feature(doc_auto_cfg))]
 #![doc = include_str!("../README.md")]
 
+pub mod cache;
 pub mod client;
+pub mod defaults;
+pub mod metadata;
+pub mod output;
+pub mod provenance;
 pub mod request;
 pub mod response;
+pub mod sanitize;
 pub mod utils;
 
+#[cfg(feature = "cli")]
+pub mod batch;
 #[cfg(feature = "cli")]
 pub mod cli;
+#[cfg(feature = "cli")]
+pub mod config;
+#[cfg(feature = "cli")]
+pub mod inputs;
+#[cfg(feature = "image")]
+pub mod mask;
+#[cfg(feature = "cli")]
+pub mod pipeline;
+#[cfg(feature = "image")]
+pub mod preprocess;
+#[cfg(feature = "cli")]
+pub mod preview;
+#[cfg(feature = "cli")]
+pub mod repl;
+#[cfg(feature = "cli")]
+pub mod report;
+#[cfg(feature = "image")]
+pub mod sweep;
+#[cfg(feature = "cli")]
+pub mod watch;
+#[cfg(feature = "image")]
+pub mod watermark;
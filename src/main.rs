@@ -4,128 +4,355 @@ use anyhow::Result;
 ///
 /// It parses command-line arguments using the `clap` crate, configures the client based on
 /// the provided command-line options, and performs an operation using the specified subcommand.
+/// With `--json` a single JSON object describing the run is printed, and the process exits with
+/// the code of the error category on failure. With `--dry-run` the requests are printed instead of
+/// being sent.
 #[tokio::main]
 async fn main() -> Result<()> {
     #[cfg(feature = "cli")]
     {
         use clap::Parser;
         use getimg::cli::{Cli, Command};
-        use getimg::client::Client;
-        use getimg::utils::{load_and_encode_image, save_image};
-        use std::env;
+        use getimg::output::STDOUT_TEMPLATE;
+        use getimg::report::{self, Report};
 
         let args: Cli = Cli::parse();
+        let json = args.json;
+        let dry_run = args.dry_run;
+        report::set_dry_run(dry_run);
+        getimg::preview::set_preview(args.preview);
+        // Keep stdout to the printed requests, except in the interactive session.
+        report::set_quiet(
+            json || args.quiet || (dry_run.is_some() && !matches!(args.cmd, Command::Repl(_))),
+        );
 
-        let api_key = if args.api_key.is_none() {
-            env::var("GETIMG_API_KEY").unwrap_or_default().to_owned()
-        } else {
-            args.api_key.unwrap().to_owned()
-        };
-
-        let model = if args.model.is_none() {
-            env::var("GETIMG_MODEL")
-                .unwrap_or("lcm-realistic-vision-v5-1".to_string())
-                .to_owned()
-        } else {
-            args.model.unwrap().to_owned()
-        };
-
-        let mut getimg_client = Client::new(&api_key, &model);
-
-        match args.cmd {
-            Command::Edit(cmd) => {
-                println!("Generating edited image...");
-                let result = getimg_client
-                    .generate_edited_image(
-                        &cmd.prompt,
-                        Some(&cmd.negative_prompt),
-                        &load_and_encode_image(&cmd.image)?,
-                        cmd.image_guidance,
-                        cmd.steps,
-                        cmd.guidance,
-                        cmd.seed.try_into().unwrap(),
-                        &cmd.scheduler,
-                        &cmd.output_format,
-                    )
-                    .await?;
-                save_image(&result.image, "edited_image.png")?;
-                println!("Edited image generated and stored successfully.");
+        let mut report = Report::new(args.cmd.name());
+        if let Err(err) = run(args, &mut report).await {
+            report.fail(&err);
+            if !json {
+                eprintln!("Error: {:?}", err);
             }
-            Command::Repaint(cmd) => {
-                println!("Repainting image...");
-                let result = getimg_client
-                    .generate_repainted_image(
-                        &cmd.prompt,
-                        Some(&cmd.negative_prompt),
-                        &load_and_encode_image(&cmd.image)?,
-                        &load_and_encode_image(&cmd.mask_image)?,
-                        Some(cmd.strength),
-                        cmd.width,
-                        cmd.height,
-                        cmd.steps,
-                        cmd.guidance,
-                        cmd.seed.try_into().unwrap(),
-                        &cmd.scheduler,
-                        &cmd.output_format,
-                    )
-                    .await?;
-                save_image(&result.image, "edited_image.png")?;
-                println!("Image repainted and stored successfully.");
-            }
-            Command::TextToImage(cmd) => {
-                println!("Generating image from text...");
-                let result = getimg_client
-                    .generate_image_from_text(
-                        &cmd.prompt,
-                        cmd.width,
-                        cmd.height,
-                        cmd.steps,
-                        &cmd.output_format,
-                        Some(&cmd.negative_prompt),
-                        Some(cmd.seed),
-                    )
-                    .await?;
-                save_image(&result.image, "t2i.png")?;
-                println!("Edited image generated and stored successfully.");
+        }
+        if json {
+            println!("{}", serde_json::to_string(&report)?);
+        } else if let Some(format) = dry_run {
+            let requests = report
+                .requests
+                .iter()
+                .map(|request| format.render(request))
+                .collect::<Vec<_>>();
+            if !requests.is_empty() {
+                println!("{}", requests.join("\n\n"));
             }
-            Command::ImageToImage(cmd) => {
-                println!("Generating image from image...");
-                let result = getimg_client
-                    .generate_image_from_image(
-                        &cmd.prompt,
-                        &load_and_encode_image(&cmd.image)?,
-                        cmd.steps,
-                        cmd.seed,
-                        &cmd.output_format,
-                        Some(&cmd.negative_prompt),
-                        Some(cmd.strength),
-                    )
-                    .await?;
-                save_image(&result.image, "i2i.png")?;
-                println!("Edited image generated and stored successfully.");
+        } else if report::quiet() {
+            for path in report
+                .outputs
+                .iter()
+                .filter_map(|output| output.path.as_ref())
+                .filter(|path| path.as_os_str() != STDOUT_TEMPLATE)
+            {
+                println!("{}", path.display());
             }
-            Command::ControlNet(cmd) => {
-                println!("Generating image using ControlNet...");
-                let result = getimg_client
-                    .generate_image_using_controlnet(
-                        &cmd.net,
-                        &cmd.prompt,
-                        &cmd.negative_prompt,
-                        &load_and_encode_image(&cmd.image)?,
-                        cmd.strength,
-                        cmd.width,
-                        cmd.height,
-                        cmd.steps,
-                        cmd.guidance,
-                        cmd.seed,
-                        &cmd.scheduler,
-                        &cmd.output_format,
-                    )
-                    .await?;
-                save_image(&result.image, "cnet.png")?;
-                println!("Edited image generated and stored successfully.");
+        }
+        if report.exit_code() != 0 {
+            std::process::exit(report.exit_code());
+        }
+    }
+    Ok(())
+}
+
+/// Runs the parsed command, recording its outputs in the report.
+#[cfg(feature = "cli")]
+async fn run(args: getimg::cli::Cli, report: &mut getimg::report::Report) -> Result<()> {
+    use anyhow::{anyhow, bail, Context};
+    use getimg::cli::{Command, InputRun, Postprocess};
+    use getimg::client::Client;
+    use getimg::config::{resolve_api_key, Config};
+    use getimg::output::{OutputSettings, DEFAULT_TEMPLATE};
+    use getimg::report::{self, print_status, ErrorKind, OutputRecord};
+    use std::env;
+    use std::time::Instant;
+
+    // Completions and man pages are written without reading the configuration.
+    match &args.cmd {
+        Command::Completions(_) if args.json => {
+            bail!("--json cannot be combined with completions, which prints the script to stdout")
+        }
+        Command::Completions(cmd) => return cmd.run(),
+        Command::Man(cmd) => return cmd.run(report),
+        Command::View(_) if args.json => {
+            bail!("--json cannot be combined with view, which draws the image on stdout")
+        }
+        Command::View(cmd) => return cmd.run(report),
+        _ => {}
+    }
+
+    let config =
+        Config::load(args.profile.as_deref()).map_err(|err| ErrorKind::Config.wrap(err))?;
+
+    let api_key = if args.cmd.uses_api() {
+        match resolve_api_key(args.api_key, &config) {
+            Ok(api_key) => api_key,
+            // Printed requests show a redacted key, so none is needed.
+            Err(_) if args.dry_run.is_some() => String::new(),
+            Err(err) => return Err(ErrorKind::Auth.wrap(err)),
+        }
+    } else {
+        String::new()
+    };
+
+    let profile = config.profile;
+
+    let model = args
+        .model
+        .or_else(|| env::var("GETIMG_MODEL").ok())
+        .or(profile.model.clone())
+        .unwrap_or("lcm-realistic-vision-v5-1".to_string());
+
+    // Commands reading images never watermark, so only the others depend on the team id.
+    let watermark = !args.no_watermark
+        && !matches!(
+            args.cmd,
+            Command::Inspect(_) | Command::Cache(_) | Command::Watermark(_)
+        );
+    let team_id = match args.team_id {
+        Some(team_id) => team_id,
+        None => match env::var("GETIMG_TEAM_ID") {
+            Ok(team_id) if watermark => team_id.trim().parse().with_context(|| {
+                format!(
+                    "invalid GETIMG_TEAM_ID `{}`, expected a number from 0 to 65535",
+                    team_id
+                )
+            })?,
+            _ => profile.team_id.unwrap_or(0),
+        },
+    };
+
+    let postprocess = Postprocess {
+        metadata: !args.no_metadata,
+        watermark,
+        team_id,
+        output: OutputSettings {
+            template: args
+                .output
+                .or_else(|| env::var("GETIMG_OUTPUT").ok())
+                .or(profile.output.template.clone())
+                .unwrap_or_else(|| DEFAULT_TEMPLATE.to_string()),
+            dir: args
+                .output_dir
+                .or_else(|| env::var_os("GETIMG_OUTPUT_DIR").map(Into::into))
+                .or(profile.output.dir.clone()),
+        },
+    };
+
+    if postprocess.output.is_stdout() {
+        if args.json {
+            bail!("--json cannot be combined with --output -, which writes the image to stdout");
+        }
+        if args.count > 1
+            || matches!(
+                args.cmd,
+                Command::Batch(_) | Command::Repl(_) | Command::Pipeline(_) | Command::Watch(_)
+            )
+        {
+            bail!(
+                "--output - writes a single image to stdout, save several images to files instead"
+            );
+        }
+        // Progress messages would be mixed with the image bytes.
+        report::set_quiet(true);
+    }
+
+    let mut getimg_client = Client::new(&api_key, &model);
+    let cache = args.cache
+        || env::var("GETIMG_CACHE").is_ok_and(|cache| matches!(cache.as_str(), "1" | "true"))
+        || profile.cache.enabled.unwrap_or(false);
+    if cache && !args.no_cache && args.cmd.chooses_seeds(args.count as usize, args.seeds) {
+        getimg_client = getimg_client.with_cache(Some(profile.cache.cache()?));
+    }
+    if let Some(base_url) = env::var("GETIMG_BASE_URL")
+        .ok()
+        .or(profile.base_url.clone())
+    {
+        getimg_client = getimg_client.with_base_url(base_url.trim_end_matches('/'));
+    }
+
+    if let Some((cmd, inputs)) = args.cmd.inputs()? {
+        return InputRun {
+            cmd,
+            inputs,
+            count: args.count as usize,
+            seeds: args.seeds,
+        }
+        .run(getimg_client, &model, profile, postprocess, report)
+        .await;
+    }
+
+    let (request, message) = match args.cmd {
+        Command::Edit(mut cmd) => {
+            cmd.read_prompt()?;
+            cmd.apply_defaults(&profile.edit);
+            print_status("Generating edited image...");
+            (
+                cmd.request()?,
+                "Edited image generated and stored successfully.",
+            )
+        }
+        Command::Repaint(mut cmd) => {
+            cmd.read_prompt()?;
+            cmd.apply_defaults(&profile.paint);
+            print_status("Repainting image...");
+            (cmd.request()?, "Image repainted and stored successfully.")
+        }
+        Command::TextToImage(mut cmd) => {
+            cmd.read_prompt()?;
+            cmd.apply_defaults(&profile.t2i);
+            print_status("Generating image from text...");
+            (
+                cmd.request(&model),
+                "Image generated and stored successfully.",
+            )
+        }
+        Command::ImageToImage(mut cmd) => {
+            cmd.read_prompt()?;
+            cmd.apply_defaults(&profile.i2i);
+            print_status("Generating image from image...");
+            (
+                cmd.request(&model)?,
+                "Image generated and stored successfully.",
+            )
+        }
+        Command::ControlNet(mut cmd) => {
+            cmd.read_prompt()?;
+            cmd.apply_defaults(&profile.cnet);
+            print_status("Generating image using ControlNet...");
+            (cmd.request()?, "Image generated and stored successfully.")
+        }
+        Command::Inspect(cmd) => return cmd.run(args.json, report),
+        Command::Cache(cmd) => return cmd.run(&profile.cache.cache()?, args.json, report),
+        Command::Watermark(cmd) => return cmd.run(args.json, report),
+        Command::Batch(_) if args.count > 1 => {
+            bail!("--count is not supported by batch, add one job per variation instead")
+        }
+        Command::Sweep(_) if args.count > 1 => {
+            bail!("--count is not supported by sweep, sweep the seed instead")
+        }
+        Command::Sweep(cmd) => {
+            return cmd
+                .run(getimg_client, &model, profile, postprocess, report)
+                .await
+        }
+        Command::Pipeline(_) if args.count > 1 => {
+            bail!("--count is not supported by pipeline, add one step per variation instead")
+        }
+        Command::Pipeline(cmd) => {
+            return cmd
+                .run(getimg_client, &model, profile, postprocess, report)
+                .await
+        }
+        Command::Repl(_) if args.count > 1 => {
+            bail!("--count is not supported by repl, set a seed and generate again instead")
+        }
+        Command::Repl(cmd) => {
+            return cmd
+                .run(getimg_client, &model, profile, postprocess, report)
+                .await
+        }
+        Command::Watch(_) if args.count > 1 => {
+            bail!("--count is not supported by watch, which generates one image per input")
+        }
+        Command::Watch(cmd) => {
+            return cmd
+                .run(getimg_client, &model, profile, postprocess, report)
+                .await
+        }
+        Command::Batch(cmd) => {
+            return cmd
+                .run(getimg_client, &model, profile, postprocess, report)
+                .await
+        }
+        Command::Completions(_) | Command::Man(_) | Command::View(_) => {
+            unreachable!("handled above")
+        }
+        Command::Rerun(cmd) => {
+            print_status(format!("Regenerating image from {}...", cmd.file));
+            (cmd.request()?, "Image regenerated and stored successfully.")
+        }
+    };
+    report.request(&request);
+
+    let count = args.count as usize;
+    let requests = if count > 1 {
+        let requests = request.variations(count, args.seeds);
+        let seeds = requests
+            .iter()
+            .filter_map(|request| request.seed())
+            .map(|seed| seed.to_string())
+            .collect::<Vec<_>>();
+        print_status(format!(
+            "Generating {} variations with seeds {}...",
+            count,
+            seeds.join(", ")
+        ));
+        requests
+    } else {
+        vec![request]
+    };
+    if report::dry_run().is_some() {
+        for request in &requests {
+            report.prepare(&getimg_client, request)?;
+        }
+        return Ok(());
+    }
+
+    let started = Instant::now();
+    let results = getimg_client.send_all(&requests).await;
+    report.latency_ms = Some(started.elapsed().as_millis() as u64);
+    let mut errors = Vec::new();
+    for (index, (request, result)) in requests.iter().zip(results).enumerate() {
+        let result = match result {
+            Ok(result) => result,
+            Err(err) if count > 1 => {
+                eprintln!("Variation {} failed: {:#}", index, err);
+                report.output(OutputRecord {
+                    index,
+                    seed: request.seed(),
+                    error: Some(format!("{:#}", err)),
+                    ..OutputRecord::default()
+                });
+                errors.push(err);
+                continue;
             }
+            Err(err) => return Err(err),
+        };
+        let saved = postprocess.save(request, &result, None, index)?;
+        report.output(OutputRecord {
+            index,
+            path: Some(saved.path),
+            seed: result.seed.or(request.seed()),
+            cost: result.cost,
+            error: None,
+        });
+        if let Some(payload) = saved.payload {
+            print_status(format!(
+                "Watermarked with team {} and generation {:08x}.",
+                payload.team_id, payload.generation_id
+            ));
         }
     }
+    if errors.len() == count {
+        // Every variation failed, most likely for the same reason, so its category is kept.
+        return Err(errors
+            .remove(0)
+            .context(format!("all {} variations failed", count)));
+    }
+    if !errors.is_empty() {
+        return Err(ErrorKind::Incomplete.wrap(anyhow!(
+            "{} of {} variations failed",
+            errors.len(),
+            count
+        )));
+    }
+    print_status(message);
     Ok(())
 }
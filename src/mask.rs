@@ -0,0 +1,313 @@
+//! This module contains helpers for building mask images used by the repainting endpoint.
+//!
+//! White pixels mark the areas to be repainted and black pixels the areas to be kept.
+//! Shapes are combined additively on a single grayscale canvas, which can then be refined
+//! with the morphological and thresholding operations below.
+
+use crate::preprocess;
+use anyhow::{bail, Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use image::{DynamicImage, GrayImage, ImageFormat, Luma};
+use std::io::Cursor;
+
+/// Pixel value of areas to be repainted.
+pub const PAINT: u8 = 255;
+
+/// Pixel value of areas to be kept.
+pub const KEEP: u8 = 0;
+
+/// An axis-aligned rectangle in pixel coordinates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    /// Left edge of the rectangle.
+    pub x: u32,
+    /// Top edge of the rectangle.
+    pub y: u32,
+    /// Width of the rectangle.
+    pub width: u32,
+    /// Height of the rectangle.
+    pub height: u32,
+}
+
+impl std::str::FromStr for Region {
+    type Err = anyhow::Error;
+
+    /// Parses a region from an `x,y,w,h` string.
+    fn from_str(s: &str) -> Result<Self> {
+        let values = s
+            .split(',')
+            .map(|value| value.trim().parse::<u32>())
+            .collect::<Result<Vec<_>, _>>()?;
+        match values[..] {
+            [x, y, width, height] => Ok(Self {
+                x,
+                y,
+                width,
+                height,
+            }),
+            _ => bail!("expected a region as x,y,w,h, got `{}`", s),
+        }
+    }
+}
+
+/// A grayscale mask image.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mask {
+    /// The underlying grayscale buffer.
+    pub image: GrayImage,
+}
+
+impl Mask {
+    /// Creates a mask of the given size where every pixel is kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - Width of the mask in pixels.
+    /// * `height` - Height of the mask in pixels.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use getimg::mask::Mask;
+    ///
+    /// let mask = Mask::new(512, 512)
+    ///     .rectangle(128, 128, 256, 256)
+    ///     .grow(8)
+    ///     .feather(4.0);
+    /// ```
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            image: GrayImage::from_pixel(width, height, Luma([KEEP])),
+        }
+    }
+
+    /// Loads a mask from an encoded image, applying its EXIF orientation and converting it to grayscale.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - The encoded mask image.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        Ok(Self {
+            image: preprocess::decode(bytes)?.to_luma8(),
+        })
+    }
+
+    /// Loads a mask from the given path, applying its EXIF orientation and converting it to grayscale.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the mask image file.
+    pub fn open(path: &str) -> Result<Self> {
+        Ok(Self {
+            image: preprocess::open(path)?.to_luma8(),
+        })
+    }
+
+    /// Builds a mask marking the transparent areas of an image for repainting.
+    ///
+    /// # Arguments
+    ///
+    /// * `image` - The image whose alpha channel is used. Fully opaque pixels are kept.
+    pub fn from_alpha(image: &DynamicImage) -> Self {
+        let rgba = image.to_rgba8();
+        Self {
+            image: GrayImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+                Luma([PAINT - rgba.get_pixel(x, y).0[3]])
+            }),
+        }
+    }
+
+    /// Builds a mask marking pixels close to the given color for repainting.
+    ///
+    /// # Arguments
+    ///
+    /// * `image` - The source image.
+    /// * `color` - The RGB color key.
+    /// * `tolerance` - Maximum per-channel difference for a pixel to match the key.
+    pub fn from_color_key(image: &DynamicImage, color: [u8; 3], tolerance: u8) -> Self {
+        let rgb = image.to_rgb8();
+        Self {
+            image: GrayImage::from_fn(rgb.width(), rgb.height(), |x, y| {
+                let pixel = rgb.get_pixel(x, y).0;
+                let matches = pixel
+                    .iter()
+                    .zip(color)
+                    .all(|(channel, key)| channel.abs_diff(key) <= tolerance);
+                Luma([if matches { PAINT } else { KEEP }])
+            }),
+        }
+    }
+
+    /// Width of the mask in pixels.
+    pub fn width(&self) -> u32 {
+        self.image.width()
+    }
+
+    /// Height of the mask in pixels.
+    pub fn height(&self) -> u32 {
+        self.image.height()
+    }
+
+    /// Marks a rectangle for repainting.
+    pub fn rectangle(self, x: u32, y: u32, width: u32, height: u32) -> Self {
+        self.fill(|px, py| {
+            px >= x && py >= y && px < x.saturating_add(width) && py < y.saturating_add(height)
+        })
+    }
+
+    /// Marks a [`Region`] for repainting.
+    pub fn region(self, region: Region) -> Self {
+        self.rectangle(region.x, region.y, region.width, region.height)
+    }
+
+    /// Marks an axis-aligned ellipse for repainting.
+    ///
+    /// # Arguments
+    ///
+    /// * `cx` - Horizontal center of the ellipse.
+    /// * `cy` - Vertical center of the ellipse.
+    /// * `rx` - Horizontal radius.
+    /// * `ry` - Vertical radius.
+    pub fn ellipse(self, cx: f64, cy: f64, rx: f64, ry: f64) -> Self {
+        self.fill(|px, py| {
+            let dx = (px as f64 + 0.5 - cx) / rx;
+            let dy = (py as f64 + 0.5 - cy) / ry;
+            dx * dx + dy * dy <= 1.0
+        })
+    }
+
+    /// Marks a polygon for repainting, using the even-odd fill rule.
+    ///
+    /// # Arguments
+    ///
+    /// * `points` - The vertices of the polygon, in order.
+    pub fn polygon(self, points: &[(f64, f64)]) -> Self {
+        self.fill(|px, py| {
+            let (x, y) = (px as f64 + 0.5, py as f64 + 0.5);
+            let mut inside = false;
+            let mut previous = points.len().wrapping_sub(1);
+            for (current, &(xi, yi)) in points.iter().enumerate() {
+                let (xj, yj) = points[previous];
+                if (yi > y) != (yj > y) && x < (xj - xi) * (y - yi) / (yj - yi) + xi {
+                    inside = !inside;
+                }
+                previous = current;
+            }
+            inside
+        })
+    }
+
+    /// Swaps repainted and kept areas.
+    pub fn invert(mut self) -> Self {
+        self.image
+            .pixels_mut()
+            .for_each(|pixel| pixel.0[0] = PAINT - pixel.0[0]);
+        self
+    }
+
+    /// Expands the repainted areas by `radius` pixels.
+    pub fn grow(self, radius: u32) -> Self {
+        self.morph(radius, u8::max)
+    }
+
+    /// Contracts the repainted areas by `radius` pixels.
+    pub fn shrink(self, radius: u32) -> Self {
+        self.morph(radius, u8::min)
+    }
+
+    /// Softens the mask edges with a gaussian blur.
+    ///
+    /// # Arguments
+    ///
+    /// * `sigma` - Standard deviation of the blur, in pixels.
+    pub fn feather(self, sigma: f32) -> Self {
+        Self {
+            image: image::imageops::blur(&self.image, sigma),
+        }
+    }
+
+    /// Converts the mask to pure black and white.
+    ///
+    /// # Arguments
+    ///
+    /// * `level` - Pixels at or above this value are repainted, all others are kept.
+    pub fn threshold(mut self, level: u8) -> Self {
+        self.image.pixels_mut().for_each(|pixel| {
+            pixel.0[0] = if pixel.0[0] >= level { PAINT } else { KEEP };
+        });
+        self
+    }
+
+    /// Checks that the mask matches the dimensions of the image to be repainted.
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - Width of the image to be repainted.
+    /// * `height` - Height of the image to be repainted.
+    pub fn validate(&self, width: u32, height: u32) -> Result<()> {
+        if self.image.dimensions() != (width, height) {
+            bail!(
+                "mask is {}x{} but the image is {}x{}",
+                self.width(),
+                self.height(),
+                width,
+                height
+            );
+        }
+        if self.image.pixels().all(|pixel| pixel.0[0] == KEEP) {
+            bail!("mask does not mark any area for repainting");
+        }
+        Ok(())
+    }
+
+    /// Encodes the mask as a PNG image.
+    pub fn to_png(&self) -> Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        self.image
+            .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)?;
+        Ok(bytes)
+    }
+
+    /// Encodes the mask as a base64 PNG string, as expected by the GetImg API.
+    pub fn to_base64(&self) -> Result<String> {
+        Ok(STANDARD.encode(self.to_png()?))
+    }
+
+    fn fill(mut self, inside: impl Fn(u32, u32) -> bool) -> Self {
+        for (x, y, pixel) in self.image.enumerate_pixels_mut() {
+            if inside(x, y) {
+                pixel.0[0] = PAINT;
+            }
+        }
+        self
+    }
+
+    fn morph(self, radius: u32, pick: fn(u8, u8) -> u8) -> Self {
+        if radius == 0 {
+            return self;
+        }
+        let (width, height) = self.image.dimensions();
+        let radius = radius as i64;
+        let window = |image: &GrayImage, x: u32, y: u32, horizontal: bool| {
+            (-radius..=radius)
+                .filter_map(|offset| {
+                    let (px, py) = if horizontal {
+                        (x as i64 + offset, y as i64)
+                    } else {
+                        (x as i64, y as i64 + offset)
+                    };
+                    (px >= 0 && py >= 0 && px < width as i64 && py < height as i64)
+                        .then(|| image.get_pixel(px as u32, py as u32).0[0])
+                })
+                .reduce(pick)
+                .unwrap_or(KEEP)
+        };
+        let rows = GrayImage::from_fn(width, height, |x, y| {
+            Luma([window(&self.image, x, y, true)])
+        });
+        Self {
+            image: GrayImage::from_fn(width, height, |x, y| Luma([window(&rows, x, y, false)])),
+        }
+    }
+}
@@ -0,0 +1,575 @@
+//! This module contains helpers for embedding generation parameters into output images.
+//!
+//! PNG outputs receive a `tEXt`/`iTXt` chunk named `parameters` holding an
+//! AUTOMATIC1111-compatible parameter string and an `iTXt` chunk named `getimg` holding the
+//! parameters as JSON. JPEG outputs receive the parameter string as an EXIF `UserComment` and
+//! both representations inside an XMP packet. Other formats are written unchanged.
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::request::GenerationRequest;
+use crate::response::ToImageResponse;
+
+pub(crate) const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+pub(crate) const EXIF_HEADER: &[u8] = b"Exif\0\0";
+pub(crate) const XMP_HEADER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+pub(crate) const XMP_PNG_KEY: &str = "XML:com.adobe.xmp";
+const MAX_SEGMENT_LEN: usize = u16::MAX as usize - 2;
+const PARAMETERS_TAG: &str = "getimg:parameters";
+
+/// XML namespace of the `getimg` XMP properties.
+pub(crate) const GETIMG_NAMESPACE: &str = "https://github.com/kevin-rs/getimg/ns/1.0/";
+
+/// Keyword of the PNG text chunk holding the AUTOMATIC1111-compatible parameter string.
+pub const PARAMETERS_KEY: &str = "parameters";
+
+/// Keyword of the PNG text chunk holding the JSON-encoded parameters.
+pub const JSON_KEY: &str = "getimg";
+
+/// Struct representing the parameters used to generate an image.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GenerationParams {
+    /// Text prompt used for generation.
+    pub prompt: String,
+    /// Negative prompt used for generation, if any.
+    pub negative_prompt: Option<String>,
+    /// Model name.
+    pub model: String,
+    /// Seed used for generation, as reported by the API when available.
+    pub seed: Option<usize>,
+    /// Number of denoising steps.
+    pub steps: usize,
+    /// Guidance scale, for endpoints that accept one.
+    pub guidance: Option<f64>,
+    /// Scheduler, for endpoints that accept one.
+    pub scheduler: Option<String>,
+    /// Requested width, for endpoints that accept one.
+    pub width: Option<usize>,
+    /// Requested height, for endpoints that accept one.
+    pub height: Option<usize>,
+    /// API endpoint path the request was sent to.
+    pub endpoint: String,
+    /// Cost of the generation, if reported by the API.
+    pub cost: Option<f64>,
+    /// The full request, with image data removed.
+    pub request: GenerationRequest,
+}
+
+impl GenerationParams {
+    /// Collects the parameters of a completed generation.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The request sent to the API.
+    /// * `response` - The response returned by the API.
+    pub fn new(request: &GenerationRequest, response: &ToImageResponse) -> Self {
+        let (width, height) = request.dimensions().unzip();
+        Self {
+            prompt: request.prompt().to_string(),
+            negative_prompt: request.negative_prompt().map(str::to_string),
+            model: request.model().to_string(),
+            seed: response.seed.or(request.seed()),
+            steps: request.steps(),
+            guidance: request.guidance(),
+            scheduler: request.scheduler().map(str::to_string),
+            width,
+            height,
+            endpoint: request.endpoint().to_string(),
+            cost: response.cost,
+            request: request.without_images(),
+        }
+    }
+
+    /// Formats the parameters as an AUTOMATIC1111-compatible `parameters` string.
+    ///
+    /// # Examples
+    ///
+    /// ```text
+    /// A colorful sunset over the ocean.
+    /// Negative prompt: Disfigured, cartoon, blurry
+    /// Steps: 4, Seed: 42, Size: 512x512, Model: lcm-realistic-vision-v5-1, Endpoint: latent-consistency/text-to-image
+    /// ```
+    pub fn to_a1111(&self) -> String {
+        let mut text = self.prompt.clone();
+        if let Some(negative_prompt) = &self.negative_prompt {
+            text.push_str("\nNegative prompt: ");
+            text.push_str(negative_prompt);
+        }
+
+        let mut fields = vec![format!("Steps: {}", self.steps)];
+        if let Some(scheduler) = &self.scheduler {
+            fields.push(format!("Sampler: {}", scheduler));
+        }
+        if let Some(guidance) = self.guidance {
+            fields.push(format!("CFG scale: {}", guidance));
+        }
+        if let Some(seed) = self.seed {
+            fields.push(format!("Seed: {}", seed));
+        }
+        if let (Some(width), Some(height)) = (self.width, self.height) {
+            fields.push(format!("Size: {}x{}", width, height));
+        }
+        fields.push(format!("Model: {}", self.model));
+        fields.push(format!("Endpoint: {}", self.endpoint));
+        if let Some(cost) = self.cost {
+            fields.push(format!("Cost: {}", cost));
+        }
+
+        text.push('\n');
+        text.push_str(&fields.join(", "));
+        text
+    }
+
+    /// Serializes the parameters as JSON.
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string(self)?)
+    }
+}
+
+/// Embeds generation parameters into an encoded image.
+///
+/// Existing parameter chunks or segments written by a previous call are replaced.
+///
+/// # Arguments
+///
+/// * `bytes` - The encoded image.
+/// * `params` - The generation parameters to embed.
+///
+/// # Returns
+///
+/// A `Result` containing the image with embedded metadata. Formats other than PNG and JPEG are returned unchanged.
+pub fn embed(bytes: &[u8], params: &GenerationParams) -> Result<Vec<u8>> {
+    let text = params.to_a1111();
+    let json = params.to_json()?;
+    if bytes.starts_with(PNG_SIGNATURE) {
+        embed_png(bytes, &text, &json)
+    } else if bytes.starts_with(&[0xFF, 0xD8]) {
+        embed_jpeg(bytes, &text, &json)
+    } else {
+        Ok(bytes.to_vec())
+    }
+}
+
+/// Reads the generation parameters embedded by [`embed`].
+///
+/// # Arguments
+///
+/// * `bytes` - The encoded image.
+///
+/// # Returns
+///
+/// A `Result` containing the parameters, `None` if the image carries none, or an error if the image or
+/// embedded JSON is malformed.
+pub fn read(bytes: &[u8]) -> Result<Option<GenerationParams>> {
+    match read_text(bytes, JSON_KEY)? {
+        Some(json) => Ok(Some(serde_json::from_str(&json)?)),
+        None => Ok(None),
+    }
+}
+
+/// Reads the AUTOMATIC1111-compatible `parameters` string, which may also have been written by other tools.
+///
+/// # Arguments
+///
+/// * `bytes` - The encoded image.
+pub fn read_parameters(bytes: &[u8]) -> Result<Option<String>> {
+    read_text(bytes, PARAMETERS_KEY)
+}
+
+fn read_text(bytes: &[u8], key: &str) -> Result<Option<String>> {
+    if bytes.starts_with(PNG_SIGNATURE) {
+        for (kind, data) in png_chunks(bytes)? {
+            if png_text_keyword(data) != key.as_bytes() {
+                continue;
+            }
+            let rest = data.get(key.len() + 1..).unwrap_or_default();
+            match &kind {
+                b"tEXt" => return Ok(Some(rest.iter().map(|byte| *byte as char).collect())),
+                b"iTXt" => return Ok(Some(parse_itxt(rest)?)),
+                _ => {}
+            }
+        }
+        Ok(None)
+    } else if let Some(xmp) = read_xmp(bytes)? {
+        let value = if key == JSON_KEY {
+            xml_element_text(&xmp, PARAMETERS_TAG)
+        } else {
+            xml_element_text(&xmp, "dc:description")
+                .and_then(|alt| xml_element_text(&alt, "rdf:li"))
+        };
+        Ok(value.map(|value| xml_unescape(&value)))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Parses the data of an `iTXt` chunk following the keyword and its null separator.
+fn parse_itxt(data: &[u8]) -> Result<String> {
+    match data {
+        [0, _, rest @ ..] => {
+            let mut fields = rest.splitn(3, |byte| *byte == 0);
+            let (_language, _translated) = (fields.next(), fields.next());
+            match fields.next() {
+                Some(text) => Ok(String::from_utf8(text.to_vec())?),
+                None => bail!("malformed iTXt chunk"),
+            }
+        }
+        [1, ..] => bail!("compressed iTXt chunks are not supported"),
+        _ => bail!("malformed iTXt chunk"),
+    }
+}
+
+/// Returns the raw content of the first `<tag ...>...</tag>` element in `xml`.
+pub(crate) fn xml_element_text(xml: &str, tag: &str) -> Option<String> {
+    let start = xml.find(&format!("<{}", tag))?;
+    let content = start + xml[start..].find('>')? + 1;
+    let end = content + xml[content..].find(&format!("</{}>", tag))?;
+    Some(xml[content..end].to_string())
+}
+
+/// Reverses [`xml_escape`].
+pub(crate) fn xml_unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Splits a PNG file into `(chunk type, chunk data)` pairs, skipping the signature.
+pub(crate) fn png_chunks(bytes: &[u8]) -> Result<Vec<([u8; 4], &[u8])>> {
+    let mut chunks = Vec::new();
+    let mut offset = PNG_SIGNATURE.len();
+    while offset < bytes.len() {
+        if offset + 12 > bytes.len() {
+            bail!("truncated PNG chunk at offset {}", offset);
+        }
+        let length = u32::from_be_bytes(bytes[offset..offset + 4].try_into()?) as usize;
+        let kind: [u8; 4] = bytes[offset + 4..offset + 8].try_into()?;
+        let end = offset + 8 + length;
+        if end + 4 > bytes.len() {
+            bail!("truncated PNG chunk at offset {}", offset);
+        }
+        chunks.push((kind, &bytes[offset + 8..end]));
+        offset = end + 4;
+    }
+    Ok(chunks)
+}
+
+/// Appends a PNG chunk with its length and CRC.
+pub(crate) fn write_png_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(kind);
+    hasher.update(data);
+    out.extend_from_slice(&hasher.finalize().to_be_bytes());
+}
+
+/// Returns the keyword of a `tEXt`, `zTXt` or `iTXt` chunk.
+pub(crate) fn png_text_keyword(data: &[u8]) -> &[u8] {
+    data.split(|byte| *byte == 0).next().unwrap_or_default()
+}
+
+/// Builds the data of an uncompressed `iTXt` chunk.
+pub(crate) fn itxt(keyword: &str, text: &str) -> Vec<u8> {
+    let mut data = Vec::with_capacity(keyword.len() + text.len() + 5);
+    data.extend_from_slice(keyword.as_bytes());
+    // Null separator, compression flag, compression method, empty language tag and translated keyword.
+    data.extend_from_slice(&[0, 0, 0, 0, 0]);
+    data.extend_from_slice(text.as_bytes());
+    data
+}
+
+fn embed_png(bytes: &[u8], text: &str, json: &str) -> Result<Vec<u8>> {
+    let parameters = if text.is_ascii() {
+        let mut data = PARAMETERS_KEY.as_bytes().to_vec();
+        data.push(0);
+        data.extend_from_slice(text.as_bytes());
+        (*b"tEXt", data)
+    } else {
+        (*b"iTXt", itxt(PARAMETERS_KEY, text))
+    };
+    let bytes = replace_png_text(bytes, PARAMETERS_KEY, Some(parameters))?;
+    replace_png_text(&bytes, JSON_KEY, Some((*b"iTXt", itxt(JSON_KEY, json))))
+}
+
+/// Removes the text chunks with the given keyword and inserts `chunk` in place of the first one,
+/// or right after the header if there was none.
+pub(crate) fn replace_png_text(
+    bytes: &[u8],
+    keyword: &str,
+    chunk: Option<([u8; 4], Vec<u8>)>,
+) -> Result<Vec<u8>> {
+    let chunks = png_chunks(bytes)?;
+    let is_match = |(kind, data): &([u8; 4], &[u8])| {
+        matches!(kind, b"tEXt" | b"zTXt" | b"iTXt") && png_text_keyword(data) == keyword.as_bytes()
+    };
+    let position = chunks.iter().position(is_match).unwrap_or_else(|| {
+        chunks
+            .iter()
+            .take_while(|(kind, _)| kind == b"IHDR")
+            .count()
+    });
+
+    let mut out = Vec::with_capacity(bytes.len() + 64);
+    out.extend_from_slice(PNG_SIGNATURE);
+    for (index, (kind, data)) in chunks.iter().enumerate() {
+        if index == position {
+            if let Some((kind, data)) = &chunk {
+                write_png_chunk(&mut out, kind, data);
+            }
+        }
+        if !is_match(&(*kind, data)) {
+            write_png_chunk(&mut out, kind, data);
+        }
+    }
+    Ok(out)
+}
+
+/// A JPEG marker and its segment payload.
+pub(crate) type JpegSegment<'a> = (u8, &'a [u8]);
+
+/// Splits a JPEG file into `(marker, segment payload)` pairs up to the start of scan.
+///
+/// Returns the segments and the offset of the start-of-scan marker.
+pub(crate) fn jpeg_segments(bytes: &[u8]) -> Result<(Vec<JpegSegment<'_>>, usize)> {
+    let mut segments = Vec::new();
+    let mut offset = 2;
+    loop {
+        while bytes.get(offset) == Some(&0xFF) && bytes.get(offset + 1) == Some(&0xFF) {
+            offset += 1;
+        }
+        match bytes.get(offset..offset + 2) {
+            Some([0xFF, 0xDA]) | Some([0xFF, 0xD9]) => return Ok((segments, offset)),
+            Some([0xFF, marker @ (0x01 | 0xD0..=0xD7)]) => {
+                segments.push((*marker, &bytes[offset..offset]));
+                offset += 2;
+            }
+            Some([0xFF, marker]) => {
+                let length = bytes
+                    .get(offset + 2..offset + 4)
+                    .map(|len| u16::from_be_bytes([len[0], len[1]]) as usize);
+                match length {
+                    Some(length) if length >= 2 && offset + 2 + length <= bytes.len() => {
+                        segments.push((*marker, &bytes[offset + 4..offset + 2 + length]));
+                        offset += 2 + length;
+                    }
+                    _ => bail!("truncated JPEG segment at offset {}", offset),
+                }
+            }
+            _ => bail!("invalid JPEG marker at offset {}", offset),
+        }
+    }
+}
+
+/// Appends a JPEG segment with its marker and length.
+pub(crate) fn write_jpeg_segment(out: &mut Vec<u8>, marker: u8, payload: &[u8]) -> Result<()> {
+    if payload.len() > MAX_SEGMENT_LEN {
+        bail!(
+            "metadata segment of {} bytes exceeds the JPEG limit of {} bytes",
+            payload.len(),
+            MAX_SEGMENT_LEN
+        );
+    }
+    out.extend_from_slice(&[0xFF, marker]);
+    out.extend_from_slice(&((payload.len() + 2) as u16).to_be_bytes());
+    out.extend_from_slice(payload);
+    Ok(())
+}
+
+fn embed_jpeg(bytes: &[u8], text: &str, json: &str) -> Result<Vec<u8>> {
+    let bytes = replace_jpeg_app1(bytes, EXIF_HEADER, Some(&exif_user_comment(text)))?;
+    write_xmp_description(&bytes, PARAMETERS_TAG, &parameters_description(text, json))
+}
+
+/// Removes the APP1 segments starting with `header` and inserts `payload` in place of the first one,
+/// or after the leading APP0/APP1 segments if there was none.
+pub(crate) fn replace_jpeg_app1(
+    bytes: &[u8],
+    header: &[u8],
+    payload: Option<&[u8]>,
+) -> Result<Vec<u8>> {
+    let (segments, scan) = jpeg_segments(bytes)?;
+    let is_match = |(marker, data): &JpegSegment| *marker == 0xE1 && data.starts_with(header);
+    let position = segments.iter().position(is_match).unwrap_or_else(|| {
+        segments
+            .iter()
+            .take_while(|(marker, _)| matches!(marker, 0xE0 | 0xE1))
+            .count()
+    });
+
+    let mut out = Vec::with_capacity(bytes.len() + payload.map_or(0, <[u8]>::len) + 4);
+    out.extend_from_slice(&[0xFF, 0xD8]);
+    for (index, segment) in segments.iter().enumerate() {
+        if index == position {
+            if let Some(payload) = payload {
+                write_jpeg_segment(&mut out, 0xE1, payload)?;
+            }
+        }
+        let (marker, data) = *segment;
+        if is_match(segment) {
+            continue;
+        }
+        if data.is_empty() && matches!(marker, 0x01 | 0xD0..=0xD7) {
+            out.extend_from_slice(&[0xFF, marker]);
+        } else {
+            write_jpeg_segment(&mut out, marker, data)?;
+        }
+    }
+    if position == segments.len() {
+        if let Some(payload) = payload {
+            write_jpeg_segment(&mut out, 0xE1, payload)?;
+        }
+    }
+    out.extend_from_slice(&bytes[scan..]);
+    Ok(out)
+}
+
+/// Builds an EXIF APP1 payload holding `text` as a `UserComment`, encoded as AUTOMATIC1111 expects.
+fn exif_user_comment(text: &str) -> Vec<u8> {
+    let mut comment = b"UNICODE\0".to_vec();
+    comment.extend(text.encode_utf16().flat_map(u16::to_be_bytes));
+
+    let mut out = EXIF_HEADER.to_vec();
+    // Big-endian TIFF header with IFD0 at offset 8.
+    out.extend_from_slice(b"MM\0\x2A\0\0\0\x08");
+    // IFD0: a single ExifIFDPointer entry, followed by the Exif IFD at offset 26.
+    out.extend_from_slice(&1u16.to_be_bytes());
+    out.extend_from_slice(&[0x87, 0x69, 0x00, 0x04, 0, 0, 0, 1]);
+    out.extend_from_slice(&26u32.to_be_bytes());
+    out.extend_from_slice(&0u32.to_be_bytes());
+    // Exif IFD: a single UserComment entry pointing at the data after the IFD (offset 44).
+    out.extend_from_slice(&1u16.to_be_bytes());
+    out.extend_from_slice(&[0x92, 0x86, 0x00, 0x07]);
+    out.extend_from_slice(&(comment.len() as u32).to_be_bytes());
+    out.extend_from_slice(&44u32.to_be_bytes());
+    out.extend_from_slice(&0u32.to_be_bytes());
+    out.extend_from_slice(&comment);
+    out
+}
+
+/// Reads the XMP packet of a PNG or JPEG image.
+pub(crate) fn read_xmp(bytes: &[u8]) -> Result<Option<String>> {
+    if bytes.starts_with(PNG_SIGNATURE) {
+        for (kind, data) in png_chunks(bytes)? {
+            if &kind == b"iTXt" && png_text_keyword(data) == XMP_PNG_KEY.as_bytes() {
+                let rest = data.get(XMP_PNG_KEY.len() + 1..).unwrap_or_default();
+                return Ok(Some(parse_itxt(rest)?));
+            }
+        }
+    } else if bytes.starts_with(&[0xFF, 0xD8]) {
+        let (segments, _) = jpeg_segments(bytes)?;
+        for (marker, payload) in segments {
+            if marker == 0xE1 && payload.starts_with(XMP_HEADER) {
+                let xmp = String::from_utf8_lossy(&payload[XMP_HEADER.len()..]);
+                return Ok(Some(xmp.into_owned()));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Adds an `rdf:Description` block to the XMP packet of a PNG or JPEG image.
+///
+/// Existing blocks containing `key` are replaced, all other blocks are preserved. Formats other than
+/// PNG and JPEG are returned unchanged.
+pub(crate) fn write_xmp_description(bytes: &[u8], key: &str, description: &str) -> Result<Vec<u8>> {
+    let mut descriptions = match read_xmp(bytes)? {
+        Some(xmp) => xmp_descriptions(&xmp),
+        None => Vec::new(),
+    };
+    descriptions.retain(|existing| !existing.contains(key));
+    descriptions.push(description.to_string());
+    let packet = xmp_packet(&descriptions);
+
+    if bytes.starts_with(PNG_SIGNATURE) {
+        replace_png_text(
+            bytes,
+            XMP_PNG_KEY,
+            Some((*b"iTXt", itxt(XMP_PNG_KEY, &packet))),
+        )
+    } else if bytes.starts_with(&[0xFF, 0xD8]) {
+        let mut payload = XMP_HEADER.to_vec();
+        payload.extend_from_slice(packet.as_bytes());
+        replace_jpeg_app1(bytes, XMP_HEADER, Some(&payload))
+    } else {
+        Ok(bytes.to_vec())
+    }
+}
+
+/// Splits an XMP packet into its `rdf:Description` blocks.
+fn xmp_descriptions(xmp: &str) -> Vec<String> {
+    let mut descriptions = Vec::new();
+    let mut rest = xmp;
+    while let Some(start) = rest.find("<rdf:Description") {
+        let Some(open_end) = rest[start..].find('>').map(|end| start + end + 1) else {
+            break;
+        };
+        let end = if rest[..open_end].ends_with("/>") {
+            open_end
+        } else {
+            match rest[open_end..].find("</rdf:Description>") {
+                Some(close) => open_end + close + "</rdf:Description>".len(),
+                None => break,
+            }
+        };
+        descriptions.push(rest[start..end].to_string());
+        rest = &rest[end..];
+    }
+    descriptions
+}
+
+/// Wraps `rdf:Description` blocks into a complete XMP packet.
+pub(crate) fn xmp_packet(descriptions: &[String]) -> String {
+    format!(
+        concat!(
+            "<?xpacket begin=\"\u{FEFF}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n",
+            "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n",
+            " <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n",
+            "  {}\n",
+            " </rdf:RDF>\n",
+            "</x:xmpmeta>\n",
+            "<?xpacket end=\"w\"?>"
+        ),
+        descriptions.join("\n  ")
+    )
+}
+
+/// Builds the `rdf:Description` block holding the parameter string as `dc:description` and the JSON
+/// blob as `getimg:parameters`.
+fn parameters_description(text: &str, json: &str) -> String {
+    format!(
+        concat!(
+            "<rdf:Description rdf:about=\"\"\n",
+            "    xmlns:dc=\"http://purl.org/dc/elements/1.1/\"\n",
+            "    xmlns:getimg=\"{}\">\n",
+            "   <dc:description><rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt></dc:description>\n",
+            "   <{}>{}</{}>\n",
+            "  </rdf:Description>"
+        ),
+        GETIMG_NAMESPACE,
+        xml_escape(text),
+        PARAMETERS_TAG,
+        xml_escape(json),
+        PARAMETERS_TAG
+    )
+}
+
+/// Escapes the XML special characters in `text`.
+pub(crate) fn xml_escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
@@ -0,0 +1,326 @@
+//! This module contains helpers for naming and placing generated images on disk.
+//!
+//! Output paths are rendered from templates such as `{cmd}-{seed}.{ext}`. The supported
+//! placeholders are `{cmd}`, `{model}`, `{seed}`, `{timestamp}`, `{prompt_slug}`, `{index}` and
+//! `{ext}`; literal braces are written as `{{` and `}}`. The template `-` writes the image to
+//! stdout instead.
+
+use anyhow::{bail, Context, Result};
+use std::fs::{File, OpenOptions};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::request::GenerationRequest;
+use crate::utils::utc_components;
+
+/// Template used when no output path is configured.
+pub const DEFAULT_TEMPLATE: &str = "{cmd}-{timestamp}-{seed}.{ext}";
+
+/// Template of sweep grids, relative to the output directory.
+pub const SWEEP_TEMPLATE: &str = "{cmd}-sweep-{timestamp}.png";
+
+/// Template of the directory mirroring a directory or glob `--image`, relative to the output
+/// directory.
+pub const INPUTS_TEMPLATE: &str = "{cmd}-{timestamp}";
+
+/// Template of pipeline manifests, relative to the output directory, where `{cmd}` is the
+/// pipeline name.
+pub const PIPELINE_TEMPLATE: &str = "{cmd}-{timestamp}.json";
+
+/// Template writing the image to stdout instead of a file.
+pub const STDOUT_TEMPLATE: &str = "-";
+
+/// Placeholders accepted in output templates.
+pub const PLACEHOLDERS: [&str; 7] = [
+    "cmd",
+    "model",
+    "seed",
+    "timestamp",
+    "prompt_slug",
+    "index",
+    "ext",
+];
+
+/// Maximum number of characters of the prompt kept in `{prompt_slug}`.
+const SLUG_LEN: usize = 48;
+
+/// Struct representing the values substituted into an output template.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputFields {
+    /// CLI name of the command, e.g. `t2i`.
+    pub cmd: String,
+    /// Model used for generation.
+    pub model: String,
+    /// Seed of the generation, if known.
+    pub seed: Option<usize>,
+    /// Time of the generation.
+    pub timestamp: SystemTime,
+    /// Text prompt of the generation.
+    pub prompt: String,
+    /// Position of the output within a run producing several images, starting at 0.
+    pub index: usize,
+    /// File extension matching the encoded image.
+    pub ext: String,
+}
+
+impl OutputFields {
+    /// Collects the template values of a generated image.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The request sent to the API.
+    /// * `seed` - The seed reported by the API, falling back to the requested one.
+    /// * `image` - The encoded image, used to pick the extension.
+    pub fn new(request: &GenerationRequest, seed: Option<usize>, image: &[u8]) -> Self {
+        Self {
+            cmd: request.command().to_string(),
+            model: request.model().to_string(),
+            seed: seed.or(request.seed()),
+            timestamp: SystemTime::now(),
+            prompt: request.prompt().to_string(),
+            index: 0,
+            ext: extension(image).to_string(),
+        }
+    }
+}
+
+/// Struct representing where and under which names outputs are saved.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputSettings {
+    /// Path template of the output file, relative to `dir` unless absolute.
+    pub template: String,
+    /// Directory outputs are written to.
+    pub dir: Option<PathBuf>,
+}
+
+impl Default for OutputSettings {
+    fn default() -> Self {
+        Self {
+            template: DEFAULT_TEMPLATE.to_string(),
+            dir: None,
+        }
+    }
+}
+
+impl OutputSettings {
+    /// Returns whether images are written to stdout rather than saved, see [`STDOUT_TEMPLATE`].
+    pub fn is_stdout(&self) -> bool {
+        self.template == STDOUT_TEMPLATE
+    }
+
+    /// Renders the output path of an image and creates the file, along with its parent directory.
+    ///
+    /// Existing files are never overwritten: a `-1`, `-2`, ... suffix is added before the
+    /// extension instead. The file is claimed when it is created, so concurrent saves rendering
+    /// the same path each get their own. A `{ext}`-less template without an extension receives the
+    /// one of the encoded image.
+    ///
+    /// # Arguments
+    ///
+    /// * `fields` - The values substituted into the template.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the path and the empty file open for writing, or an error if the
+    /// template is invalid or the file cannot be created.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use getimg::output::{OutputFields, OutputSettings};
+    /// use std::time::SystemTime;
+    ///
+    /// let settings = OutputSettings {
+    ///     template: "{cmd}/{prompt_slug}-{seed}.{ext}".to_string(),
+    ///     dir: Some("out".into()),
+    /// };
+    /// let fields = OutputFields {
+    ///     cmd: "t2i".to_string(),
+    ///     model: "lcm-realistic-vision-v5-1".to_string(),
+    ///     seed: Some(42),
+    ///     timestamp: SystemTime::now(),
+    ///     prompt: "A colorful sunset over the ocean.".to_string(),
+    ///     index: 0,
+    ///     ext: "png".to_string(),
+    /// };
+    /// // out/t2i/a-colorful-sunset-over-the-ocean-42.png
+    /// let (path, file) = settings.create(&fields).unwrap();
+    /// ```
+    pub fn create(&self, fields: &OutputFields) -> Result<(PathBuf, File)> {
+        let mut rendered = render(&self.template, fields)?;
+        if rendered.is_empty() {
+            bail!("output template `{}` renders an empty path", self.template);
+        }
+        if Path::new(&rendered).extension().is_none() {
+            rendered = format!("{}.{}", rendered, fields.ext);
+        }
+        let path = match &self.dir {
+            Some(dir) => dir.join(rendered),
+            None => PathBuf::from(rendered),
+        };
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            std::fs::create_dir_all(parent)?;
+        }
+        create_unique(path)
+    }
+}
+
+/// Renders an output template.
+///
+/// # Arguments
+///
+/// * `template` - The template, e.g. `{cmd}-{seed}.{ext}`.
+/// * `fields` - The values substituted into the template.
+///
+/// # Returns
+///
+/// A `Result` containing the rendered string, or an error on unknown or unbalanced placeholders.
+pub fn render(template: &str, fields: &OutputFields) -> Result<String> {
+    let mut out = String::with_capacity(template.len());
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                out.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                out.push('}');
+            }
+            '{' => {
+                let mut name = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => name.push(c),
+                        None => bail!("unclosed `{{` in output template `{}`", template),
+                    }
+                }
+                out.push_str(&placeholder(&name, fields, template)?);
+            }
+            '}' => bail!("unmatched `}}` in output template `{}`", template),
+            c => out.push(c),
+        }
+    }
+    Ok(out)
+}
+
+/// Escapes the braces of literal text, such as a path, so that [`render`] leaves it unchanged.
+///
+/// # Arguments
+///
+/// * `text` - The text to escape.
+pub fn escape(text: &str) -> String {
+    text.replace('{', "{{").replace('}', "}}")
+}
+
+fn placeholder(name: &str, fields: &OutputFields, template: &str) -> Result<String> {
+    Ok(match name {
+        "cmd" => fields.cmd.clone(),
+        "model" => fields.model.replace(['/', '\\'], "-"),
+        "seed" => fields
+            .seed
+            .map(|seed| seed.to_string())
+            .unwrap_or_else(|| "noseed".to_string()),
+        "timestamp" => {
+            let (year, month, day, hour, minute, second) = utc_components(fields.timestamp);
+            format!(
+                "{:04}{:02}{:02}-{:02}{:02}{:02}",
+                year, month, day, hour, minute, second
+            )
+        }
+        "prompt_slug" => slug(&fields.prompt),
+        "index" => fields.index.to_string(),
+        "ext" => fields.ext.clone(),
+        _ => bail!(
+            "unknown placeholder `{{{}}}` in output template `{}`, expected one of {}",
+            name,
+            template,
+            PLACEHOLDERS
+                .iter()
+                .map(|name| format!("{{{}}}", name))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    })
+}
+
+/// Turns a prompt into a lowercase, dash-separated file name fragment.
+///
+/// # Arguments
+///
+/// * `text` - The text to slugify.
+pub fn slug(text: &str) -> String {
+    let words = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase);
+    let mut out = String::new();
+    for word in words {
+        if out.chars().count() + word.chars().count() + 1 > SLUG_LEN {
+            if out.is_empty() {
+                out = word.chars().take(SLUG_LEN).collect();
+            }
+            break;
+        }
+        if !out.is_empty() {
+            out.push('-');
+        }
+        out.push_str(&word);
+    }
+    if out.is_empty() {
+        "untitled".to_string()
+    } else {
+        out
+    }
+}
+
+/// Returns the file extension matching an encoded image, `png` if unknown.
+///
+/// # Arguments
+///
+/// * `bytes` - The encoded image.
+pub fn extension(bytes: &[u8]) -> &'static str {
+    if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        "jpg"
+    } else if bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(b"WEBP") {
+        "webp"
+    } else {
+        "png"
+    }
+}
+
+/// Creates a file at a path, or at the first free `-1`, `-2`, ... variant of it if it exists.
+fn create_unique(path: PathBuf) -> Result<(PathBuf, File)> {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+    let candidates = std::iter::once(path.clone())
+        .chain((1..).map(|n| path.with_file_name(format!("{}-{}{}", stem, n, extension))));
+    for candidate in candidates {
+        // `create_new` fails if the file exists, so the check and the creation cannot race.
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&candidate)
+        {
+            Ok(file) => return Ok((candidate, file)),
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => continue,
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("failed to create {}", candidate.display()))
+            }
+        }
+    }
+    unreachable!("the suffixes are unbounded")
+}
@@ -0,0 +1,553 @@
+//! This module contains declarative multi-step pipelines.
+//!
+//! A pipeline is a YAML (or JSON) workflow listing named steps that run in order. Every step
+//! names its subcommand in `type` and accepts the options of a batch job (see [`crate::batch`]),
+//! plus `upscale`, which enlarges an image locally with a Lanczos filter:
+//!
+//! ```yaml
+//! name: lighthouse
+//! vars:
+//!   subject: a lighthouse on a cliff
+//!   seed: 42
+//! steps:
+//!   - name: base
+//!     type: t2i
+//!     prompt: "{{ subject }} at dawn"
+//!     seed: "{{ seed }}"
+//!   - name: painted
+//!     type: cnet
+//!     net: canny-1.1
+//!     image: "{{ base }}"
+//!     prompt: "{{ subject }}, watercolor"
+//!     seed: "{{ base.seed }}"
+//!   - name: door
+//!     type: paint
+//!     image: "{{ painted }}"
+//!     mask_image: masks/door.png
+//!     prompt: a red wooden door
+//!   - name: large
+//!     type: upscale
+//!     image: "{{ door }}"
+//!     scale: 2
+//! ```
+//!
+//! String options may contain `{{ name }}` templates referring to a variable or to an earlier
+//! step, whose output path it stands for; `{{ step.seed }}` and `{{ step.cost }}` refer to the
+//! seed and cost of its generation. A string made of a single template takes the type of the
+//! value, so numbers can be templated too. Relative input paths are resolved against the
+//! directory of the workflow file.
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Instant;
+
+use crate::batch::{Job, JobKind, Runner};
+use crate::cli::Postprocess;
+use crate::output::OutputSettings;
+use crate::report::status;
+use crate::request::GenerationRequest;
+
+/// Type of the steps enlarging an image locally.
+pub const UPSCALE: &str = "upscale";
+
+/// Struct representing a workflow file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Pipeline {
+    /// Name of the pipeline, used to name its outputs. Defaults to the workflow file stem.
+    pub name: Option<String>,
+    /// Variables available to templates.
+    #[serde(default)]
+    pub vars: Map<String, Value>,
+    /// Steps of the pipeline, in order.
+    pub steps: Vec<Step>,
+}
+
+/// Struct representing one step of a pipeline.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Step {
+    /// Name of the step, referred to by later steps and naming its output.
+    pub name: String,
+    /// Options of the step, including its `type`, possibly templated.
+    #[serde(flatten)]
+    pub options: Map<String, Value>,
+}
+
+/// Struct representing the options of an `upscale` step.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Upscale {
+    /// Path to the image to enlarge.
+    pub image: String,
+    /// Factor both sides are multiplied by.
+    #[serde(default = "default_scale")]
+    pub scale: f64,
+}
+
+/// Struct representing a variable given on the command line as `name=value`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Var {
+    /// Name of the variable.
+    pub name: String,
+    /// Value of the variable, a number if it parses as one and a string otherwise.
+    pub value: Value,
+}
+
+/// Struct representing the record of one step in a pipeline manifest.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct StepRecord {
+    /// Name of the step.
+    pub name: String,
+    /// Options of the step with templates rendered.
+    pub options: Map<String, Value>,
+    /// Path of the saved image.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    /// Seed of the generation.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<usize>,
+    /// Cost of the generation.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cost: Option<f64>,
+    /// Time spent on the step, in milliseconds.
+    pub latency_ms: u64,
+    /// Error message of a failed step.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Struct representing the manifest written next to the outputs of a pipeline run.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct PipelineManifest {
+    /// Name of the pipeline.
+    pub name: String,
+    /// Directory the step outputs are saved in.
+    pub dir: PathBuf,
+    /// Variables of the run, after command-line overrides.
+    pub vars: Map<String, Value>,
+    /// Steps that ran, in order; a failed step ends the run.
+    pub steps: Vec<StepRecord>,
+}
+
+impl Pipeline {
+    /// Parses and validates a workflow.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The workflow, as YAML or JSON.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the pipeline, or an error if it is malformed or a step refers to
+    /// an unknown variable or to a step that has not run yet.
+    pub fn from_yaml(text: &str) -> Result<Self> {
+        let pipeline: Self = serde_yaml::from_str(text)?;
+        pipeline.validate()?;
+        Ok(pipeline)
+    }
+
+    /// Reads a workflow file, naming the pipeline after the file if it has no name.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the workflow file.
+    pub fn open(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let mut pipeline = Self::from_yaml(&text)
+            .with_context(|| format!("invalid workflow {}", path.display()))?;
+        if pipeline.name.is_none() {
+            pipeline.name = path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned());
+        }
+        Ok(pipeline)
+    }
+
+    /// Checks step names, types and template references.
+    pub fn validate(&self) -> Result<()> {
+        if self.steps.is_empty() {
+            bail!("the pipeline has no steps");
+        }
+        let mut known = self.vars.keys().cloned().collect::<Vec<_>>();
+        for step in &self.steps {
+            if step.name.is_empty()
+                || !step
+                    .name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            {
+                bail!(
+                    "invalid step name `{}`, use letters, digits, `-` and `_`",
+                    step.name
+                );
+            }
+            if known.contains(&step.name) {
+                bail!(
+                    "step `{}` is defined twice or shadows a variable",
+                    step.name
+                );
+            }
+            step.kind()?;
+            for reference in references(&Value::Object(step.options.clone()))? {
+                let root = reference.split('.').next().unwrap_or_default();
+                if !known.iter().any(|name| name == root) {
+                    bail!(
+                        "step `{}` refers to `{}`, which is neither a variable nor an earlier step",
+                        step.name,
+                        reference
+                    );
+                }
+            }
+            known.push(step.name.clone());
+        }
+        Ok(())
+    }
+
+    /// Runs the steps in order, saving each output as `<step name>.<ext>` in `dir` and stopping
+    /// at the first failing step.
+    ///
+    /// # Arguments
+    ///
+    /// * `runner` - Client, model, defaults and image processing of the steps; relative input
+    ///   paths are resolved against its base directory.
+    /// * `dir` - Directory the outputs are saved in, created if missing.
+    /// * `manifest` - The manifest of the run, receiving one record per step that ran.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` that is the error of the failing step, if any.
+    pub async fn run(
+        &self,
+        runner: &Runner,
+        dir: &Path,
+        manifest: &mut PipelineManifest,
+    ) -> Result<()> {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("failed to create {}", dir.display()))?;
+        manifest.name = self.name.clone().unwrap_or_else(|| "pipeline".to_string());
+        manifest.dir = dir.to_path_buf();
+        manifest.vars = self.vars.clone();
+
+        let mut scope = self.vars.clone();
+        for (index, step) in self.steps.iter().enumerate() {
+            let mut record = StepRecord {
+                name: step.name.clone(),
+                ..StepRecord::default()
+            };
+            let started = Instant::now();
+            let result = self
+                .run_step(runner, dir, index, step, &scope, &mut record)
+                .await;
+            record.latency_ms = started.elapsed().as_millis() as u64;
+            if let Err(err) = result {
+                record.error = Some(format!("{:#}", err));
+                manifest.steps.push(record);
+                return Err(err.context(format!("step `{}` failed", step.name)));
+            }
+            let path = record.path.clone().unwrap_or_default();
+            scope.insert(
+                step.name.clone(),
+                serde_json::json!({
+                    "path": std::path::absolute(&path)?,
+                    "seed": record.seed,
+                    "cost": record.cost,
+                }),
+            );
+            manifest.steps.push(record);
+        }
+        Ok(())
+    }
+
+    /// Builds the requests of the generation steps that depend on variables only, for
+    /// `--dry-run`. Steps using the output of an earlier step and `upscale` steps are skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `runner` - Model, defaults and image processing of the steps; relative input paths
+    ///   are resolved against its base directory.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the name and request of each step that could be prepared.
+    pub async fn requests(&self, runner: &Runner) -> Result<Vec<(String, GenerationRequest)>> {
+        let mut requests = Vec::new();
+        for step in &self.steps {
+            let independent = references(&Value::Object(step.options.clone()))?
+                .iter()
+                .all(|reference| self.vars.contains_key(reference));
+            if step.kind()? == UPSCALE || !independent {
+                continue;
+            }
+            let Value::Object(options) = render(&Value::Object(step.options.clone()), &self.vars)?
+            else {
+                unreachable!("objects render to objects");
+            };
+            let (_, request) = job_request(runner, options)
+                .await
+                .with_context(|| format!("step `{}` failed", step.name))?;
+            requests.push((step.name.clone(), request));
+        }
+        Ok(requests)
+    }
+
+    async fn run_step(
+        &self,
+        runner: &Runner,
+        dir: &Path,
+        index: usize,
+        step: &Step,
+        scope: &Map<String, Value>,
+        record: &mut StepRecord,
+    ) -> Result<()> {
+        let Value::Object(options) = render(&Value::Object(step.options.clone()), scope)? else {
+            unreachable!("objects render to objects");
+        };
+        record.options = options.clone();
+        let kind = step.kind()?;
+        status!("Running step {} ({})...", step.name, kind);
+        let mut options = options;
+        options.remove("type");
+
+        if kind == UPSCALE {
+            let upscale: Upscale = serde_json::from_value(Value::Object(options))?;
+            let input = runner.base.join(&upscale.image);
+            let output = dir.join(&step.name);
+            let path =
+                tokio::task::spawn_blocking(move || upscale_image(&input, &output, upscale.scale))
+                    .await??;
+            status!("Image saved as: {}", path.display());
+            record.path = Some(path);
+            return Ok(());
+        }
+
+        options.insert("type".to_string(), Value::from(kind));
+        let (job, request) = job_request(runner, options).await?;
+        let response = job.client(&runner.client).send(&request).await?;
+        let postprocess = Postprocess {
+            output: OutputSettings {
+                template: job
+                    .output
+                    .clone()
+                    .unwrap_or_else(|| format!("{}.{{ext}}", step.name)),
+                dir: Some(dir.to_path_buf()),
+            },
+            ..runner.postprocess.clone()
+        };
+        let saved = postprocess.save(&request, &response, None, index)?;
+        record.path = Some(saved.path);
+        record.seed = response.seed.or(request.seed());
+        record.cost = response.cost;
+        Ok(())
+    }
+}
+
+impl Step {
+    /// Returns the type of the step: a batch job type such as `t2i`, or `upscale`.
+    pub fn kind(&self) -> Result<&str> {
+        let kind = self
+            .options
+            .get("type")
+            .and_then(Value::as_str)
+            .ok_or_else(|| anyhow!("step `{}` has no `type`", self.name))?;
+        if kind != UPSCALE && serde_json::from_value::<JobKind>(Value::from(kind)).is_err() {
+            bail!(
+                "step `{}` has unknown type `{}`, expected t2i, i2i, cnet, paint, edit or upscale",
+                self.name,
+                kind
+            );
+        }
+        Ok(kind)
+    }
+}
+
+impl FromStr for Var {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (name, value) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow!("expected `name=value`, got `{}`", s))?;
+        let value = serde_json::from_str::<Value>(value)
+            .ok()
+            .filter(Value::is_number)
+            .unwrap_or_else(|| Value::from(value));
+        Ok(Self {
+            name: name.trim().to_string(),
+            value,
+        })
+    }
+}
+
+impl PipelineManifest {
+    /// Returns the total cost of the steps that ran.
+    pub fn cost(&self) -> Option<f64> {
+        self.steps
+            .iter()
+            .filter_map(|step| step.cost)
+            .reduce(|total, cost| total + cost)
+    }
+}
+
+/// Renders the templates in the strings of a value.
+///
+/// # Arguments
+///
+/// * `value` - The value, e.g. the options of a step.
+/// * `scope` - Variables and outputs of earlier steps, by name.
+///
+/// # Returns
+///
+/// A `Result` containing the rendered value, or an error naming an unknown reference.
+pub fn render(value: &Value, scope: &Map<String, Value>) -> Result<Value> {
+    Ok(match value {
+        Value::String(text) => render_str(text, scope)?,
+        Value::Array(values) => Value::Array(
+            values
+                .iter()
+                .map(|value| render(value, scope))
+                .collect::<Result<_>>()?,
+        ),
+        Value::Object(values) => Value::Object(
+            values
+                .iter()
+                .map(|(key, value)| Ok((key.clone(), render(value, scope)?)))
+                .collect::<Result<_>>()?,
+        ),
+        value => value.clone(),
+    })
+}
+
+fn render_str(text: &str, scope: &Map<String, Value>) -> Result<Value> {
+    if let Some(reference) = text
+        .trim()
+        .strip_prefix("{{")
+        .and_then(|rest| rest.strip_suffix("}}"))
+        .filter(|reference| !reference.contains("{{"))
+    {
+        return lookup(reference.trim(), scope);
+    }
+    let mut rendered = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        let end = rest[start..]
+            .find("}}")
+            .ok_or_else(|| anyhow!("unclosed `{{{{` in `{}`", text))?;
+        rendered.push_str(&rest[..start]);
+        let reference = rest[start + 2..start + end].trim();
+        match lookup(reference, scope)? {
+            Value::String(value) => rendered.push_str(&value),
+            value @ (Value::Number(_) | Value::Bool(_)) => rendered.push_str(&value.to_string()),
+            _ => bail!("`{}` has no value to insert into `{}`", reference, text),
+        }
+        rest = &rest[start + end + 2..];
+    }
+    rendered.push_str(rest);
+    Ok(Value::String(rendered))
+}
+
+fn lookup(reference: &str, scope: &Map<String, Value>) -> Result<Value> {
+    let mut parts = reference.split('.');
+    let root = parts.next().unwrap_or_default();
+    let mut value = scope
+        .get(root)
+        .ok_or_else(|| anyhow!("unknown reference `{}`", reference))?;
+    for part in parts {
+        value = value
+            .get(part)
+            .ok_or_else(|| anyhow!("unknown reference `{}`", reference))?;
+    }
+    // A step stands for its output path.
+    match value.get("path") {
+        Some(path) if value.is_object() => Ok(path.clone()),
+        _ => Ok(value.clone()),
+    }
+}
+
+/// Returns the references of the templates in the strings of a value.
+fn references(value: &Value) -> Result<Vec<String>> {
+    let mut found = Vec::new();
+    match value {
+        Value::String(text) => {
+            let mut rest = text.as_str();
+            while let Some(start) = rest.find("{{") {
+                let end = rest[start..]
+                    .find("}}")
+                    .ok_or_else(|| anyhow!("unclosed `{{{{` in `{}`", text))?;
+                found.push(rest[start + 2..start + end].trim().to_string());
+                rest = &rest[start + end + 2..];
+            }
+        }
+        Value::Array(values) => {
+            for value in values {
+                found.extend(references(value)?);
+            }
+        }
+        Value::Object(values) => {
+            for value in values.values() {
+                found.extend(references(value)?);
+            }
+        }
+        _ => {}
+    }
+    Ok(found)
+}
+
+/// Builds the job and request of a generation step from its rendered options.
+async fn job_request(
+    runner: &Runner,
+    options: Map<String, Value>,
+) -> Result<(Job, GenerationRequest)> {
+    let job: Job = serde_json::from_value(Value::Object(options))?;
+    let (task, model, profile, preprocess, base) = (
+        job.clone(),
+        runner.model.clone(),
+        runner.profile.clone(),
+        runner.preprocess.clone(),
+        runner.base.clone(),
+    );
+    let request =
+        tokio::task::spawn_blocking(move || task.request(&model, &profile, &preprocess, &base))
+            .await??;
+    Ok((job, request))
+}
+
+/// Enlarges an image with a Lanczos filter, keeping its format.
+///
+/// # Arguments
+///
+/// * `input` - Path to the image.
+/// * `output` - Path of the enlarged image, without extension.
+/// * `scale` - Factor both sides are multiplied by.
+///
+/// # Returns
+///
+/// A `Result` containing the path of the enlarged image, with the extension of the input.
+fn upscale_image(input: &Path, output: &Path, scale: f64) -> Result<PathBuf> {
+    if !(scale > 0.0 && scale <= 8.0) {
+        bail!("upscale factor {} is outside (0, 8]", scale);
+    }
+    let image =
+        image::open(input).with_context(|| format!("failed to read {}", input.display()))?;
+    let (width, height) = (
+        (image.width() as f64 * scale).round().max(1.0) as u32,
+        (image.height() as f64 * scale).round().max(1.0) as u32,
+    );
+    let upscaled = image.resize_exact(width, height, image::imageops::FilterType::Lanczos3);
+    let ext = input
+        .extension()
+        .map(|ext| ext.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "png".to_string());
+    let path = output.with_extension(ext);
+    upscaled
+        .save(&path)
+        .with_context(|| format!("failed to write {}", path.display()))?;
+    Ok(path)
+}
+
+fn default_scale() -> f64 {
+    2.0
+}
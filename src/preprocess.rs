@@ -0,0 +1,279 @@
+//! This module contains local preprocessing of input images before they are sent to the GetImg API.
+//!
+//! Inputs are decoded with the `image` crate, rotated according to their EXIF orientation,
+//! flattened onto a solid background, fitted to the target dimensions (snapped to the multiple
+//! required by the models) and re-encoded as PNG or JPEG.
+
+use anyhow::Result;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::PngEncoder;
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader, Rgb, RgbImage};
+use std::fs;
+use std::io::Cursor;
+
+/// Decodes an image, applying its EXIF orientation.
+///
+/// # Arguments
+///
+/// * `bytes` - The encoded image.
+///
+/// # Returns
+///
+/// A `Result` containing the decoded image, or an error if the format is not supported.
+pub fn decode(bytes: &[u8]) -> Result<DynamicImage> {
+    Ok(decode_with_format(bytes)?.0)
+}
+
+/// Loads an image from the given path, applying its EXIF orientation.
+///
+/// # Arguments
+///
+/// * `image_path` - A string slice representing the path to the image file.
+pub fn open(image_path: &str) -> Result<DynamicImage> {
+    decode(&fs::read(image_path)?)
+}
+
+fn decode_with_format(bytes: &[u8]) -> Result<(DynamicImage, Option<ImageFormat>)> {
+    let reader = ImageReader::new(Cursor::new(bytes)).with_guessed_format()?;
+    let format = reader.format();
+    let mut decoder = reader.into_decoder()?;
+    let orientation = decoder.orientation()?;
+    let mut image = DynamicImage::from_decoder(decoder)?;
+    image.apply_orientation(orientation);
+    Ok((image, format))
+}
+
+/// Strategy used to fit an image into the target dimensions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Fit {
+    /// Stretch the image to the target dimensions, ignoring its aspect ratio.
+    Resize,
+    /// Scale the image to cover the target dimensions and crop the overflow.
+    #[default]
+    Crop,
+    /// Scale the image to fit inside the target dimensions and pad the remainder.
+    Pad,
+}
+
+/// Encoding used for preprocessed images.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum OutputFormat {
+    /// Lossless PNG encoding.
+    Png,
+    /// JPEG encoding with the configured quality.
+    Jpeg,
+}
+
+/// Settings controlling how input images are preprocessed.
+#[derive(Debug, Clone)]
+pub struct Preprocessor {
+    /// Target width in pixels. Derived from the aspect ratio when only the height is set.
+    pub width: Option<u32>,
+    /// Target height in pixels. Derived from the aspect ratio when only the width is set.
+    pub height: Option<u32>,
+    /// Upper bound for the longest side when the image is scaled down.
+    pub max_side: Option<u32>,
+    /// Dimensions are rounded to a multiple of this value (usually 8 or 64).
+    pub multiple_of: u32,
+    /// Strategy used to fit the image into the target dimensions.
+    pub fit: Fit,
+    /// Output encoding. Defaults to JPEG for JPEG sources and PNG for everything else.
+    pub format: Option<OutputFormat>,
+    /// Background color used when flattening alpha and padding.
+    pub background: [u8; 3],
+    /// Quality used for JPEG encoding.
+    pub jpeg_quality: u8,
+}
+
+impl Default for Preprocessor {
+    fn default() -> Self {
+        Self {
+            width: None,
+            height: None,
+            max_side: Some(1024),
+            multiple_of: 64,
+            fit: Fit::default(),
+            format: None,
+            background: [255, 255, 255],
+            jpeg_quality: 95,
+        }
+    }
+}
+
+/// An image produced by a [`Preprocessor`].
+#[derive(Debug, Clone)]
+pub struct PreprocessedImage {
+    /// Encoded image bytes.
+    pub data: Vec<u8>,
+    /// Width of the encoded image.
+    pub width: u32,
+    /// Height of the encoded image.
+    pub height: u32,
+    /// Encoding of the image bytes.
+    pub format: OutputFormat,
+}
+
+impl PreprocessedImage {
+    /// Encodes the image bytes as a base64 string, as expected by the GetImg API.
+    pub fn to_base64(&self) -> String {
+        STANDARD.encode(&self.data)
+    }
+}
+
+impl Preprocessor {
+    /// Creates a preprocessor targeting the given dimensions with default settings.
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - Target width in pixels.
+    /// * `height` - Target height in pixels.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use getimg::preprocess::{Fit, Preprocessor};
+    ///
+    /// let preprocessor = Preprocessor {
+    ///     fit: Fit::Pad,
+    ///     ..Preprocessor::new(512, 512)
+    /// };
+    /// ```
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width: Some(width),
+            height: Some(height),
+            ..Self::default()
+        }
+    }
+
+    /// Preprocesses an encoded image.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - The encoded source image (PNG, JPEG, WebP, BMP, TIFF or GIF).
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the preprocessed image, or an error if the image cannot be decoded or encoded.
+    pub fn process(&self, bytes: &[u8]) -> Result<PreprocessedImage> {
+        let (image, source_format) = decode_with_format(bytes)?;
+        let flattened = self.flatten(&image);
+        let (width, height) = self.target_dimensions(flattened.width(), flattened.height());
+        let fitted = self.fit(flattened, width, height);
+
+        let format = self.format.unwrap_or(match source_format {
+            Some(ImageFormat::Jpeg) => OutputFormat::Jpeg,
+            _ => OutputFormat::Png,
+        });
+
+        let mut data = Vec::new();
+        match format {
+            OutputFormat::Png => fitted.write_with_encoder(PngEncoder::new(&mut data))?,
+            OutputFormat::Jpeg => fitted
+                .write_with_encoder(JpegEncoder::new_with_quality(&mut data, self.jpeg_quality))?,
+        }
+
+        Ok(PreprocessedImage {
+            data,
+            width,
+            height,
+            format,
+        })
+    }
+
+    /// Loads an image from the given path, preprocesses it and encodes it as a base64 string.
+    ///
+    /// # Arguments
+    ///
+    /// * `image_path` - A string slice representing the path to the image file.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the base64-encoded string on success, or an error if the file cannot be read or processed.
+    pub fn load_and_encode(&self, image_path: &str) -> Result<String> {
+        let bytes = fs::read(image_path)?;
+        Ok(self.process(&bytes)?.to_base64())
+    }
+
+    /// Computes the output dimensions for a source image of the given size.
+    ///
+    /// # Arguments
+    ///
+    /// * `source_width` - Width of the source image after orientation.
+    /// * `source_height` - Height of the source image after orientation.
+    ///
+    /// # Returns
+    ///
+    /// The target `(width, height)`, scaled down to `max_side` and snapped to `multiple_of`.
+    pub fn target_dimensions(&self, source_width: u32, source_height: u32) -> (u32, u32) {
+        let aspect = source_width as f64 / source_height.max(1) as f64;
+        let (mut width, mut height) = match (self.width, self.height) {
+            (Some(width), Some(height)) => (width as f64, height as f64),
+            (Some(width), None) => (width as f64, width as f64 / aspect),
+            (None, Some(height)) => (height as f64 * aspect, height as f64),
+            (None, None) => (source_width as f64, source_height as f64),
+        };
+
+        if let Some(max_side) = self.max_side {
+            let longest = width.max(height);
+            if longest > max_side as f64 {
+                let scale = max_side as f64 / longest;
+                width *= scale;
+                height *= scale;
+            }
+        }
+
+        (self.snap(width), self.snap(height))
+    }
+
+    fn snap(&self, value: f64) -> u32 {
+        let multiple = self.multiple_of.max(1) as f64;
+        ((value / multiple).round() * multiple).max(multiple) as u32
+    }
+
+    fn flatten(&self, image: &DynamicImage) -> RgbImage {
+        if !image.color().has_alpha() {
+            return image.to_rgb8();
+        }
+
+        let background = self.background.map(|channel| channel as f32);
+        let rgba = image.to_rgba8();
+        RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+            let [r, g, b, a] = rgba.get_pixel(x, y).0;
+            let alpha = a as f32 / 255.0;
+            let blend = |channel: u8, background: f32| {
+                (channel as f32 * alpha + background * (1.0 - alpha)).round() as u8
+            };
+            Rgb([
+                blend(r, background[0]),
+                blend(g, background[1]),
+                blend(b, background[2]),
+            ])
+        })
+    }
+
+    fn fit(&self, image: RgbImage, width: u32, height: u32) -> DynamicImage {
+        if image.dimensions() == (width, height) {
+            return DynamicImage::ImageRgb8(image);
+        }
+
+        let image = DynamicImage::ImageRgb8(image);
+        match self.fit {
+            Fit::Resize => image.resize_exact(width, height, FilterType::Lanczos3),
+            Fit::Crop => image.resize_to_fill(width, height, FilterType::Lanczos3),
+            Fit::Pad => {
+                let scaled = image.resize(width, height, FilterType::Lanczos3).to_rgb8();
+                let mut canvas = RgbImage::from_pixel(width, height, Rgb(self.background));
+                let x = (width - scaled.width()) / 2;
+                let y = (height - scaled.height()) / 2;
+                imageops::overlay(&mut canvas, &scaled, x as i64, y as i64);
+                DynamicImage::ImageRgb8(canvas)
+            }
+        }
+    }
+}
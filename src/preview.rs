@@ -0,0 +1,310 @@
+//! This module contains the inline terminal previews of images, printed by `--preview` after an
+//! image is saved and by `getimg view`.
+//!
+//! Images are drawn with the graphics protocol of the terminal when it is known to support one:
+//!
+//! | Protocol | Terminals                                            |
+//! |----------|------------------------------------------------------|
+//! | `kitty`  | kitty, Ghostty                                       |
+//! | `iterm`  | iTerm2, WezTerm                                      |
+//! | `sixel`  | foot, mlterm, and terminals whose `TERM` names sixel |
+//! | `blocks` | any terminal with 24-bit colors                      |
+//!
+//! `blocks` draws two pixels per character cell with the `▀` half block, and is used inside tmux
+//! and screen, which do not pass graphics through, and over SSH to unknown terminals.
+
+use anyhow::{Context, Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat, RgbImage};
+use std::io::{Cursor, Write};
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// Width of previews in character cells when `COLUMNS` is unset.
+pub const DEFAULT_COLUMNS: u32 = 80;
+
+/// Assumed width of a character cell in pixels, used to size sixel images.
+const CELL_WIDTH: u32 = 10;
+
+/// Size of the base64 chunks of the kitty graphics protocol.
+const KITTY_CHUNK: usize = 4096;
+
+/// Protocol of the previews printed after saving, or 0 when nothing is previewed.
+static PREVIEW: AtomicU8 = AtomicU8::new(0);
+
+/// Protocol used to draw images in the terminal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Protocol {
+    /// Detect the protocol from the environment, see [`Protocol::detect`].
+    Auto,
+    /// The kitty graphics protocol.
+    Kitty,
+    /// iTerm2 inline images.
+    Iterm,
+    /// Sixel graphics.
+    Sixel,
+    /// ANSI half blocks with 24-bit colors.
+    Blocks,
+}
+
+impl Protocol {
+    /// Returns the protocol supported by the current terminal, from the `TERM`, `TERM_PROGRAM`,
+    /// `LC_TERMINAL`, `KITTY_WINDOW_ID` and `TMUX` environment variables.
+    pub fn detect() -> Self {
+        Self::detect_from(|name| std::env::var(name).ok())
+    }
+
+    /// Returns the protocol supported by a terminal described by environment variables.
+    ///
+    /// # Arguments
+    ///
+    /// * `var` - Returns the value of an environment variable, if set.
+    pub fn detect_from(var: impl Fn(&str) -> Option<String>) -> Self {
+        let term = var("TERM").unwrap_or_default();
+        let program = var("TERM_PROGRAM").unwrap_or_default();
+        // Multiplexers do not pass graphics through to the outer terminal.
+        if var("TMUX").is_some() || term.starts_with("screen") || term.starts_with("tmux") {
+            return Self::Blocks;
+        }
+        if var("KITTY_WINDOW_ID").is_some() || term == "xterm-kitty" || program == "ghostty" {
+            Self::Kitty
+        } else if program == "iTerm.app"
+            || program == "WezTerm"
+            || var("LC_TERMINAL").as_deref() == Some("iTerm2")
+        {
+            Self::Iterm
+        } else if term.contains("sixel") || term.starts_with("foot") || term.starts_with("mlterm") {
+            Self::Sixel
+        } else {
+            Self::Blocks
+        }
+    }
+
+    /// Returns the protocol itself, or the detected one for [`Protocol::Auto`].
+    pub fn resolve(self) -> Self {
+        match self {
+            Self::Auto => Self::detect(),
+            protocol => protocol,
+        }
+    }
+}
+
+/// Enables or disables the preview of saved images.
+///
+/// # Arguments
+///
+/// * `preview` - Protocol of the previews, or `None` to disable them.
+pub fn set_preview(preview: Option<Protocol>) {
+    let value = match preview {
+        None => 0,
+        Some(Protocol::Auto) => 1,
+        Some(Protocol::Kitty) => 2,
+        Some(Protocol::Iterm) => 3,
+        Some(Protocol::Sixel) => 4,
+        Some(Protocol::Blocks) => 5,
+    };
+    PREVIEW.store(value, Ordering::Relaxed);
+}
+
+/// Returns the protocol of the previews of saved images, if enabled.
+pub fn preview() -> Option<Protocol> {
+    match PREVIEW.load(Ordering::Relaxed) {
+        1 => Some(Protocol::Auto),
+        2 => Some(Protocol::Kitty),
+        3 => Some(Protocol::Iterm),
+        4 => Some(Protocol::Sixel),
+        5 => Some(Protocol::Blocks),
+        _ => None,
+    }
+}
+
+/// Returns the width of the terminal in character cells, from `COLUMNS`.
+pub fn columns() -> u32 {
+    std::env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.trim().parse().ok())
+        .filter(|&columns| columns > 0)
+        .unwrap_or(DEFAULT_COLUMNS)
+}
+
+/// Renders an image as the escape sequences or characters drawing it in a terminal.
+///
+/// # Arguments
+///
+/// * `image` - The image.
+/// * `protocol` - The protocol, see [`Protocol::resolve`] for [`Protocol::Auto`].
+/// * `columns` - Width of the preview in character cells. Images are never enlarged.
+///
+/// # Returns
+///
+/// A `Result` containing the bytes to write to the terminal, ending with a newline.
+pub fn render(image: &DynamicImage, protocol: Protocol, columns: u32) -> Result<Vec<u8>> {
+    let columns = columns.max(1);
+    match protocol.resolve() {
+        Protocol::Kitty => {
+            let png = encode_png(&fit(image, columns * CELL_WIDTH))?;
+            let cells = columns.min(image.width().div_ceil(CELL_WIDTH));
+            Ok(kitty(&STANDARD.encode(png), cells))
+        }
+        Protocol::Iterm => {
+            let png = encode_png(&fit(image, columns * CELL_WIDTH))?;
+            let cells = columns.min(image.width().div_ceil(CELL_WIDTH));
+            Ok(format!(
+                "\x1b]1337;File=inline=1;size={};width={};preserveAspectRatio=1:{}\x07\n",
+                png.len(),
+                cells,
+                STANDARD.encode(&png)
+            )
+            .into_bytes())
+        }
+        Protocol::Sixel => Ok(sixel(&fit(image, columns * CELL_WIDTH).to_rgb8())),
+        Protocol::Blocks | Protocol::Auto => Ok(blocks(&fit(image, columns).to_rgb8())),
+    }
+}
+
+/// Draws an image file in the terminal.
+///
+/// # Arguments
+///
+/// * `bytes` - The encoded image.
+/// * `protocol` - The protocol.
+/// * `columns` - Width of the preview in character cells.
+/// * `out` - The terminal.
+pub fn show(bytes: &[u8], protocol: Protocol, columns: u32, out: &mut impl Write) -> Result<()> {
+    let image = image::load_from_memory(bytes).context("failed to decode the image")?;
+    out.write_all(&render(&image, protocol, columns)?)?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Scales an image down to a maximum width, keeping its aspect ratio.
+fn fit(image: &DynamicImage, width: u32) -> DynamicImage {
+    if image.width() <= width {
+        return image.clone();
+    }
+    let height = (image.height() as u64 * width as u64 / image.width() as u64).max(1) as u32;
+    image.resize_exact(width, height, FilterType::Triangle)
+}
+
+/// Encodes an image as PNG.
+fn encode_png(image: &DynamicImage) -> Result<Vec<u8>> {
+    let mut png = Vec::new();
+    image.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)?;
+    Ok(png)
+}
+
+/// Transmits and displays a base64 PNG with the kitty graphics protocol, in chunks.
+fn kitty(data: &str, cells: u32) -> Vec<u8> {
+    let chunks = data.as_bytes().chunks(KITTY_CHUNK).collect::<Vec<_>>();
+    let mut out = Vec::new();
+    for (index, chunk) in chunks.iter().enumerate() {
+        let more = u8::from(index + 1 < chunks.len());
+        if index == 0 {
+            out.extend_from_slice(format!("\x1b_Gf=100,a=T,c={},m={};", cells, more).as_bytes());
+        } else {
+            out.extend_from_slice(format!("\x1b_Gm={};", more).as_bytes());
+        }
+        out.extend_from_slice(chunk);
+        out.extend_from_slice(b"\x1b\\");
+    }
+    out.push(b'\n');
+    out
+}
+
+/// Draws two rows of pixels per line with upper half blocks, the upper pixel in the foreground
+/// color and the lower one in the background color.
+fn blocks(image: &RgbImage) -> Vec<u8> {
+    let mut out = String::new();
+    for y in (0..image.height()).step_by(2) {
+        for x in 0..image.width() {
+            let [r, g, b] = image.get_pixel(x, y).0;
+            if y + 1 < image.height() {
+                let [lr, lg, lb] = image.get_pixel(x, y + 1).0;
+                out.push_str(&format!(
+                    "\x1b[38;2;{};{};{};48;2;{};{};{}m▀",
+                    r, g, b, lr, lg, lb
+                ));
+            } else {
+                out.push_str(&format!("\x1b[38;2;{};{};{}m▀", r, g, b));
+            }
+        }
+        out.push_str("\x1b[0m\n");
+    }
+    out.into_bytes()
+}
+
+/// Encodes an image as sixels with a 6×6×6 color cube palette.
+fn sixel(image: &RgbImage) -> Vec<u8> {
+    let level = |value: u8| (value as u16 * 5 + 127) / 255;
+    let indices = image
+        .pixels()
+        .map(|pixel| {
+            let [r, g, b] = pixel.0;
+            (level(r) * 36 + level(g) * 6 + level(b)) as usize
+        })
+        .collect::<Vec<_>>();
+    let (width, height) = (image.width() as usize, image.height() as usize);
+
+    let mut out = format!("\x1bPq\"1;1;{};{}", width, height);
+    for index in 0..216 {
+        let percent = |step: usize| step * 100 / 5;
+        out.push_str(&format!(
+            "#{};2;{};{};{}",
+            index,
+            percent(index / 36),
+            percent(index / 6 % 6),
+            percent(index % 6)
+        ));
+    }
+    for top in (0..height).step_by(6) {
+        let rows = (top..height.min(top + 6)).collect::<Vec<_>>();
+        let mut colors = rows
+            .iter()
+            .flat_map(|&y| indices[y * width..(y + 1) * width].iter().copied())
+            .collect::<Vec<_>>();
+        colors.sort_unstable();
+        colors.dedup();
+        for (position, &color) in colors.iter().enumerate() {
+            if position > 0 {
+                out.push('$');
+            }
+            out.push_str(&format!("#{}", color));
+            let bits = (0..width).map(|x| {
+                rows.iter().enumerate().fold(0u8, |bits, (bit, &y)| {
+                    bits | (u8::from(indices[y * width + x] == color) << bit)
+                })
+            });
+            push_runs(&mut out, bits);
+        }
+        out.push('-');
+    }
+    out.push_str("\x1b\\\n");
+    out.into_bytes()
+}
+
+/// Appends sixel characters, compressing runs of the same one with `!`.
+fn push_runs(out: &mut String, bits: impl Iterator<Item = u8>) {
+    let mut run: Option<(u8, usize)> = None;
+    let flush = |out: &mut String, (bits, count): (u8, usize)| {
+        let character = (b'?' + bits) as char;
+        if count > 3 {
+            out.push_str(&format!("!{}{}", count, character));
+        } else {
+            out.extend(std::iter::repeat_n(character, count));
+        }
+    };
+    for bits in bits {
+        run = match run {
+            Some((current, count)) if current == bits => Some((current, count + 1)),
+            Some(previous) => {
+                flush(out, previous);
+                Some((bits, 1))
+            }
+            None => Some((bits, 1)),
+        };
+    }
+    if let Some(last) = run {
+        flush(out, last);
+    }
+}
@@ -0,0 +1,185 @@
+//! This module contains provenance labeling of generated images.
+//!
+//! Labeled images carry the IPTC `DigitalSourceType` property set to `trainedAlgorithmicMedia`
+//! and a minimal C2PA-style manifest, both stored in the XMP packet of PNG and JPEG files.
+
+use anyhow::Result;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::time::SystemTime;
+
+use crate::metadata::{self, xml_escape, xml_unescape, GETIMG_NAMESPACE};
+use crate::request::GenerationRequest;
+use crate::utils::rfc3339;
+
+/// IPTC digital source type of media created by a trained algorithmic model.
+pub const TRAINED_ALGORITHMIC_MEDIA: &str =
+    "http://cv.iptc.org/newscodes/digitalsourcetype/trainedAlgorithmicMedia";
+
+const IPTC_EXT_NAMESPACE: &str = "http://iptc.org/std/Iptc4xmpExt/2008-02-29/";
+const SOURCE_TYPE_TAG: &str = "Iptc4xmpExt:DigitalSourceType";
+const MANIFEST_TAG: &str = "getimg:manifest";
+
+/// Struct representing an input image that contributed to a generated image.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Ingredient {
+    /// Request field the input was sent as, e.g. `image` or `mask_image`.
+    pub name: String,
+    /// Relationship of the input to the output, following C2PA naming.
+    pub relationship: String,
+    /// Hash algorithm used for `hash`.
+    pub alg: String,
+    /// Hex-encoded hash of the decoded input image.
+    pub hash: String,
+}
+
+/// Struct representing a minimal C2PA-style manifest describing how an image was produced.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Software and version that produced the claim.
+    pub claim_generator: String,
+    /// Name of the generator.
+    pub generator: String,
+    /// Model used for generation.
+    pub model: String,
+    /// API endpoint path the request was sent to.
+    pub endpoint: String,
+    /// C2PA action, `c2pa.created` for text-to-image and `c2pa.edited` otherwise.
+    pub action: String,
+    /// IPTC digital source type.
+    pub digital_source_type: String,
+    /// RFC 3339 timestamp of the generation.
+    pub created: String,
+    /// Input images the output was derived from.
+    pub ingredients: Vec<Ingredient>,
+}
+
+impl Manifest {
+    /// Builds the manifest for a request, hashing its input images.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The request sent to the API, including its image data.
+    pub fn new(request: &GenerationRequest) -> Self {
+        let ingredients = request
+            .images()
+            .into_iter()
+            .map(|(name, data)| {
+                let decoded = STANDARD.decode(data);
+                let bytes = decoded.as_deref().unwrap_or(data.as_bytes());
+                Ingredient {
+                    name: name.to_string(),
+                    relationship: "inputTo".to_string(),
+                    alg: "sha256".to_string(),
+                    hash: format!("{:x}", Sha256::digest(bytes)),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        Self {
+            claim_generator: format!("getimg/{}", env!("CARGO_PKG_VERSION")),
+            generator: "getimg".to_string(),
+            model: request.model().to_string(),
+            endpoint: request.endpoint().to_string(),
+            action: if ingredients.is_empty() {
+                "c2pa.created".to_string()
+            } else {
+                "c2pa.edited".to_string()
+            },
+            digital_source_type: TRAINED_ALGORITHMIC_MEDIA.to_string(),
+            created: rfc3339(SystemTime::now()),
+            ingredients,
+        }
+    }
+}
+
+/// Struct representing the provenance label found in an image.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Verification {
+    /// The IPTC digital source type, if present.
+    pub digital_source_type: Option<String>,
+    /// The embedded manifest, if present.
+    pub manifest: Option<Manifest>,
+}
+
+impl Verification {
+    /// Returns whether the image is labeled as created by a trained algorithmic model.
+    pub fn is_labeled(&self) -> bool {
+        self.digital_source_type.as_deref() == Some(TRAINED_ALGORITHMIC_MEDIA)
+    }
+}
+
+/// Labels an encoded image as AI-generated.
+///
+/// Any previous label is replaced, other XMP properties are preserved.
+///
+/// # Arguments
+///
+/// * `bytes` - The encoded image.
+/// * `manifest` - The manifest to embed.
+///
+/// # Returns
+///
+/// A `Result` containing the labeled image. Formats other than PNG and JPEG are returned unchanged.
+pub fn label(bytes: &[u8], manifest: &Manifest) -> Result<Vec<u8>> {
+    let description = format!(
+        concat!(
+            "<rdf:Description rdf:about=\"\"\n",
+            "    xmlns:Iptc4xmpExt=\"{}\"\n",
+            "    xmlns:getimg=\"{}\">\n",
+            "   <{}>{}</{}>\n",
+            "   <{}>{}</{}>\n",
+            "  </rdf:Description>"
+        ),
+        IPTC_EXT_NAMESPACE,
+        GETIMG_NAMESPACE,
+        SOURCE_TYPE_TAG,
+        xml_escape(&manifest.digital_source_type),
+        SOURCE_TYPE_TAG,
+        MANIFEST_TAG,
+        xml_escape(&serde_json::to_string(manifest)?),
+        MANIFEST_TAG,
+    );
+    metadata::write_xmp_description(bytes, SOURCE_TYPE_TAG, &description)
+}
+
+/// Reads the provenance label of an encoded image.
+///
+/// # Arguments
+///
+/// * `bytes` - The encoded image.
+///
+/// # Returns
+///
+/// A `Result` containing the label found in the image, or an error if the image or manifest is malformed.
+///
+/// # Examples
+///
+/// ```no_run
+/// use getimg::provenance::verify;
+///
+/// let bytes = std::fs::read("t2i.png").unwrap();
+/// if verify(&bytes).unwrap().is_labeled() {
+///     println!("t2i.png is labeled as AI-generated");
+/// }
+/// ```
+pub fn verify(bytes: &[u8]) -> Result<Verification> {
+    let Some(xmp) = metadata::read_xmp(bytes)? else {
+        return Ok(Verification {
+            digital_source_type: None,
+            manifest: None,
+        });
+    };
+    let digital_source_type =
+        metadata::xml_element_text(&xmp, SOURCE_TYPE_TAG).map(|value| xml_unescape(value.trim()));
+    let manifest = match metadata::xml_element_text(&xmp, MANIFEST_TAG) {
+        Some(json) => Some(serde_json::from_str(&xml_unescape(&json))?),
+        None => None,
+    };
+    Ok(Verification {
+        digital_source_type,
+        manifest,
+    })
+}
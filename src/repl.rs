@@ -0,0 +1,472 @@
+//! This module contains the interactive session of `getimg repl`.
+//!
+//! A session keeps a model and generation settings between commands, and a stack of images. A
+//! generation command is a subcommand followed by its prompt; `t2i` starts from text while `i2i`,
+//! `edit`, `cnet` and `paint` transform the latest image, so results can be refined step by step:
+//!
+//! ```text
+//! getimg> t2i A lighthouse at dawn.
+//! getimg> :set steps 30
+//! getimg> i2i Make it snowy.
+//! getimg> :undo
+//! getimg> cnet A watercolor lighthouse.
+//! ```
+//!
+//! Omitting the prompt reuses the previous one. Lines starting with `:` manage the session:
+//!
+//! | Command                 | Effect                                                        |
+//! |-------------------------|---------------------------------------------------------------|
+//! | `:set <name> <value>`   | Sets a generation setting, e.g. `:set guidance 7.5`.          |
+//! | `:unset <name>`         | Restores the default of a setting.                            |
+//! | `:show`                 | Prints the settings.                                          |
+//! | `:open <path>`          | Continues from an existing image.                             |
+//! | `:undo`                 | Goes back to the previous image.                              |
+//! | `:history`              | Lists the images of the session.                              |
+//! | `:save [path]`          | Writes the transcript of the session as JSONL.                |
+//! | `:help`                 | Lists the commands.                                           |
+//! | `:quit`                 | Ends the session, like Ctrl-D.                                |
+//!
+//! Settings are the options of batch jobs (see [`crate::batch`]): `model`, `seed`,
+//! `negative_prompt`, `width`, `height`, `steps`, `guidance`, `scheduler`, `strength`,
+//! `image_guidance`, `output_format`, `net`, `mask_image` and `output`. Unset settings fall back
+//! to the configuration profile and then to the model defaults, and a random seed is picked for
+//! every generation unless `seed` is set. With `--dry-run` the requests are printed instead of
+//! sent, and the image stack is left unchanged.
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Serialize;
+use serde_json::Value;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use crate::batch::{Job, JobKind};
+use crate::cli::{Postprocess, PreprocessArgs};
+use crate::client::Client;
+use crate::config::Profile;
+use crate::report::{dry_run, status, OutputRecord};
+
+/// Transcript path used by `:save` when none is given.
+pub const TRANSCRIPT_PATH: &str = "getimg-session.jsonl";
+
+/// Settings that are part of a generation command rather than of the session.
+const COMMAND_FIELDS: [&str; 4] = ["type", "id", "prompt", "image"];
+
+/// Help printed by `:help`.
+const HELP: &str = "\
+Generation commands, run on the latest image except for t2i:
+  t2i <prompt>            Generate an image from text.
+  i2i <prompt>            Transform the latest image.
+  edit <prompt>           Edit the latest image following an instruction.
+  cnet <prompt>           Generate an image conditioned on the latest image (:set net first).
+  paint <prompt>          Repaint the latest image inside a mask (:set mask_image first).
+  Omitting the prompt reuses the previous one.
+
+Session commands:
+  :set <name> <value>     Set model, seed, negative_prompt, width, height, steps, guidance,
+                          scheduler, strength, image_guidance, output_format, net, mask_image
+                          or output.
+  :unset <name>           Restore the default of a setting.
+  :show                   Print the settings.
+  :open <path>            Continue from an existing image.
+  :undo                   Go back to the previous image.
+  :history                List the images of the session.
+  :save [path]            Write the transcript of the session as JSONL.
+  :help                   Print this help.
+  :quit                   End the session.";
+
+/// Struct representing an image of the session.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SessionImage {
+    /// Path of the image.
+    pub path: PathBuf,
+    /// Command line that produced or opened the image.
+    pub input: String,
+}
+
+/// Struct representing one line of the session transcript.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct TranscriptEntry {
+    /// The line as typed.
+    pub input: String,
+    /// The generation the line ran, with every setting in effect.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub job: Option<Job>,
+    /// Path of the generated image.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    /// Seed of the generation.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<usize>,
+    /// Cost of the generation.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cost: Option<f64>,
+    /// Time spent waiting for the API, in milliseconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u64>,
+    /// Error message of a failed line.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Enum representing what a line of the session did.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    /// The line changed or printed the session.
+    Done,
+    /// The line generated and saved an image.
+    Generated(OutputRecord),
+    /// The line ended the session.
+    Quit,
+}
+
+/// Struct representing an interactive session.
+#[derive(Debug, Clone)]
+pub struct Session {
+    /// Client sending the requests.
+    pub client: Client,
+    /// Model used unless the `model` setting is set.
+    pub model: String,
+    /// Configuration profile providing defaults.
+    pub profile: Profile,
+    /// How input images are prepared before upload.
+    pub preprocess: PreprocessArgs,
+    /// How generated images are finished and saved.
+    pub postprocess: Postprocess,
+    /// Settings of the session, as a job whose type, prompt and image are ignored.
+    pub settings: Job,
+    /// Images of the session, the latest last.
+    pub images: Vec<SessionImage>,
+    /// Lines run so far.
+    pub transcript: Vec<TranscriptEntry>,
+    /// Prompt of the previous generation.
+    pub prompt: Option<String>,
+}
+
+impl Session {
+    /// Creates a session without settings or images.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - Client sending the requests.
+    /// * `model` - Model used unless the `model` setting is set.
+    /// * `profile` - Configuration profile providing defaults.
+    /// * `preprocess` - How input images are prepared before upload.
+    /// * `postprocess` - How generated images are finished and saved.
+    pub fn new(
+        client: Client,
+        model: &str,
+        profile: Profile,
+        preprocess: PreprocessArgs,
+        postprocess: Postprocess,
+    ) -> Self {
+        Self {
+            client,
+            model: model.to_string(),
+            profile,
+            preprocess,
+            postprocess,
+            settings: settings_job(),
+            images: Vec::new(),
+            transcript: Vec::new(),
+            prompt: None,
+        }
+    }
+
+    /// Returns the latest image, which generation commands other than `t2i` start from.
+    pub fn current(&self) -> Option<&SessionImage> {
+        self.images.last()
+    }
+
+    /// Runs one line, recording it in the transcript.
+    ///
+    /// # Arguments
+    ///
+    /// * `line` - The line, a generation command or a `:` session command.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing what the line did, or the error it failed with. The session stays
+    /// usable after an error.
+    pub async fn execute(&mut self, line: &str) -> Result<Outcome> {
+        let line = line.trim();
+        if line.is_empty() {
+            return Ok(Outcome::Done);
+        }
+        let mut entry = TranscriptEntry {
+            input: line.to_string(),
+            ..TranscriptEntry::default()
+        };
+        let result = match line.strip_prefix(':') {
+            Some(command) => self.command(command),
+            None => self.generate(line, &mut entry).await,
+        };
+        if let Err(err) = &result {
+            entry.error = Some(format!("{:#}", err));
+        }
+        self.transcript.push(entry);
+        result
+    }
+
+    /// Sets a generation setting, named as a job option with `-` or `_`.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the setting, e.g. `steps`.
+    /// * `value` - The value, parsed as the type of the setting.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<()> {
+        let name = setting_name(name)?;
+        let mut settings = serde_json::to_value(&self.settings)?;
+        let number = serde_json::from_str::<Value>(value)
+            .ok()
+            .filter(Value::is_number);
+        for candidate in number.into_iter().chain([Value::from(value)]) {
+            settings[&name] = candidate;
+            if let Ok(job) = serde_json::from_value::<Job>(settings.clone()) {
+                self.settings = job;
+                return Ok(());
+            }
+        }
+        bail!("invalid value `{}` for {}", value, name)
+    }
+
+    /// Restores the default of a generation setting.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the setting, e.g. `steps`.
+    pub fn unset(&mut self, name: &str) -> Result<()> {
+        let name = setting_name(name)?;
+        let mut settings = serde_json::to_value(&self.settings)?;
+        settings[&name] = Value::Null;
+        self.settings = serde_json::from_value(settings)?;
+        Ok(())
+    }
+
+    /// Writes the transcript as one JSON object per line.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the transcript file, overwritten if it exists.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut file =
+            File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+        for entry in &self.transcript {
+            writeln!(file, "{}", serde_json::to_string(entry)?)
+                .with_context(|| format!("failed to write {}", path.display()))?;
+        }
+        Ok(())
+    }
+
+    fn command(&mut self, command: &str) -> Result<Outcome> {
+        let (name, rest) = split_word(command);
+        match name {
+            "set" if rest.is_empty() => self.show()?,
+            "set" => {
+                let (setting, value) = split_word(rest);
+                if value.is_empty() {
+                    bail!("usage: :set <name> <value>");
+                }
+                self.set(setting, value)?;
+            }
+            "unset" if rest.is_empty() => bail!("usage: :unset <name>"),
+            "unset" => self.unset(rest)?,
+            "show" => self.show()?,
+            "open" if rest.is_empty() => bail!("usage: :open <path>"),
+            "open" => {
+                let path = PathBuf::from(rest);
+                if !path.is_file() {
+                    bail!("{} is not a file", path.display());
+                }
+                status!("Continuing from {}.", path.display());
+                self.images.push(SessionImage {
+                    path,
+                    input: format!(":{}", command),
+                });
+            }
+            "undo" => {
+                if self.images.pop().is_none() {
+                    bail!("there is no image to undo");
+                }
+                match self.current() {
+                    Some(image) => status!("Back to {}.", image.path.display()),
+                    None => status!("Back to no image."),
+                }
+            }
+            "history" => {
+                for (index, image) in self.images.iter().enumerate() {
+                    println!(
+                        "{:>3}  {}  {}",
+                        index + 1,
+                        image.path.display(),
+                        image.input
+                    );
+                }
+            }
+            "save" => {
+                let path = PathBuf::from(if rest.is_empty() {
+                    TRANSCRIPT_PATH
+                } else {
+                    rest
+                });
+                self.save(&path)?;
+                status!("Transcript saved as: {}", path.display());
+            }
+            "help" => println!("{}", HELP),
+            "quit" | "q" | "exit" => return Ok(Outcome::Quit),
+            _ => bail!("unknown command `:{}`, see :help", name),
+        }
+        Ok(Outcome::Done)
+    }
+
+    fn show(&self) -> Result<()> {
+        let Value::Object(settings) = serde_json::to_value(&self.settings)? else {
+            unreachable!("jobs serialize to objects");
+        };
+        for (name, value) in settings {
+            if COMMAND_FIELDS.contains(&name.as_str()) {
+                continue;
+            }
+            let value = match value {
+                Value::Null if name == "model" => format!("{} (default)", self.model),
+                Value::Null => "default".to_string(),
+                Value::String(value) => value,
+                value => value.to_string(),
+            };
+            println!("{:<16} {}", name, value);
+        }
+        Ok(())
+    }
+
+    async fn generate(&mut self, line: &str, entry: &mut TranscriptEntry) -> Result<Outcome> {
+        let (command, prompt) = split_word(line);
+        let kind: JobKind = serde_json::from_value(Value::from(command))
+            .map_err(|_| anyhow!("unknown command `{}`, see :help", command))?;
+        let prompt = match prompt {
+            "" => self
+                .prompt
+                .clone()
+                .ok_or_else(|| anyhow!("usage: {} <prompt>", command))?,
+            prompt => prompt.to_string(),
+        };
+        let image = match kind {
+            JobKind::TextToImage => None,
+            _ => Some(self.current().ok_or_else(|| {
+                anyhow!(
+                    "`{}` starts from the latest image, run t2i or :open an image first",
+                    command
+                )
+            })?),
+        };
+        if kind == JobKind::Repaint && self.settings.mask_image.is_none() {
+            bail!("`paint` needs a mask, set one with :set mask_image <path>");
+        }
+        let job = Job {
+            kind,
+            prompt: prompt.clone(),
+            image: image.map(|image| image.path.to_string_lossy().into_owned()),
+            ..self.settings.clone()
+        };
+        self.prompt = Some(prompt);
+        entry.job = Some(job.clone());
+
+        let (task, model, profile, preprocess) = (
+            job.clone(),
+            self.model.clone(),
+            self.profile.clone(),
+            self.preprocess.clone(),
+        );
+        let request = tokio::task::spawn_blocking(move || {
+            task.request(&model, &profile, &preprocess, Path::new(""))
+        })
+        .await??;
+        if let Some(format) = dry_run() {
+            println!(
+                "{}",
+                format.render(&self.client.prepare(&request)?.redacted())
+            );
+            return Ok(Outcome::Done);
+        }
+        let index = self
+            .transcript
+            .iter()
+            .filter(|entry| entry.path.is_some())
+            .count();
+        let started = Instant::now();
+        let response = job.client(&self.client).send(&request).await?;
+        entry.latency_ms = Some(started.elapsed().as_millis() as u64);
+        let seed = response.seed.or(request.seed());
+        let saved = self
+            .postprocess
+            .save(&request, &response, job.output.as_deref(), index)?;
+        if let Some(seed) = seed {
+            status!("Seed {}.", seed);
+        }
+
+        entry.path = Some(saved.path.clone());
+        entry.seed = seed;
+        entry.cost = response.cost;
+        self.images.push(SessionImage {
+            path: saved.path.clone(),
+            input: line.to_string(),
+        });
+        Ok(Outcome::Generated(OutputRecord {
+            index,
+            path: Some(saved.path),
+            seed,
+            cost: response.cost,
+            error: None,
+        }))
+    }
+}
+
+/// Returns the path of the line history shared by sessions, in the local data directory.
+pub fn history_path() -> Option<PathBuf> {
+    dirs::data_local_dir().map(|dir| dir.join("getimg").join("repl_history.txt"))
+}
+
+/// Returns a job without any option set, holding the settings of a new session.
+fn settings_job() -> Job {
+    Job {
+        kind: JobKind::TextToImage,
+        id: None,
+        prompt: String::new(),
+        model: None,
+        image: None,
+        mask_image: None,
+        seed: None,
+        output: None,
+        negative_prompt: None,
+        width: None,
+        height: None,
+        steps: None,
+        guidance: None,
+        scheduler: None,
+        strength: None,
+        image_guidance: None,
+        output_format: None,
+        net: None,
+    }
+}
+
+/// Returns the job field of a setting, accepting `-` in place of `_`.
+fn setting_name(name: &str) -> Result<String> {
+    let name = name.replace('-', "_");
+    let Value::Object(settings) = serde_json::to_value(settings_job())? else {
+        unreachable!("jobs serialize to objects");
+    };
+    if !settings.contains_key(&name) || COMMAND_FIELDS.contains(&name.as_str()) {
+        bail!("unknown setting `{}`, see :help", name);
+    }
+    Ok(name)
+}
+
+/// Splits the first word off a line, returning it and the trimmed rest.
+fn split_word(line: &str) -> (&str, &str) {
+    let line = line.trim();
+    match line.split_once(char::is_whitespace) {
+        Some((word, rest)) => (word, rest.trim()),
+        None => (line, ""),
+    }
+}
@@ -0,0 +1,366 @@
+//! This module contains the machine-readable reporting of CLI runs: the JSON object printed by
+//! `--json`, the error categories mapped to process exit codes, and the switches silencing
+//! progress messages for `--quiet` and printing requests instead of sending them for
+//! `--dry-run`.
+//!
+//! | Code | Category          | Meaning                                                   |
+//! |------|-------------------|-----------------------------------------------------------|
+//! | 0    |                   | Success.                                                  |
+//! | 1    | `other`           | Any other error.                                          |
+//! | 2    |                   | Invalid command-line usage, reported by the parser.       |
+//! | 3    | `auth`            | The API key is missing or was rejected.                   |
+//! | 4    | `invalid_request` | The API rejected the request parameters.                  |
+//! | 5    | `rate_limited`    | Too many requests, or not enough credits.                 |
+//! | 6    | `server`          | The API failed or answered with an unreadable response.   |
+//! | 7    | `network`         | The API could not be reached.                             |
+//! | 8    | `io`              | A file could not be read or written.                      |
+//! | 9    | `config`          | The configuration could not be loaded.                    |
+//! | 10   | `incomplete`      | Some generations of a run failed or were interrupted.     |
+
+use anyhow::Error;
+use serde::Serialize;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+use crate::client::{ApiError, Client, PreparedRequest};
+use crate::request::GenerationRequest;
+
+/// Whether progress messages are silenced.
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Silences or restores the progress messages printed with [`status!`].
+///
+/// # Arguments
+///
+/// * `quiet` - Whether progress messages are silenced.
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+/// Returns whether progress messages are silenced.
+pub fn quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+/// Format of the requests printed by `--dry-run`, or 0 when requests are sent.
+static DRY_RUN: AtomicU8 = AtomicU8::new(0);
+
+/// Format of the requests printed by `--dry-run`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum DryRun {
+    /// The method, URL, headers and body as JSON.
+    Json,
+    /// A ready-to-run `curl` command.
+    Curl,
+}
+
+impl DryRun {
+    /// Renders a request in this format.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The request, already redacted.
+    ///
+    /// # Returns
+    ///
+    /// The text to print.
+    pub fn render(self, request: &PreparedRequest) -> String {
+        match self {
+            Self::Json => serde_json::to_string_pretty(request)
+                .expect("a prepared request serializes to JSON"),
+            Self::Curl => request.to_curl(),
+        }
+    }
+}
+
+/// Makes commands print their requests instead of sending them, or restores sending.
+///
+/// # Arguments
+///
+/// * `dry_run` - Format of the printed requests, or `None` to send them.
+pub fn set_dry_run(dry_run: Option<DryRun>) {
+    let value = match dry_run {
+        None => 0,
+        Some(DryRun::Json) => 1,
+        Some(DryRun::Curl) => 2,
+    };
+    DRY_RUN.store(value, Ordering::Relaxed);
+}
+
+/// Returns the format of the requests printed by `--dry-run`, or `None` if requests are sent.
+pub fn dry_run() -> Option<DryRun> {
+    match DRY_RUN.load(Ordering::Relaxed) {
+        1 => Some(DryRun::Json),
+        2 => Some(DryRun::Curl),
+        _ => None,
+    }
+}
+
+/// Prints a progress message to stdout unless `--quiet` or `--json` was given.
+///
+/// # Arguments
+///
+/// * `message` - The message.
+pub fn print_status(message: impl std::fmt::Display) {
+    if !quiet() {
+        println!("{}", message);
+    }
+}
+
+/// Formats and prints a progress message with [`print_status`].
+macro_rules! status {
+    ($($arg:tt)*) => {
+        $crate::report::print_status(format_args!($($arg)*))
+    };
+}
+pub(crate) use status;
+
+/// Category of an error, deciding the exit code of the process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    /// Any other error.
+    Other,
+    /// The API key is missing or was rejected.
+    Auth,
+    /// The API rejected the request parameters.
+    InvalidRequest,
+    /// Too many requests, or not enough credits.
+    RateLimited,
+    /// The API failed or answered with an unreadable response.
+    Server,
+    /// The API could not be reached.
+    Network,
+    /// A file could not be read or written.
+    Io,
+    /// The configuration could not be loaded.
+    Config,
+    /// Some generations of a run failed or were interrupted.
+    Incomplete,
+}
+
+impl ErrorKind {
+    /// Returns the category of an error: the one given with [`ErrorKind::wrap`] if any, or the
+    /// one inferred from the first API, HTTP, I/O or configuration error of its chain.
+    ///
+    /// # Arguments
+    ///
+    /// * `error` - The error to categorize.
+    pub fn of(error: &Error) -> Self {
+        if let Some(kind) = error
+            .chain()
+            .find_map(|cause| cause.downcast_ref::<Categorized>())
+            .map(|categorized| categorized.kind)
+        {
+            return kind;
+        }
+        for cause in error.chain() {
+            if let Some(error) = cause.downcast_ref::<ApiError>() {
+                return match error.status {
+                    401 | 403 => Self::Auth,
+                    402 | 429 => Self::RateLimited,
+                    400..=499 => Self::InvalidRequest,
+                    _ => Self::Server,
+                };
+            }
+            if let Some(error) = cause.downcast_ref::<reqwest::Error>() {
+                return if error.is_decode() {
+                    Self::Server
+                } else {
+                    Self::Network
+                };
+            }
+            if cause.is::<std::io::Error>() {
+                return Self::Io;
+            }
+            if cause.is::<toml::de::Error>() {
+                return Self::Config;
+            }
+        }
+        Self::Other
+    }
+
+    /// Returns the process exit code of the category.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Self::Other => 1,
+            Self::Auth => 3,
+            Self::InvalidRequest => 4,
+            Self::RateLimited => 5,
+            Self::Server => 6,
+            Self::Network => 7,
+            Self::Io => 8,
+            Self::Config => 9,
+            Self::Incomplete => 10,
+        }
+    }
+
+    /// Tags an error with this category, leaving its message unchanged.
+    ///
+    /// # Arguments
+    ///
+    /// * `error` - The error to tag.
+    pub fn wrap(self, error: Error) -> Error {
+        Error::new(Categorized { kind: self, error })
+    }
+}
+
+/// Error tagged with a category by [`ErrorKind::wrap`], displayed as the error it wraps.
+#[derive(Debug)]
+struct Categorized {
+    kind: ErrorKind,
+    error: Error,
+}
+
+impl fmt::Display for Categorized {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+impl std::error::Error for Categorized {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.error.chain().nth(1).map(|cause| cause as _)
+    }
+}
+
+/// Struct representing the error of a failed run.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ErrorReport {
+    /// Category of the error.
+    pub kind: ErrorKind,
+    /// Exit code of the process.
+    pub code: i32,
+    /// Error message, including its causes.
+    pub message: String,
+}
+
+/// Struct representing one output of a run.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct OutputRecord {
+    /// Position of the output within the run.
+    pub index: usize,
+    /// Path of the saved image.
+    pub path: Option<PathBuf>,
+    /// Seed of the generation.
+    pub seed: Option<usize>,
+    /// Cost of the generation.
+    pub cost: Option<f64>,
+    /// Error message of a failed generation.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Struct representing the JSON object printed by `--json` once a command finishes.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Report {
+    /// Whether the command succeeded.
+    pub ok: bool,
+    /// Name of the subcommand, e.g. `t2i`.
+    pub command: String,
+    /// API endpoint the requests were sent to, if they all share one.
+    pub endpoint: Option<String>,
+    /// Model of the requests.
+    pub model: Option<String>,
+    /// Seed of the first output.
+    pub seed: Option<usize>,
+    /// Total cost reported by the API.
+    pub cost: Option<f64>,
+    /// Time spent waiting for the API, in milliseconds.
+    pub latency_ms: Option<u64>,
+    /// Outputs of the command, in order.
+    pub outputs: Vec<OutputRecord>,
+    /// Command-specific details, e.g. the parameters read by `inspect`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<serde_json::Value>,
+    /// Requests prepared by `--dry-run`, redacted.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub requests: Vec<PreparedRequest>,
+    /// Error of a failed command.
+    pub error: Option<ErrorReport>,
+}
+
+impl Report {
+    /// Creates the report of a command that has not produced anything yet.
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - Name of the subcommand.
+    pub fn new(command: &str) -> Self {
+        Self {
+            ok: true,
+            command: command.to_string(),
+            ..Self::default()
+        }
+    }
+
+    /// Records the endpoint, model and seed of the request a command sends.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The request.
+    pub fn request(&mut self, request: &GenerationRequest) {
+        self.endpoint = Some(request.endpoint().to_string());
+        self.model = Some(request.model().to_string());
+        self.seed = request.seed();
+    }
+
+    /// Records a request prepared instead of being sent by `--dry-run`, redacted.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - The client that would send the request.
+    /// * `request` - The request.
+    pub fn prepare(&mut self, client: &Client, request: &GenerationRequest) -> anyhow::Result<()> {
+        if self.requests.is_empty() {
+            self.request(request);
+        } else {
+            // The endpoint and model are only reported if every request shares them.
+            if self.endpoint.as_deref() != Some(request.endpoint()) {
+                self.endpoint = None;
+            }
+            if self.model.as_deref() != Some(request.model()) {
+                self.model = None;
+            }
+        }
+        self.requests.push(client.prepare(request)?.redacted());
+        Ok(())
+    }
+
+    /// Records an output, adding its cost to the total.
+    ///
+    /// # Arguments
+    ///
+    /// * `output` - The output.
+    pub fn output(&mut self, output: OutputRecord) {
+        if let Some(cost) = output.cost {
+            self.cost = Some(self.cost.unwrap_or(0.0) + cost);
+        }
+        let first = self.outputs.iter().all(|previous| previous.error.is_some());
+        if first && output.error.is_none() {
+            self.seed = output.seed.or(self.seed);
+        }
+        self.outputs.push(output);
+    }
+
+    /// Marks the command as failed.
+    ///
+    /// # Arguments
+    ///
+    /// * `error` - The error the command failed with.
+    pub fn fail(&mut self, error: &Error) {
+        let kind = ErrorKind::of(error);
+        self.ok = false;
+        self.error = Some(ErrorReport {
+            kind,
+            code: kind.exit_code(),
+            message: format!("{:#}", error),
+        });
+    }
+
+    /// Returns the process exit code of the command.
+    pub fn exit_code(&self) -> i32 {
+        self.error.as_ref().map_or(0, |error| error.code)
+    }
+}
@@ -1,16 +1,20 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+
+use crate::defaults::MAX_SEED;
+use crate::utils::random_seed;
 
 /// Struct representing the request body for edited image generation endpoint.
 ///
 /// This struct contains fields necessary for generating an edited image, such as the model name,
 /// prompts, image data, guidance parameters, and output format.
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EditImageRequest {
     /// Model name.
     pub model: String,
     /// Text prompt guiding the image editing process.
     pub prompt: String,
     /// Optional text prompt that contradicts the guidance for editing.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub negative_prompt: Option<String>,
     /// Base64 encoded image data.
     pub image: String,
@@ -32,19 +36,21 @@ pub struct EditImageRequest {
 ///
 /// This struct contains fields necessary for generating a repainted image, such as the model name,
 /// prompts, image and mask data, strength, guidance parameters, and output format.
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RepaintImageRequest {
     /// Model name.
     pub model: String,
     /// Text prompt guiding the image repainting process.
     pub prompt: String,
     /// Optional text prompt that contradicts the guidance for repainting.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub negative_prompt: Option<String>,
     /// Base64 encoded image data.
     pub image: String,
     /// Base64 encoded mask image data indicating areas to be repainted.
     pub mask_image: String,
     /// Strength of the repainting effect.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub strength: Option<f64>,
     /// Width of the generated image.
     pub width: usize,
@@ -66,13 +72,14 @@ pub struct RepaintImageRequest {
 ///
 /// This struct contains fields necessary for generating an image from text input, such as the prompts,
 /// model name, image dimensions, generation steps, output format, and seed.
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TextToImageRequest {
     /// Text prompt guiding the image generation process.
     pub prompt: String,
     /// Model name.
     pub model: String,
     /// Optional text prompt that contradicts the guidance for image generation.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub negative_prompt: Option<String>,
     /// Width of the generated image.
     pub width: usize,
@@ -83,6 +90,7 @@ pub struct TextToImageRequest {
     /// Output format of the generated image.
     pub output_format: String,
     /// Seed for deterministic generation.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub seed: Option<usize>,
 }
 
@@ -90,23 +98,26 @@ pub struct TextToImageRequest {
 ///
 /// This struct contains fields necessary for generating an image from another image, such as the prompts,
 /// model name, image data, strength, generation steps, output format, and seed.
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ImageToImageRequest {
     /// Model name.
     pub model: String,
     /// Text prompt guiding the image generation process.
     pub prompt: String,
     /// Optional text prompt that contradicts the guidance for image generation.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub negative_prompt: Option<String>,
     /// Base64 encoded image data.
     pub image: String,
     /// Strength of the conditioning effect.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub strength: Option<f64>,
     /// Number of steps in the generation process.
     pub steps: usize,
     /// Output format of the generated image.
     pub output_format: String,
     /// Seed for deterministic generation.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub seed: Option<usize>,
 }
 
@@ -115,7 +126,7 @@ pub struct ImageToImageRequest {
 /// This struct contains fields necessary for generating images using ControlNet conditioning,
 /// such as the ControlNet type, model name, prompts, image data, strength, image dimensions,
 /// generation steps, guidance parameters, scheduler, output format, and seed.
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ControlNetRequest {
     /// Type of ControlNet conditioning.
     pub controlnet: String,
@@ -124,6 +135,7 @@ pub struct ControlNetRequest {
     /// Text prompt guiding the image generation process.
     pub prompt: String,
     /// Optional text prompt that contradicts the guidance for image generation.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub negative_prompt: Option<String>,
     /// Base64 encoded image data.
     pub image: String,
@@ -144,3 +156,256 @@ pub struct ControlNetRequest {
     /// Output format of the generated image.
     pub output_format: String,
 }
+
+/// Enum representing how the seeds of variations are chosen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum SeedMode {
+    /// Consecutive seeds starting at the seed of the request.
+    #[default]
+    Sequential,
+    /// The seed of the request followed by independent random seeds.
+    Random,
+}
+
+/// Enum representing a request body for any of the generation endpoints.
+///
+/// Each variant is tagged with the name of the matching CLI subcommand, so that requests can be
+/// stored alongside generated images and read back from JSON.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum GenerationRequest {
+    /// Text-to-image request.
+    #[serde(rename = "t2i")]
+    TextToImage(TextToImageRequest),
+    /// Image-to-image request.
+    #[serde(rename = "i2i")]
+    ImageToImage(ImageToImageRequest),
+    /// ControlNet request.
+    #[serde(rename = "cnet")]
+    ControlNet(ControlNetRequest),
+    /// Repaint (inpainting) request.
+    #[serde(rename = "paint")]
+    Repaint(RepaintImageRequest),
+    /// Instruction-based edit request.
+    #[serde(rename = "edit")]
+    Edit(EditImageRequest),
+}
+
+impl GenerationRequest {
+    /// Returns the name of the CLI subcommand matching this request.
+    pub fn command(&self) -> &'static str {
+        match self {
+            Self::TextToImage(_) => "t2i",
+            Self::ImageToImage(_) => "i2i",
+            Self::ControlNet(_) => "cnet",
+            Self::Repaint(_) => "paint",
+            Self::Edit(_) => "edit",
+        }
+    }
+
+    /// Returns the API endpoint path, relative to the base URL.
+    pub fn endpoint(&self) -> &'static str {
+        match self {
+            Self::TextToImage(_) => "latent-consistency/text-to-image",
+            Self::ImageToImage(_) => "latent-consistency/image-to-image",
+            Self::ControlNet(_) => "stable-diffusion/controlnet",
+            Self::Repaint(_) => "stable-diffusion/inpaint",
+            Self::Edit(_) => "stable-diffusion/instruct",
+        }
+    }
+
+    /// Returns the model name.
+    pub fn model(&self) -> &str {
+        match self {
+            Self::TextToImage(r) => &r.model,
+            Self::ImageToImage(r) => &r.model,
+            Self::ControlNet(r) => &r.model,
+            Self::Repaint(r) => &r.model,
+            Self::Edit(r) => &r.model,
+        }
+    }
+
+    /// Returns the text prompt.
+    pub fn prompt(&self) -> &str {
+        match self {
+            Self::TextToImage(r) => &r.prompt,
+            Self::ImageToImage(r) => &r.prompt,
+            Self::ControlNet(r) => &r.prompt,
+            Self::Repaint(r) => &r.prompt,
+            Self::Edit(r) => &r.prompt,
+        }
+    }
+
+    /// Returns the negative prompt, if any.
+    pub fn negative_prompt(&self) -> Option<&str> {
+        match self {
+            Self::TextToImage(r) => r.negative_prompt.as_deref(),
+            Self::ImageToImage(r) => r.negative_prompt.as_deref(),
+            Self::ControlNet(r) => r.negative_prompt.as_deref(),
+            Self::Repaint(r) => r.negative_prompt.as_deref(),
+            Self::Edit(r) => r.negative_prompt.as_deref(),
+        }
+    }
+
+    /// Returns the seed, if any.
+    pub fn seed(&self) -> Option<usize> {
+        match self {
+            Self::TextToImage(r) => r.seed,
+            Self::ImageToImage(r) => r.seed,
+            Self::ControlNet(r) => Some(r.seed),
+            Self::Repaint(r) => Some(r.seed),
+            Self::Edit(r) => Some(r.seed),
+        }
+    }
+
+    /// Returns the number of denoising steps.
+    pub fn steps(&self) -> usize {
+        match self {
+            Self::TextToImage(r) => r.steps,
+            Self::ImageToImage(r) => r.steps,
+            Self::ControlNet(r) => r.steps,
+            Self::Repaint(r) => r.steps,
+            Self::Edit(r) => r.steps,
+        }
+    }
+
+    /// Returns the guidance scale, for endpoints that accept one.
+    pub fn guidance(&self) -> Option<f64> {
+        match self {
+            Self::TextToImage(_) | Self::ImageToImage(_) => None,
+            Self::ControlNet(r) => Some(r.guidance),
+            Self::Repaint(r) => Some(r.guidance),
+            Self::Edit(r) => Some(r.guidance),
+        }
+    }
+
+    /// Returns the scheduler, for endpoints that accept one.
+    pub fn scheduler(&self) -> Option<&str> {
+        match self {
+            Self::TextToImage(_) | Self::ImageToImage(_) => None,
+            Self::ControlNet(r) => Some(&r.scheduler),
+            Self::Repaint(r) => Some(&r.scheduler),
+            Self::Edit(r) => Some(&r.scheduler),
+        }
+    }
+
+    /// Returns the requested `(width, height)`, for endpoints that accept them.
+    pub fn dimensions(&self) -> Option<(usize, usize)> {
+        match self {
+            Self::TextToImage(r) => Some((r.width, r.height)),
+            Self::ControlNet(r) => Some((r.width, r.height)),
+            Self::Repaint(r) => Some((r.width, r.height)),
+            Self::ImageToImage(_) | Self::Edit(_) => None,
+        }
+    }
+
+    /// Returns the output format of the generated image.
+    pub fn output_format(&self) -> &str {
+        match self {
+            Self::TextToImage(r) => &r.output_format,
+            Self::ImageToImage(r) => &r.output_format,
+            Self::ControlNet(r) => &r.output_format,
+            Self::Repaint(r) => &r.output_format,
+            Self::Edit(r) => &r.output_format,
+        }
+    }
+
+    /// Returns the base64 input images as `(field name, data)` pairs.
+    pub fn images(&self) -> Vec<(&'static str, &str)> {
+        match self {
+            Self::TextToImage(_) => Vec::new(),
+            Self::ImageToImage(r) => vec![("image", &r.image)],
+            Self::ControlNet(r) => vec![("image", &r.image)],
+            Self::Repaint(r) => vec![("image", &r.image), ("mask_image", &r.mask_image)],
+            Self::Edit(r) => vec![("image", &r.image)],
+        }
+    }
+
+    /// Overrides the seed.
+    pub fn set_seed(&mut self, seed: usize) {
+        match self {
+            Self::TextToImage(r) => r.seed = Some(seed),
+            Self::ImageToImage(r) => r.seed = Some(seed),
+            Self::ControlNet(r) => r.seed = seed,
+            Self::Repaint(r) => r.seed = seed,
+            Self::Edit(r) => r.seed = seed,
+        }
+    }
+
+    /// Overrides the number of denoising steps.
+    pub fn set_steps(&mut self, steps: usize) {
+        match self {
+            Self::TextToImage(r) => r.steps = steps,
+            Self::ImageToImage(r) => r.steps = steps,
+            Self::ControlNet(r) => r.steps = steps,
+            Self::Repaint(r) => r.steps = steps,
+            Self::Edit(r) => r.steps = steps,
+        }
+    }
+
+    /// Returns a copy of the request with all base64 image data removed.
+    pub fn without_images(&self) -> Self {
+        let mut request = self.clone();
+        match &mut request {
+            Self::TextToImage(_) => {}
+            Self::ImageToImage(r) => r.image.clear(),
+            Self::ControlNet(r) => r.image.clear(),
+            Self::Repaint(r) => {
+                r.image.clear();
+                r.mask_image.clear();
+            }
+            Self::Edit(r) => r.image.clear(),
+        }
+        request
+    }
+
+    /// Returns `count` copies of the request that differ only in their seed.
+    ///
+    /// # Arguments
+    ///
+    /// * `count` - Number of variations.
+    /// * `mode` - How seeds are chosen. The first variation keeps the seed of the request, or a
+    ///   random seed if it has none. Sequential seeds continue from it and wrap around after the
+    ///   largest seed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use getimg::request::{GenerationRequest, SeedMode, TextToImageRequest};
+    ///
+    /// let request = GenerationRequest::TextToImage(TextToImageRequest {
+    ///     prompt: "Rusty crab on the beach".to_string(),
+    ///     model: "lcm-realistic-vision-v5-1".to_string(),
+    ///     negative_prompt: None,
+    ///     width: 512,
+    ///     height: 512,
+    ///     steps: 4,
+    ///     output_format: "jpeg".to_string(),
+    ///     seed: Some(41),
+    /// });
+    /// let seeds: Vec<_> = request
+    ///     .variations(3, SeedMode::Sequential)
+    ///     .iter()
+    ///     .map(|variation| variation.seed())
+    ///     .collect();
+    /// assert_eq!(seeds, [Some(41), Some(42), Some(43)]);
+    /// ```
+    pub fn variations(&self, count: usize, mode: SeedMode) -> Vec<Self> {
+        let first = self.seed().unwrap_or_else(random_seed);
+        (0..count)
+            .map(|index| {
+                let mut variation = self.clone();
+                variation.set_seed(match mode {
+                    SeedMode::Sequential => match first + index {
+                        seed if seed > MAX_SEED => seed - MAX_SEED,
+                        seed => seed,
+                    },
+                    SeedMode::Random if index > 0 => random_seed(),
+                    SeedMode::Random => first,
+                });
+                variation
+            })
+            .collect()
+    }
+}
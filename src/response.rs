@@ -1,10 +1,10 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 /// Struct representing the response body for text-to-image and image-to-image generation endpoint.
 ///
 /// This struct contains fields representing the generated image, seed used for generation (if applicable),
 /// and the cost of generation (if applicable).
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ToImageResponse {
     /// The generated image data.
     pub image: String,
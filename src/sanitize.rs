@@ -0,0 +1,272 @@
+//! This module contains helpers for stripping private metadata from input images before upload.
+//!
+//! Photos taken with phones and cameras commonly carry GPS coordinates, serial numbers, owner
+//! names and editing history in their EXIF, XMP, IPTC, ICC profile and comment segments. These
+//! are removed surgically from PNG and JPEG images, without re-encoding the pixel data. The EXIF
+//! orientation is the only property kept, so that the image is still displayed upright.
+//!
+//! Other formats, such as WebP, TIFF, GIF and BMP, are decoded and re-encoded as PNG, which drops
+//! all of their metadata. Without the `image` feature they are refused instead.
+
+#[cfg(not(feature = "image"))]
+use anyhow::bail;
+#[cfg(feature = "image")]
+use anyhow::Context;
+use anyhow::Result;
+#[cfg(feature = "image")]
+use image::ImageFormat;
+#[cfg(feature = "image")]
+use std::io::Cursor;
+
+use crate::metadata::{
+    jpeg_segments, png_chunks, png_text_keyword, write_jpeg_segment, write_png_chunk, EXIF_HEADER,
+    PNG_SIGNATURE, XMP_HEADER, XMP_PNG_KEY,
+};
+
+const XMP_EXTENSION_HEADER: &[u8] = b"http://ns.adobe.com/xmp/extension/\0";
+const ICC_HEADER: &[u8] = b"ICC_PROFILE\0";
+
+const ORIENTATION_TAG: u16 = 0x0112;
+const EXIF_IFD_TAG: u16 = 0x8769;
+const GPS_IFD_TAG: u16 = 0x8825;
+
+/// Struct representing an image with its private metadata removed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sanitized {
+    /// The encoded image without private metadata.
+    pub data: Vec<u8>,
+    /// Human-readable descriptions of the removed metadata, in file order.
+    pub removed: Vec<String>,
+}
+
+/// Removes EXIF, XMP, IPTC, ICC profile, comment and text metadata from an encoded image.
+///
+/// # Arguments
+///
+/// * `bytes` - The encoded image.
+///
+/// # Returns
+///
+/// A `Result` containing the stripped image and a report of what was removed. Formats other than
+/// PNG and JPEG are re-encoded as PNG, or an error without the `image` feature.
+///
+/// # Examples
+///
+/// ```no_run
+/// use getimg::sanitize::strip;
+///
+/// let sanitized = strip(&std::fs::read("photo.jpg").unwrap()).unwrap();
+/// for removed in &sanitized.removed {
+///     println!("removed {}", removed);
+/// }
+/// ```
+pub fn strip(bytes: &[u8]) -> Result<Sanitized> {
+    if bytes.starts_with(PNG_SIGNATURE) {
+        strip_png(bytes)
+    } else if bytes.starts_with(&[0xFF, 0xD8]) {
+        strip_jpeg(bytes)
+    } else {
+        reencode(bytes)
+    }
+}
+
+/// Decodes an image in another format and encodes its pixels as PNG, applying its orientation.
+#[cfg(feature = "image")]
+fn reencode(bytes: &[u8]) -> Result<Sanitized> {
+    let format = image::guess_format(bytes).context("unsupported image format")?;
+    let image = crate::preprocess::decode(bytes)?;
+    let mut data = Vec::new();
+    image.write_to(&mut Cursor::new(&mut data), ImageFormat::Png)?;
+    Ok(Sanitized {
+        data,
+        removed: vec![format!(
+            "all {} metadata, re-encoded as PNG",
+            format!("{:?}", format).to_uppercase()
+        )],
+    })
+}
+
+#[cfg(not(feature = "image"))]
+fn reencode(_bytes: &[u8]) -> Result<Sanitized> {
+    bail!("metadata can only be removed from PNG and JPEG images without the `image` feature")
+}
+
+fn strip_png(bytes: &[u8]) -> Result<Sanitized> {
+    let mut removed = Vec::new();
+    let mut out = Vec::with_capacity(bytes.len());
+    out.extend_from_slice(PNG_SIGNATURE);
+    for (kind, data) in png_chunks(bytes)? {
+        let description = match &kind {
+            b"eXIf" => Some(exif_description(data)),
+            b"iCCP" => Some("ICC profile".to_string()),
+            b"tIME" => Some("modification time".to_string()),
+            b"tEXt" | b"zTXt" | b"iTXt" if png_text_keyword(data) == XMP_PNG_KEY.as_bytes() => {
+                Some("XMP".to_string())
+            }
+            b"tEXt" | b"zTXt" | b"iTXt" => Some(format!(
+                "text `{}`",
+                String::from_utf8_lossy(png_text_keyword(data))
+            )),
+            _ => None,
+        };
+        match description {
+            Some(description) => report(&mut removed, description),
+            None => write_png_chunk(&mut out, &kind, data),
+        }
+    }
+    Ok(Sanitized { data: out, removed })
+}
+
+fn strip_jpeg(bytes: &[u8]) -> Result<Sanitized> {
+    let (segments, scan) = jpeg_segments(bytes)?;
+    let mut removed = Vec::new();
+    let mut out = Vec::with_capacity(bytes.len());
+    out.extend_from_slice(&[0xFF, 0xD8]);
+    for (marker, data) in segments {
+        let description = match marker {
+            0xE1 if data.starts_with(EXIF_HEADER) => {
+                let tiff = &data[EXIF_HEADER.len()..];
+                if let Some(orientation) = orientation(tiff).filter(|value| *value != 1) {
+                    write_jpeg_segment(&mut out, 0xE1, &orientation_exif(orientation))?;
+                }
+                Some(exif_description(tiff))
+            }
+            0xE1 if data.starts_with(XMP_HEADER) || data.starts_with(XMP_EXTENSION_HEADER) => {
+                Some("XMP".to_string())
+            }
+            0xE2 if data.starts_with(ICC_HEADER) => Some("ICC profile".to_string()),
+            0xED => Some("IPTC".to_string()),
+            0xFE => Some("comment".to_string()),
+            // JFIF and Adobe segments describe how to decode the pixel data.
+            0xE0 | 0xEE => None,
+            0xE1..=0xEF => Some(format!("APP{} segment", marker - 0xE0)),
+            _ => None,
+        };
+        match description {
+            Some(description) => report(&mut removed, description),
+            None if matches!(marker, 0x01 | 0xD0..=0xD7) => out.extend_from_slice(&[0xFF, marker]),
+            None => write_jpeg_segment(&mut out, marker, data)?,
+        }
+    }
+    out.extend_from_slice(&bytes[scan..]);
+    Ok(Sanitized { data: out, removed })
+}
+
+fn report(removed: &mut Vec<String>, description: String) {
+    if !removed.contains(&description) {
+        removed.push(description);
+    }
+}
+
+/// Reads TIFF-structured EXIF data, as found after the `Exif\0\0` header.
+struct Tiff<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+impl<'a> Tiff<'a> {
+    fn new(data: &'a [u8]) -> Option<Self> {
+        let big_endian = match data.get(..4)? {
+            b"MM\0\x2a" => true,
+            b"II\x2a\0" => false,
+            _ => return None,
+        };
+        Some(Self { data, big_endian })
+    }
+
+    fn u16(&self, offset: usize) -> Option<u16> {
+        let bytes = self.data.get(offset..offset + 2)?.try_into().ok()?;
+        Some(if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    }
+
+    fn u32(&self, offset: usize) -> Option<u32> {
+        let bytes = self.data.get(offset..offset + 4)?.try_into().ok()?;
+        Some(if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+
+    /// Returns the `(tag, entry offset)` pairs of the IFD at `offset`.
+    fn entries(&self, offset: usize) -> Vec<(u16, usize)> {
+        let count = self.u16(offset).unwrap_or(0) as usize;
+        (0..count)
+            .map(|index| offset + 2 + index * 12)
+            .map_while(|entry| Some((self.u16(entry)?, entry)))
+            .collect()
+    }
+
+    fn first_ifd(&self) -> usize {
+        self.u32(4).unwrap_or(0) as usize
+    }
+}
+
+fn orientation(tiff: &[u8]) -> Option<u16> {
+    let tiff = Tiff::new(tiff)?;
+    let (_, entry) = tiff
+        .entries(tiff.first_ifd())
+        .into_iter()
+        .find(|(tag, _)| *tag == ORIENTATION_TAG)?;
+    tiff.u16(entry + 8)
+}
+
+/// Builds a minimal EXIF segment holding only the orientation.
+fn orientation_exif(orientation: u16) -> Vec<u8> {
+    let mut out = EXIF_HEADER.to_vec();
+    out.extend_from_slice(b"MM\0\x2a");
+    out.extend_from_slice(&8u32.to_be_bytes());
+    out.extend_from_slice(&1u16.to_be_bytes());
+    out.extend_from_slice(&ORIENTATION_TAG.to_be_bytes());
+    out.extend_from_slice(&3u16.to_be_bytes());
+    out.extend_from_slice(&1u32.to_be_bytes());
+    out.extend_from_slice(&orientation.to_be_bytes());
+    out.extend_from_slice(&[0, 0]);
+    out.extend_from_slice(&0u32.to_be_bytes());
+    out
+}
+
+/// Describes the notable private fields of EXIF data.
+fn exif_description(tiff: &[u8]) -> String {
+    let Some(tiff) = Tiff::new(tiff) else {
+        return "EXIF".to_string();
+    };
+    let mut tags = tiff
+        .entries(tiff.first_ifd())
+        .into_iter()
+        .map(|(tag, _)| tag)
+        .collect::<Vec<_>>();
+    if let Some((_, entry)) = tiff
+        .entries(tiff.first_ifd())
+        .into_iter()
+        .find(|(tag, _)| *tag == EXIF_IFD_TAG)
+    {
+        let exif_ifd = tiff.u32(entry + 8).unwrap_or(0) as usize;
+        tags.extend(tiff.entries(exif_ifd).into_iter().map(|(tag, _)| tag));
+    }
+
+    let fields = [
+        (&[GPS_IFD_TAG][..], "GPS location"),
+        (
+            &[0x010F, 0x0110, 0xA433, 0xA434][..],
+            "camera and lens model",
+        ),
+        (&[0xA431, 0xA435, 0xC62F][..], "serial numbers"),
+        (&[0x013B, 0x8298, 0xA430][..], "owner"),
+        (&[0x9003, 0x9004, 0x0132][..], "capture time"),
+        (&[0x0131][..], "software"),
+    ]
+    .into_iter()
+    .filter(|(fields, _)| fields.iter().any(|field| tags.contains(field)))
+    .map(|(_, name)| name)
+    .collect::<Vec<_>>();
+    if fields.is_empty() {
+        "EXIF".to_string()
+    } else {
+        format!("EXIF ({})", fields.join(", "))
+    }
+}
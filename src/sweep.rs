@@ -0,0 +1,357 @@
+//! This module contains parameter sweeps: the cartesian product of up to three value lists
+//! applied to a base request, and the labeled grid image comparing the results.
+//!
+//! An axis is written `parameter=value,value,...`, e.g. `steps=4,8,12` or `scheduler=euler,ddim`.
+//! The X axis varies along columns, the Y axis along rows, and each Z value gets its own panel.
+
+use anyhow::{anyhow, bail, Result};
+use embedded_graphics::mono_font::ascii::FONT_10X20;
+use embedded_graphics::mono_font::MonoTextStyle;
+use embedded_graphics::pixelcolor::{Rgb888, RgbColor};
+use embedded_graphics::prelude::{DrawTarget, OriginDimensions, Pixel, Point, Size};
+use embedded_graphics::text::{Baseline, Text};
+use embedded_graphics::Drawable;
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, Rgb, RgbImage};
+use serde::Serialize;
+use std::convert::Infallible;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::request::GenerationRequest;
+
+/// Parameters that can be swept.
+pub const PARAMETERS: [&str; 11] = [
+    "steps",
+    "guidance",
+    "strength",
+    "scheduler",
+    "image_guidance",
+    "width",
+    "height",
+    "seed",
+    "model",
+    "prompt",
+    "negative_prompt",
+];
+
+/// Width of a label character in pixels.
+const CHAR_WIDTH: u32 = 10;
+
+/// Height of a label line in pixels.
+const LINE_HEIGHT: u32 = 20;
+
+/// Space around labels and between panels in pixels.
+const PADDING: u32 = 8;
+
+/// Struct representing one axis of a sweep.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Axis {
+    /// Swept parameter, one of [`PARAMETERS`].
+    pub name: String,
+    /// Values taken by the parameter.
+    pub values: Vec<String>,
+}
+
+impl FromStr for Axis {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (name, values) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow!("expected `parameter=value,value,...`, got `{}`", s))?;
+        let name = name.trim().replace('-', "_");
+        if !PARAMETERS.contains(&name.as_str()) {
+            bail!(
+                "cannot sweep `{}`, expected one of {}",
+                name,
+                PARAMETERS.join(", ")
+            );
+        }
+        let values = values
+            .split(',')
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        if values.is_empty() {
+            bail!("the `{}` axis has no values", name);
+        }
+        Ok(Self { name, values })
+    }
+}
+
+/// Struct representing one combination of axis values.
+#[derive(Debug, Clone)]
+pub struct Cell {
+    /// Position of the cell in the sweep, X varying fastest.
+    pub index: usize,
+    /// Value index along each axis, in axis order.
+    pub position: Vec<usize>,
+    /// `(parameter, value)` pairs of the cell, in axis order.
+    pub values: Vec<(String, String)>,
+    /// The base request with the values applied.
+    pub request: GenerationRequest,
+}
+
+/// Builds the cells of a sweep.
+///
+/// # Arguments
+///
+/// * `base` - The request every cell starts from.
+/// * `axes` - One to three axes, X first.
+///
+/// # Returns
+///
+/// A `Result` containing the cells with X varying fastest, then Y, then Z, or an error if an
+/// axis does not apply to the request or a value is invalid.
+pub fn cells(base: &GenerationRequest, axes: &[Axis]) -> Result<Vec<Cell>> {
+    if axes.is_empty() || axes.len() > 3 {
+        bail!("a sweep takes one to three axes, got {}", axes.len());
+    }
+    let total = axes.iter().map(|axis| axis.values.len()).product::<usize>();
+    (0..total)
+        .map(|index| {
+            let mut rest = index;
+            let position = axes
+                .iter()
+                .map(|axis| {
+                    let position = rest % axis.values.len();
+                    rest /= axis.values.len();
+                    position
+                })
+                .collect::<Vec<_>>();
+            let mut request = base.clone();
+            let mut values = Vec::with_capacity(axes.len());
+            for (axis, &position) in axes.iter().zip(&position) {
+                let value = &axis.values[position];
+                apply(&mut request, &axis.name, value)?;
+                values.push((axis.name.clone(), value.clone()));
+            }
+            Ok(Cell {
+                index,
+                position,
+                values,
+                request,
+            })
+        })
+        .collect()
+}
+
+/// Sets a parameter of a request from its textual value.
+///
+/// # Arguments
+///
+/// * `request` - The request to modify.
+/// * `name` - The parameter, one of [`PARAMETERS`].
+/// * `value` - The value to parse.
+///
+/// # Returns
+///
+/// A `Result` that is an error if the value is invalid or the endpoint has no such parameter.
+pub fn apply(request: &mut GenerationRequest, name: &str, value: &str) -> Result<()> {
+    fn parse<T: FromStr>(name: &str, value: &str) -> Result<T> {
+        value
+            .parse()
+            .map_err(|_| anyhow!("invalid {} `{}`", name, value))
+    }
+    let command = request.command();
+    let unsupported = || anyhow!("`{}` requests have no {} to sweep", command, name);
+    match (name, request) {
+        ("steps", request) => request.set_steps(parse(name, value)?),
+        ("seed", request) => request.set_seed(parse(name, value)?),
+        ("prompt", GenerationRequest::TextToImage(r)) => r.prompt = value.to_string(),
+        ("prompt", GenerationRequest::ImageToImage(r)) => r.prompt = value.to_string(),
+        ("prompt", GenerationRequest::ControlNet(r)) => r.prompt = value.to_string(),
+        ("prompt", GenerationRequest::Repaint(r)) => r.prompt = value.to_string(),
+        ("prompt", GenerationRequest::Edit(r)) => r.prompt = value.to_string(),
+        ("negative_prompt", request) => {
+            let negative_prompt = Some(value.to_string());
+            match request {
+                GenerationRequest::TextToImage(r) => r.negative_prompt = negative_prompt,
+                GenerationRequest::ImageToImage(r) => r.negative_prompt = negative_prompt,
+                GenerationRequest::ControlNet(r) => r.negative_prompt = negative_prompt,
+                GenerationRequest::Repaint(r) => r.negative_prompt = negative_prompt,
+                GenerationRequest::Edit(r) => r.negative_prompt = negative_prompt,
+            }
+        }
+        ("guidance", GenerationRequest::ControlNet(r)) => r.guidance = parse(name, value)?,
+        ("guidance", GenerationRequest::Repaint(r)) => r.guidance = parse(name, value)?,
+        ("guidance", GenerationRequest::Edit(r)) => r.guidance = parse(name, value)?,
+        ("strength", GenerationRequest::ImageToImage(r)) => r.strength = Some(parse(name, value)?),
+        ("strength", GenerationRequest::ControlNet(r)) => r.strength = parse(name, value)?,
+        ("strength", GenerationRequest::Repaint(r)) => r.strength = Some(parse(name, value)?),
+        ("scheduler", GenerationRequest::ControlNet(r)) => r.scheduler = value.to_string(),
+        ("scheduler", GenerationRequest::Repaint(r)) => r.scheduler = value.to_string(),
+        ("scheduler", GenerationRequest::Edit(r)) => r.scheduler = value.to_string(),
+        ("image_guidance", GenerationRequest::Edit(r)) => r.image_guidance = parse(name, value)?,
+        ("width", GenerationRequest::TextToImage(r)) => r.width = parse(name, value)?,
+        ("width", GenerationRequest::ControlNet(r)) => r.width = parse(name, value)?,
+        ("width", GenerationRequest::Repaint(r)) => r.width = parse(name, value)?,
+        ("height", GenerationRequest::TextToImage(r)) => r.height = parse(name, value)?,
+        ("height", GenerationRequest::ControlNet(r)) => r.height = parse(name, value)?,
+        ("height", GenerationRequest::Repaint(r)) => r.height = parse(name, value)?,
+        ("model", GenerationRequest::TextToImage(r)) => r.model = value.to_string(),
+        ("model", GenerationRequest::ImageToImage(r)) => r.model = value.to_string(),
+        _ => return Err(unsupported()),
+    }
+    Ok(())
+}
+
+/// Struct representing the record of one cell in a sweep manifest.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CellRecord {
+    /// Position of the cell in the sweep.
+    pub index: usize,
+    /// `(parameter, value)` pairs of the cell, in axis order.
+    pub values: Vec<(String, String)>,
+    /// Seed of the generation.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<usize>,
+    /// Path of the saved image.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    /// Cost of the generation.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cost: Option<f64>,
+    /// Error message of a failed cell.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Struct representing the manifest written next to a sweep grid.
+#[derive(Debug, Clone, Serialize)]
+pub struct SweepManifest {
+    /// Path of the grid image.
+    pub grid: PathBuf,
+    /// Axes of the sweep, X first.
+    pub axes: Vec<Axis>,
+    /// The base request, without input image data.
+    pub request: GenerationRequest,
+    /// The cells of the sweep.
+    pub cells: Vec<CellRecord>,
+}
+
+/// Renders the labeled grid of a sweep.
+///
+/// Columns follow the X axis and rows the Y axis; each Z value is drawn as a separate panel,
+/// left to right. Cells without an image are drawn as gray boxes.
+///
+/// # Arguments
+///
+/// * `axes` - The axes of the sweep, X first.
+/// * `images` - One image per cell, in the order of [`cells`].
+/// * `cell_size` - Longest side of a cell in pixels.
+pub fn render_grid(axes: &[Axis], images: &[Option<DynamicImage>], cell_size: u32) -> RgbImage {
+    let count = |axis: usize| axes.get(axis).map_or(1, |axis| axis.values.len());
+    let (columns, rows, panels) = (count(0), count(1), count(2));
+    let label = |axis: usize, value: usize| {
+        axes.get(axis)
+            .map(|axis| format!("{}={}", axis.name, axis.values[value]))
+    };
+
+    let header = LINE_HEIGHT + 2 * PADDING;
+    let title = if panels > 1 { header } else { 0 };
+    let left = (0..rows)
+        .filter_map(|row| label(1, row))
+        .map(|label| label.chars().count() as u32 * CHAR_WIDTH + 2 * PADDING)
+        .max()
+        .unwrap_or(0);
+    let panel_width = left + columns as u32 * cell_size;
+    let width = panels as u32 * panel_width + (panels as u32 - 1) * PADDING * 4;
+    let height = title + header + rows as u32 * cell_size;
+
+    let mut grid = RgbImage::from_pixel(width, height, Rgb([255, 255, 255]));
+    for panel in 0..panels {
+        let x0 = panel as u32 * (panel_width + PADDING * 4);
+        if let Some(text) = label(2, panel) {
+            draw_label(&mut grid, &text, x0 + left, PADDING, panel_width - left);
+        }
+        for column in 0..columns {
+            if let Some(text) = label(0, column) {
+                let x = x0 + left + column as u32 * cell_size;
+                draw_label(&mut grid, &text, x + PADDING, title + PADDING, cell_size);
+            }
+        }
+        for row in 0..rows {
+            let y = title + header + row as u32 * cell_size;
+            if let Some(text) = label(1, row) {
+                let y = y + cell_size.saturating_sub(LINE_HEIGHT) / 2;
+                draw_label(&mut grid, &text, x0 + PADDING, y, left);
+            }
+            for column in 0..columns {
+                let index = (panel * rows + row) * columns + column;
+                let x = x0 + left + column as u32 * cell_size;
+                draw_cell(
+                    &mut grid,
+                    images.get(index).and_then(Option::as_ref),
+                    x,
+                    y,
+                    cell_size,
+                );
+            }
+        }
+    }
+    grid
+}
+
+fn draw_cell(grid: &mut RgbImage, image: Option<&DynamicImage>, x: u32, y: u32, size: u32) {
+    let Some(image) = image else {
+        let gray = RgbImage::from_pixel(
+            size.saturating_sub(2),
+            size.saturating_sub(2),
+            Rgb([200, 200, 200]),
+        );
+        imageops::replace(grid, &gray, x as i64 + 1, y as i64 + 1);
+        draw_label(grid, "failed", x + PADDING, y + PADDING, size);
+        return;
+    };
+    let thumbnail = image.resize(size, size, FilterType::Triangle).to_rgb8();
+    let dx = (size - thumbnail.width()) / 2;
+    let dy = (size - thumbnail.height()) / 2;
+    imageops::replace(grid, &thumbnail, (x + dx) as i64, (y + dy) as i64);
+}
+
+/// Draws a single line of text, truncated with `...` to fit `max_width`.
+fn draw_label(grid: &mut RgbImage, text: &str, x: u32, y: u32, max_width: u32) {
+    let max_chars = (max_width.saturating_sub(PADDING) / CHAR_WIDTH) as usize;
+    let text = if text.chars().count() > max_chars {
+        let kept = text.chars().take(max_chars.saturating_sub(3));
+        format!("{}...", kept.collect::<String>())
+    } else {
+        text.to_string()
+    };
+    let style = MonoTextStyle::new(&FONT_10X20, Rgb888::BLACK);
+    let point = Point::new(x as i32, y as i32);
+    let Ok(_) = Text::with_baseline(&text, point, style, Baseline::Top).draw(&mut Canvas(grid));
+}
+
+/// Adapter drawing embedded-graphics primitives onto an `RgbImage`.
+struct Canvas<'a>(&'a mut RgbImage);
+
+impl OriginDimensions for Canvas<'_> {
+    fn size(&self) -> Size {
+        Size::new(self.0.width(), self.0.height())
+    }
+}
+
+impl DrawTarget for Canvas<'_> {
+    type Color = Rgb888;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            let (Ok(x), Ok(y)) = (u32::try_from(point.x), u32::try_from(point.y)) else {
+                continue;
+            };
+            if x < self.0.width() && y < self.0.height() {
+                self.0
+                    .put_pixel(x, y, Rgb([color.r(), color.g(), color.b()]));
+            }
+        }
+        Ok(())
+    }
+}
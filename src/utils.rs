@@ -1,9 +1,15 @@
+use crate::defaults::MAX_SEED;
+use crate::metadata::{self, GenerationParams};
 use anyhow::Result;
 use base64::engine::general_purpose::STANDARD;
 use base64::Engine;
+use std::collections::hash_map::RandomState;
 use std::fs::File;
+use std::hash::{BuildHasher, Hasher};
 use std::io::Read;
 use std::io::Write;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 /// Saves an image to a file.
 ///
@@ -18,12 +24,47 @@ use std::io::Write;
 /// or an `std::io::Error` if there is an issue with file creation or writing.
 pub fn save_image(image_data: &str, filename: &str) -> Result<()> {
     let decoded_image_data = STANDARD.decode(image_data)?;
+    save_image_bytes(&decoded_image_data, filename)
+}
+
+/// Saves already decoded image bytes to a file.
+///
+/// # Arguments
+///
+/// * `image_data` - The encoded image bytes.
+/// * `filename` - A string slice representing the name of the file to save the image to.
+///
+/// # Returns
+///
+/// A `Result` indicating success or failure of the operation.
+pub fn save_image_bytes(image_data: &[u8], filename: &str) -> Result<()> {
     let mut file = File::create(filename)?;
-    file.write_all(&decoded_image_data)?;
+    file.write_all(image_data)?;
     println!("Image saved as: {}", filename);
     Ok(())
 }
 
+/// Saves an image to a file, embedding the parameters used to generate it.
+///
+/// # Arguments
+///
+/// * `image_data` - A base64-encoded string representing the image data.
+/// * `filename` - A string slice representing the name of the file to save the image to.
+/// * `params` - The generation parameters to embed, see [`metadata::embed`].
+///
+/// # Returns
+///
+/// A `Result` indicating success or failure of the operation.
+pub fn save_image_with_metadata(
+    image_data: &str,
+    filename: &str,
+    params: &GenerationParams,
+) -> Result<()> {
+    let decoded_image_data = STANDARD.decode(image_data)?;
+    let image_data = metadata::embed(&decoded_image_data, params)?;
+    save_image_bytes(&image_data, filename)
+}
+
 /// Load the image from the given path and encode it as a base64 string.
 ///
 /// # Arguments
@@ -46,3 +87,75 @@ pub fn load_and_encode_image(image_path: &str) -> Result<String, std::io::Error>
 
     Ok(base64_string)
 }
+
+/// Writes a file atomically: the bytes go to a temporary file next to it, which then replaces it,
+/// so that a crash never leaves the file truncated.
+///
+/// # Arguments
+///
+/// * `path` - Path to the file.
+/// * `bytes` - The new contents of the file.
+///
+/// # Returns
+///
+/// A `Result` indicating success or failure of the operation.
+pub fn write_atomic(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    std::fs::write(&temporary, bytes)?;
+    std::fs::rename(&temporary, path)
+}
+
+/// Splits a system time into its UTC `(year, month, day, hour, minute, second)` components.
+pub(crate) fn utc_components(time: SystemTime) -> (i64, u32, u32, u32, u32, u32) {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as i64)
+        .unwrap_or_default();
+    let (days, rest) = (seconds.div_euclid(86_400), seconds.rem_euclid(86_400));
+
+    // Converts days since the epoch to a civil date in the proleptic Gregorian calendar.
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    (
+        year,
+        month as u32,
+        day as u32,
+        (rest / 3_600) as u32,
+        (rest % 3_600 / 60) as u32,
+        (rest % 60) as u32,
+    )
+}
+
+/// Formats a system time as an RFC 3339 UTC timestamp, e.g. `2024-04-01T12:30:00Z`.
+///
+/// # Arguments
+///
+/// * `time` - The time to format.
+pub fn rfc3339(time: SystemTime) -> String {
+    let (year, month, day, hour, minute, second) = utc_components(time);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year, month, day, hour, minute, second
+    )
+}
+
+/// Returns a random seed accepted by the API, between 1 and [`MAX_SEED`].
+pub fn random_seed() -> usize {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u128(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos(),
+    );
+    (hasher.finish() % MAX_SEED as u64) as usize + 1
+}
@@ -0,0 +1,354 @@
+//! This module contains the watch mode, processing images as they are dropped into a directory.
+//!
+//! Every image file appearing in the watched directory is sent with the same options, as an
+//! `i2i`, `cnet`, `edit` or `paint` job (see [`crate::batch`]), and the output is saved under the
+//! name of the input in the output directory, a sibling of the watched one by default:
+//!
+//! ```text
+//! sketches/cabin.png      ->  sketches-out/cabin.jpeg
+//! sketches/harbor.jpg     ->  sketches-out/harbor.jpeg
+//! ```
+//!
+//! Processed files are recorded with a hash of their content in a state file kept in the output
+//! directory, so that a restarted watcher only processes new, modified and failed files. New
+//! files are processed once their size stops changing, so that files still being copied are not
+//! read half-written.
+
+use anyhow::{bail, Context, Result};
+use notify::{EventKind, RecursiveMode, Watcher as _};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+use crate::batch::{Job, Runner};
+use crate::output::escape;
+use crate::report::{status, OutputRecord};
+use crate::utils::write_atomic;
+
+/// Subcommands the watcher can run, all taking an input image.
+pub const COMMANDS: [&str; 4] = ["i2i", "cnet", "edit", "paint"];
+
+/// Extensions of the files processed by the watcher, compared case-insensitively.
+pub const IMAGE_EXTENSIONS: [&str; 4] = ["png", "jpg", "jpeg", "webp"];
+
+/// Name of the state file, in the output directory.
+pub const STATE_FILE: &str = ".getimg-watch.json";
+
+/// Time the size of a new file must stay the same before the file is processed.
+const SETTLE: Duration = Duration::from_millis(500);
+
+/// Struct representing the persisted state of a watcher, one entry per input file name.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct WatchState {
+    /// States of the processed files, by file name.
+    pub files: BTreeMap<String, FileState>,
+}
+
+/// Struct representing the outcome of processing one input file.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FileState {
+    /// Hash of the content of the input file.
+    pub fingerprint: String,
+    /// Path of the saved image.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    /// Seed of the generation.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<usize>,
+    /// Cost of the generation.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cost: Option<f64>,
+    /// Error message of a failed generation.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl WatchState {
+    /// Reads a state file, or returns an empty state if there is none yet.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the state file.
+    pub fn open(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read the watch state {}", path.display()))?;
+        serde_json::from_str(&text)
+            .with_context(|| format!("invalid watch state {}", path.display()))
+    }
+
+    /// Writes the state file, see [`write_atomic`].
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the state file.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        write_atomic(path, &serde_json::to_vec_pretty(self)?)
+            .with_context(|| format!("failed to write the watch state {}", path.display()))
+    }
+
+    /// Returns whether a file with this name and content was processed successfully.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - File name of the input.
+    /// * `fingerprint` - Hash of its content, see [`fingerprint`].
+    pub fn is_done(&self, name: &str, fingerprint: &str) -> bool {
+        self.files
+            .get(name)
+            .is_some_and(|file| file.fingerprint == fingerprint && file.error.is_none())
+    }
+}
+
+/// Returns the hash of the content of a file, as 16 hexadecimal digits.
+///
+/// # Arguments
+///
+/// * `path` - Path to the file.
+pub fn fingerprint(path: &Path) -> Result<String> {
+    let content =
+        std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+    Ok(Sha256::digest(content)[..8]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+/// Returns whether a path names an image the watcher processes: a file with one of
+/// [`IMAGE_EXTENSIONS`] whose name does not start with `.`.
+///
+/// # Arguments
+///
+/// * `path` - The path.
+pub fn is_image(path: &Path) -> bool {
+    let hidden = path
+        .file_name()
+        .is_none_or(|name| name.to_string_lossy().starts_with('.'));
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+    !hidden && IMAGE_EXTENSIONS.contains(&extension.as_str())
+}
+
+/// Returns the default output directory of a watched directory: its sibling named
+/// `<name>-out`, e.g. `sketches-out` for `sketches`.
+///
+/// # Arguments
+///
+/// * `dir` - The watched directory.
+pub fn out_dir(dir: &Path) -> PathBuf {
+    // Paths such as `.` have no name of their own.
+    let dir = match dir.file_name() {
+        Some(_) => dir.to_path_buf(),
+        None => std::path::absolute(dir).unwrap_or_else(|_| dir.to_path_buf()),
+    };
+    let name = dir
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "watch".to_string());
+    dir.with_file_name(format!("{}-out", name))
+}
+
+/// Struct representing a watcher: the watched and output directories and the job sent for every
+/// input file.
+pub struct Watcher {
+    /// Client, model, defaults and image processing of the jobs.
+    pub runner: Arc<Runner>,
+    /// Job sent for every input file, whose `image` is set to the file.
+    pub job: Job,
+    /// Watched directory.
+    pub dir: PathBuf,
+    /// Directory the outputs are saved in.
+    pub out: PathBuf,
+    /// Path of the state file.
+    pub state_path: PathBuf,
+}
+
+impl Watcher {
+    /// Returns the job of an input file, saving its output under the name of the input.
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - Path to the input file.
+    pub fn job(&self, file: &Path) -> Job {
+        let stem = file
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let output = format!(
+            "{}/{}.{{ext}}",
+            escape(&self.out.to_string_lossy()),
+            escape(&stem)
+        );
+        Job {
+            image: Some(file.to_string_lossy().into_owned()),
+            output: Some(output),
+            ..self.job.clone()
+        }
+    }
+
+    /// Returns the image files of the watched directory that were not processed successfully,
+    /// in name order.
+    ///
+    /// # Arguments
+    ///
+    /// * `state` - The state of the watcher.
+    pub fn pending(&self, state: &WatchState) -> Result<Vec<PathBuf>> {
+        let entries = std::fs::read_dir(&self.dir)
+            .with_context(|| format!("failed to read {}", self.dir.display()))?;
+        let mut files = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path.is_file()
+                && is_image(&path)
+                && !state.is_done(&file_name(&path), &fingerprint(&path)?)
+            {
+                files.push(path);
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+
+    /// Processes the pending files, then, unless `once` is set, every image file created or
+    /// modified in the watched directory until Ctrl-C. The state is saved after every file, and
+    /// failing files are recorded instead of stopping the watcher.
+    ///
+    /// # Arguments
+    ///
+    /// * `state` - The state of the watcher, receiving one entry per processed file.
+    /// * `once` - Whether to stop after the pending files instead of watching for new ones.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing one record per processed file, or an error if the directory cannot
+    /// be watched or the state cannot be written.
+    pub async fn run(&self, state: &mut WatchState, once: bool) -> Result<Vec<OutputRecord>> {
+        std::fs::create_dir_all(&self.out)
+            .with_context(|| format!("failed to create {}", self.out.display()))?;
+        if std::fs::canonicalize(&self.out)? == std::fs::canonicalize(&self.dir)? {
+            bail!("the output directory must differ from the watched directory");
+        }
+
+        // Events received while the pending files are processed are queued, not lost.
+        let (events, mut received) = mpsc::unbounded_channel();
+        let mut watcher = notify::recommended_watcher(move |event| {
+            let _ = events.send(event);
+        })?;
+        if !once {
+            watcher
+                .watch(&self.dir, RecursiveMode::NonRecursive)
+                .with_context(|| format!("failed to watch {}", self.dir.display()))?;
+        }
+
+        let mut records = Vec::new();
+        for file in self.pending(state)? {
+            records.push(self.process(&file, records.len(), state).await?);
+        }
+        if once {
+            return Ok(records);
+        }
+
+        status!("Watching {} for new images...", self.dir.display());
+        let ctrl_c = tokio::signal::ctrl_c();
+        tokio::pin!(ctrl_c);
+        let mut ticks = tokio::time::interval(SETTLE / 2);
+        // Files waiting for their size to settle, with the size and time of the last change.
+        let mut settling: BTreeMap<PathBuf, (u64, Instant)> = BTreeMap::new();
+        loop {
+            tokio::select! {
+                event = received.recv() => match event {
+                    Some(Ok(event)) => {
+                        if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                            for path in event.paths.into_iter().filter(|path| is_image(path)) {
+                                settling.insert(path, (u64::MAX, Instant::now()));
+                            }
+                        }
+                    }
+                    Some(Err(err)) => eprintln!("Watch error: {}", err),
+                    None => break,
+                },
+                _ = ticks.tick() => {
+                    let mut ready = Vec::new();
+                    settling.retain(|path, (size, changed)| {
+                        let Ok(metadata) = std::fs::metadata(path) else {
+                            // The file was removed or renamed away.
+                            return false;
+                        };
+                        if metadata.len() != *size {
+                            *size = metadata.len();
+                            *changed = Instant::now();
+                        } else if changed.elapsed() >= SETTLE {
+                            ready.push(path.clone());
+                            return false;
+                        }
+                        true
+                    });
+                    for file in ready {
+                        // Already attempted with this content, e.g. after a metadata change.
+                        let attempted = state.files.get(&file_name(&file)).is_some_and(|entry| {
+                            fingerprint(&file).is_ok_and(|hash| hash == entry.fingerprint)
+                        });
+                        if !attempted {
+                            records.push(self.process(&file, records.len(), state).await?);
+                        }
+                    }
+                }
+                _ = &mut ctrl_c => break,
+            }
+        }
+        Ok(records)
+    }
+
+    /// Processes one input file, recording the outcome in the state and saving it.
+    async fn process(
+        &self,
+        file: &Path,
+        index: usize,
+        state: &mut WatchState,
+    ) -> Result<OutputRecord> {
+        let name = file_name(file);
+        status!("Processing {}...", name);
+        let job = self.job(file);
+        let mut entry = FileState {
+            fingerprint: fingerprint(file)?,
+            ..FileState::default()
+        };
+        match self.runner.run_job(index, &job).await {
+            Ok((path, seed, cost)) => {
+                entry.path = Some(path);
+                entry.seed = seed;
+                entry.cost = cost;
+            }
+            Err(err) => {
+                eprintln!("{} failed: {:#}", name, err);
+                entry.seed = job.seed;
+                entry.error = Some(format!("{:#}", err));
+            }
+        }
+        let record = OutputRecord {
+            index,
+            path: entry.path.clone(),
+            seed: entry.seed,
+            cost: entry.cost,
+            error: entry.error.clone(),
+        };
+        state.files.insert(name, entry);
+        state.save(&self.state_path)?;
+        Ok(record)
+    }
+}
+
+/// Returns the file name of a path, the key of its entry in the state.
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
@@ -0,0 +1,319 @@
+//! This module contains invisible watermarking of generated images.
+//!
+//! The payload (a team id and a generation id, protected by a checksum) is embedded into the
+//! luminance of the image with quantization index modulation of low-frequency 8x8 DCT
+//! coefficients. Coefficients are computed on a copy of the image resampled to a fixed grid, so
+//! the watermark survives JPEG recompression and moderate resizing. Every payload bit is spread
+//! over many blocks in a key-dependent order and recovered by soft majority voting.
+
+use anyhow::Result;
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, ImageBuffer, ImageFormat, Luma, Rgba, RgbaImage};
+use sha2::{Digest, Sha256};
+use std::f32::consts::PI;
+use std::io::Cursor;
+
+/// Side of the grid the luminance is resampled to before embedding and detection.
+const GRID: u32 = 256;
+/// Side of a DCT block.
+const BLOCK: usize = 8;
+/// Low-frequency DCT coefficients carrying the payload in every block.
+const COEFFICIENTS: [(usize, usize); 2] = [(0, 1), (1, 0)];
+/// Number of payload bits, including the checksum.
+const BITS: usize = 64;
+/// Number of embedding passes used to compensate for resampling and rounding losses.
+const PASSES: usize = 3;
+
+type LumaImage = ImageBuffer<Luma<f32>, Vec<f32>>;
+
+/// Struct representing the information carried by a watermark.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Payload {
+    /// Identifier of the team that generated the image.
+    pub team_id: u16,
+    /// Identifier of the generation.
+    pub generation_id: u32,
+}
+
+impl Payload {
+    /// Builds the payload of a generated image, deriving the generation id from its contents.
+    ///
+    /// # Arguments
+    ///
+    /// * `team_id` - Identifier of the team that generated the image.
+    /// * `image` - The encoded image returned by the API.
+    pub fn from_output(team_id: u16, image: &[u8]) -> Self {
+        let digest = Sha256::digest(image);
+        Self {
+            team_id,
+            generation_id: u32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]]),
+        }
+    }
+
+    fn to_bits(self) -> [bool; BITS] {
+        let mut data = [0u8; 8];
+        data[..2].copy_from_slice(&self.team_id.to_be_bytes());
+        data[2..6].copy_from_slice(&self.generation_id.to_be_bytes());
+        let checksum = checksum(&data[..6]);
+        data[6..].copy_from_slice(&checksum.to_be_bytes());
+        let value = u64::from_be_bytes(data);
+        std::array::from_fn(|bit| value >> (BITS - 1 - bit) & 1 == 1)
+    }
+
+    fn from_bits(bits: &[bool; BITS]) -> Option<Self> {
+        let value = bits
+            .iter()
+            .fold(0u64, |value, bit| value << 1 | u64::from(*bit));
+        let data = value.to_be_bytes();
+        if checksum(&data[..6]).to_be_bytes() != data[6..] {
+            return None;
+        }
+        Some(Self {
+            team_id: u16::from_be_bytes([data[0], data[1]]),
+            generation_id: u32::from_be_bytes([data[2], data[3], data[4], data[5]]),
+        })
+    }
+}
+
+/// Struct representing a detected watermark.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Detection {
+    /// The recovered payload.
+    pub payload: Payload,
+    /// Average agreement of the votes for each bit, from 0 (noise) to 1 (intact watermark).
+    pub confidence: f32,
+}
+
+/// Settings for embedding and detecting watermarks.
+#[derive(Debug, Clone)]
+pub struct Watermarker {
+    /// Secret key deciding which blocks carry which payload bit.
+    pub key: u64,
+    /// Quantization step of the DCT coefficients. Higher values are more robust but more visible.
+    pub strength: f32,
+}
+
+impl Default for Watermarker {
+    fn default() -> Self {
+        Self {
+            key: 0x6765_7469_6d67_7761,
+            strength: 20.0,
+        }
+    }
+}
+
+impl Watermarker {
+    /// Embeds a payload into an image.
+    ///
+    /// # Arguments
+    ///
+    /// * `image` - The image to watermark. The alpha channel, if any, is preserved.
+    /// * `payload` - The information to embed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use getimg::watermark::{Payload, Watermarker};
+    /// use image::DynamicImage;
+    ///
+    /// let watermarker = Watermarker::default();
+    /// let payload = Payload { team_id: 7, generation_id: 42 };
+    /// let marked = watermarker.embed(&DynamicImage::new_rgb8(256, 256), payload);
+    /// ```
+    pub fn embed(&self, image: &DynamicImage, payload: Payload) -> DynamicImage {
+        let bits = payload.to_bits();
+        let slots = self.slots();
+        let mut rgba = image.to_rgba8();
+        if rgba.width() < BLOCK as u32 || rgba.height() < BLOCK as u32 {
+            return image.clone();
+        }
+
+        for _ in 0..PASSES {
+            let grid = to_grid(&rgba);
+            let mut marked = grid.clone();
+            for (slot, bit) in slots.iter().enumerate() {
+                let (block, (u, v)) = slot_position(slot);
+                let value = coefficient(&marked, block, u, v);
+                let target = self.quantize(value, bits[*bit]);
+                add_basis(&mut marked, block, u, v, target - value);
+            }
+
+            // Float images are resampled in the 0..1 range, so the signed difference is offset.
+            let delta = LumaImage::from_fn(GRID, GRID, |x, y| {
+                let difference = marked.get_pixel(x, y).0[0] - grid.get_pixel(x, y).0[0];
+                Luma([0.5 + difference / 255.0])
+            });
+            let delta = imageops::resize(&delta, rgba.width(), rgba.height(), FilterType::Triangle);
+            for (x, y, pixel) in rgba.enumerate_pixels_mut() {
+                let shift = (delta.get_pixel(x, y).0[0] - 0.5) * 255.0;
+                let [r, g, b, a] = pixel.0;
+                let apply = |channel: u8| (channel as f32 + shift).round().clamp(0.0, 255.0) as u8;
+                *pixel = Rgba([apply(r), apply(g), apply(b), a]);
+            }
+        }
+
+        if image.color().has_alpha() {
+            DynamicImage::ImageRgba8(rgba)
+        } else {
+            DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(rgba).to_rgb8())
+        }
+    }
+
+    /// Detects a watermark embedded with the same key.
+    ///
+    /// # Arguments
+    ///
+    /// * `image` - The possibly recompressed or resized image.
+    ///
+    /// # Returns
+    ///
+    /// The detected watermark, or `None` if no payload with a valid checksum could be recovered.
+    pub fn detect(&self, image: &DynamicImage) -> Option<Detection> {
+        let rgba = image.to_rgba8();
+        if rgba.width() < BLOCK as u32 || rgba.height() < BLOCK as u32 {
+            return None;
+        }
+        let grid = to_grid(&rgba);
+
+        let mut votes = [0f32; BITS];
+        let mut counts = [0f32; BITS];
+        for (slot, bit) in self.slots().iter().enumerate() {
+            let (block, (u, v)) = slot_position(slot);
+            let value = coefficient(&grid, block, u, v);
+            votes[*bit] += (2.0 * PI * value / self.strength).cos();
+            counts[*bit] += 1.0;
+        }
+
+        let bits = std::array::from_fn(|bit| votes[bit] < 0.0);
+        let confidence = votes
+            .iter()
+            .zip(counts)
+            .map(|(vote, count)| vote.abs() / count)
+            .sum::<f32>()
+            / BITS as f32;
+        Payload::from_bits(&bits).map(|payload| Detection {
+            payload,
+            confidence,
+        })
+    }
+
+    /// Embeds a payload into an encoded PNG or JPEG image, keeping its format.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - The encoded image.
+    /// * `payload` - The information to embed.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the re-encoded image. Other formats are returned unchanged.
+    pub fn embed_bytes(&self, bytes: &[u8], payload: Payload) -> Result<Vec<u8>> {
+        let format = image::guess_format(bytes)?;
+        if !matches!(format, ImageFormat::Png | ImageFormat::Jpeg) {
+            return Ok(bytes.to_vec());
+        }
+        let marked = self.embed(&image::load_from_memory(bytes)?, payload);
+        let mut out = Vec::new();
+        match format {
+            ImageFormat::Jpeg => marked
+                .to_rgb8()
+                .write_with_encoder(JpegEncoder::new_with_quality(&mut out, 95))?,
+            _ => marked.write_to(&mut Cursor::new(&mut out), ImageFormat::Png)?,
+        }
+        Ok(out)
+    }
+
+    /// Detects a watermark in an encoded image.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - The encoded image.
+    pub fn detect_bytes(&self, bytes: &[u8]) -> Result<Option<Detection>> {
+        Ok(self.detect(&image::load_from_memory(bytes)?))
+    }
+
+    fn quantize(&self, value: f32, bit: bool) -> f32 {
+        let offset = if bit { self.strength / 2.0 } else { 0.0 };
+        ((value - offset) / self.strength).round() * self.strength + offset
+    }
+
+    /// Assigns a payload bit to every coefficient slot in a key-dependent order.
+    fn slots(&self) -> Vec<usize> {
+        let blocks = (GRID as usize / BLOCK).pow(2);
+        let mut slots = (0..blocks * COEFFICIENTS.len())
+            .map(|slot| slot % BITS)
+            .collect::<Vec<_>>();
+        let mut state = self.key | 1;
+        for index in (1..slots.len()).rev() {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            slots.swap(index, (state % (index as u64 + 1)) as usize);
+        }
+        slots
+    }
+}
+
+fn checksum(data: &[u8]) -> u16 {
+    (crc32fast::hash(data) & 0xFFFF) as u16
+}
+
+/// Resamples the luminance of an image to the embedding grid, in the 0..255 range.
+fn to_grid(image: &RgbaImage) -> LumaImage {
+    let luma = LumaImage::from_fn(image.width(), image.height(), |x, y| {
+        let [r, g, b, _] = image.get_pixel(x, y).0;
+        Luma([(0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32) / 255.0])
+    });
+    let mut grid = imageops::resize(&luma, GRID, GRID, FilterType::Triangle);
+    grid.pixels_mut().for_each(|pixel| pixel.0[0] *= 255.0);
+    grid
+}
+
+fn basis(k: usize, n: usize) -> f32 {
+    let scale = if k == 0 {
+        (1.0 / BLOCK as f32).sqrt()
+    } else {
+        (2.0 / BLOCK as f32).sqrt()
+    };
+    scale * ((2 * n + 1) as f32 * k as f32 * PI / (2 * BLOCK) as f32).cos()
+}
+
+/// Returns the block index and the DCT coefficient of a slot.
+fn slot_position(slot: usize) -> (usize, (usize, usize)) {
+    (
+        slot / COEFFICIENTS.len(),
+        COEFFICIENTS[slot % COEFFICIENTS.len()],
+    )
+}
+
+fn block_origin(block: usize) -> (u32, u32) {
+    let per_row = GRID as usize / BLOCK;
+    (
+        ((block % per_row) * BLOCK) as u32,
+        ((block / per_row) * BLOCK) as u32,
+    )
+}
+
+/// Computes a single DCT coefficient of a block.
+fn coefficient(image: &LumaImage, block: usize, u: usize, v: usize) -> f32 {
+    let (x0, y0) = block_origin(block);
+    let mut value = 0.0;
+    for y in 0..BLOCK {
+        for x in 0..BLOCK {
+            value += basis(u, y) * basis(v, x) * image.get_pixel(x0 + x as u32, y0 + y as u32).0[0];
+        }
+    }
+    value
+}
+
+/// Adds `amount` to a single DCT coefficient of a block.
+fn add_basis(image: &mut LumaImage, block: usize, u: usize, v: usize, amount: f32) {
+    let (x0, y0) = block_origin(block);
+    for y in 0..BLOCK {
+        for x in 0..BLOCK {
+            image.get_pixel_mut(x0 + x as u32, y0 + y as u32).0[0] +=
+                amount * basis(u, y) * basis(v, x);
+        }
+    }
+}
@@ -0,0 +1,257 @@
+#![cfg(feature = "cli")]
+
+mod common;
+
+use anyhow::Result;
+use getimg::batch::{
+    parse_csv, parse_jsonl, spawn_bounded, BatchState, JobKind, JobResult, JobStatus, Runner,
+};
+use getimg::client::Client;
+use getimg::config::{CommandDefaults, Profile};
+use getimg::request::GenerationRequest;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[test]
+fn test_parses_jsonl_and_csv_jobs() -> Result<()> {
+    let jobs = parse_jsonl(
+        r#"
+# campaign A
+{"type": "t2i", "prompt": "A lighthouse at dawn.", "seed": 7, "output": "a/{seed}"}
+
+{"type": "cnet", "id": "city", "prompt": "A watercolor city.", "image": "city.png", "net": "canny-1.1"}
+"#,
+    )?;
+    assert_eq!(jobs.len(), 2);
+    assert_eq!(jobs[0].kind, JobKind::TextToImage);
+    assert_eq!(jobs[0].output.as_deref(), Some("a/{seed}"));
+    assert_eq!(jobs[1].id.as_deref(), Some("city"));
+    assert_eq!(jobs[1].net.as_deref(), Some("canny-1.1"));
+
+    let jobs = parse_csv(
+        "type,prompt,steps,seed,image\nt2i,A lighthouse at dawn.,6,,\ni2i, Make it snowy. ,,3,street.jpg\n",
+    )?;
+    assert_eq!(jobs[0].steps, Some(6));
+    assert_eq!(jobs[0].seed, None);
+    assert_eq!(jobs[1].prompt, "Make it snowy.");
+    assert_eq!(jobs[1].image.as_deref(), Some("street.jpg"));
+
+    let error = parse_jsonl(
+        "{\"type\": \"t2i\", \"prompt\": \"x\"}\n{\"type\": \"t2i\", \"promt\": \"x\"}",
+    )
+    .unwrap_err();
+    assert!(format!("{:#}", error).contains("line 2"));
+    assert!(parse_jsonl(r#"{"prompt": "x"}"#).is_err());
+    assert!(parse_csv("type,prompt\nupscale,x\n").is_err());
+    Ok(())
+}
+
+#[test]
+fn test_jobs_override_profile_defaults() -> Result<()> {
+    let jobs =
+        parse_jsonl(r#"{"type": "t2i", "prompt": "A lighthouse.", "width": 640, "seed": 3}"#)?;
+    let profile = Profile {
+        t2i: CommandDefaults {
+            width: Some(768),
+            height: Some(384),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    let request = jobs[0].request(
+        "lcm-realistic-vision-v5-1",
+        &profile,
+        &common::preprocess(),
+        Path::new(""),
+    )?;
+
+    let GenerationRequest::TextToImage(request) = request else {
+        panic!("expected a text-to-image request");
+    };
+    assert_eq!((request.width, request.height), (640, 384));
+    assert_eq!(request.steps, 4);
+    assert_eq!(request.seed, Some(3));
+
+    let jobs = parse_jsonl(r#"{"type": "paint", "prompt": "A door.", "image": "door.png"}"#)?;
+    let error = jobs[0]
+        .request("m", &profile, &common::preprocess(), Path::new(""))
+        .unwrap_err();
+    assert!(error.to_string().contains("mask_image"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_runs_jobs_and_records_results() -> Result<()> {
+    let requests = Arc::new(AtomicUsize::new(0));
+    let address = common::mock_api(requests.clone()).await?;
+
+    let dir = common::temp_dir("batch")?;
+    let jobs = parse_jsonl(
+        r#"
+{"type": "t2i", "id": "first", "prompt": "A lighthouse.", "seed": 1, "output": "named/{seed}.{ext}"}
+{"type": "t2i", "prompt": "Please fail.", "seed": 2}
+{"type": "t2i", "prompt": "A harbor.", "seed": 3}
+"#,
+    )?;
+    let client = Client::new("key", "lcm-realistic-vision-v5-1")
+        .with_base_url(format!("http://{}", address));
+    let runner = Runner {
+        client,
+        model: "lcm-realistic-vision-v5-1".to_string(),
+        profile: Profile::default(),
+        preprocess: common::preprocess(),
+        postprocess: common::postprocess("{index}-{seed}", Some(dir.clone())),
+        base: dir.clone(),
+        concurrency: 2,
+    };
+    let results = dir.join("jobs.results.jsonl");
+    let state_path = dir.join("jobs.state.json");
+    let mut state = BatchState::new(&jobs);
+
+    let summary = runner
+        .clone()
+        .run(
+            jobs.clone(),
+            &mut state,
+            &state_path,
+            File::create(&results)?,
+        )
+        .await?;
+
+    assert_eq!((summary.succeeded, summary.failed), (2, 1));
+    assert_eq!(summary.cost, 0.5);
+    let mut lines = std::fs::read_to_string(&results)?
+        .lines()
+        .map(serde_json::from_str::<JobResult>)
+        .collect::<Result<Vec<_>, _>>()?;
+    lines.sort_by_key(|result| result.index);
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[0].id.as_deref(), Some("first"));
+    assert_eq!(lines[0].path, Some(dir.join("named").join("1.png")));
+    assert!(!lines[1].ok);
+    assert!(lines[1].error.is_some());
+    assert_eq!(lines[2].path, Some(dir.join("2-3.png")));
+    assert!(dir.join("named").join("1.png").exists());
+    assert!(dir.join("2-3.png").exists());
+    assert_eq!(requests.load(Ordering::SeqCst), 3);
+
+    let mut state = BatchState::open(&state_path)?.resume(&jobs);
+    assert_eq!(state.count(JobStatus::Done), 2);
+    assert_eq!(state.jobs[0].path, Some(dir.join("named").join("1.png")));
+    let summary = runner
+        .run(jobs, &mut state, &state_path, File::create(&results)?)
+        .await?;
+    assert_eq!(
+        (summary.skipped, summary.succeeded, summary.failed),
+        (2, 0, 1)
+    );
+    assert_eq!(requests.load(Ordering::SeqCst), 4);
+    assert_eq!(state.jobs[1].attempts, 2);
+
+    std::fs::remove_dir_all(dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_resume_reruns_unfinished_and_edited_jobs() -> Result<()> {
+    let jobs = parse_jsonl(
+        r#"
+{"type": "t2i", "prompt": "Done.", "seed": 1}
+{"type": "t2i", "prompt": "In flight.", "seed": 2}
+{"type": "t2i", "prompt": "Edited.", "seed": 3}
+"#,
+    )?;
+    let mut state = BatchState::new(&jobs);
+    state.jobs[0].status = JobStatus::Done;
+    state.jobs[1].status = JobStatus::InFlight;
+    state.jobs[2].status = JobStatus::Done;
+
+    let mut edited = jobs.clone();
+    edited[2].seed = Some(4);
+    edited.push(edited[0].clone());
+    let state = state.resume(&edited);
+
+    let statuses = state.jobs.iter().map(|job| job.status).collect::<Vec<_>>();
+    assert_eq!(
+        statuses,
+        [
+            JobStatus::Done,
+            JobStatus::Pending,
+            JobStatus::Pending,
+            JobStatus::Pending
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_resume_matches_jobs_moved_by_inserted_lines() -> Result<()> {
+    let jobs = parse_jsonl(
+        r#"
+{"type": "t2i", "prompt": "A lighthouse.", "seed": 1}
+{"type": "t2i", "prompt": "A harbor.", "seed": 2}
+{"id": "cabin", "type": "t2i", "prompt": "A cabin.", "seed": 3}
+"#,
+    )?;
+    let mut state = BatchState::new(&jobs);
+    for (index, job) in state.jobs.iter_mut().enumerate() {
+        job.status = JobStatus::Done;
+        job.path = Some(PathBuf::from(format!("{}.png", index)));
+    }
+
+    // A line inserted at the top shifts every job, and a renamed id makes a different job.
+    let mut edited = parse_jsonl(r#"{"type": "t2i", "prompt": "A castle.", "seed": 4}"#)?;
+    edited.extend(jobs.iter().cloned());
+    edited[3].id = Some("chalet".to_string());
+    let state = state.resume(&edited);
+
+    let statuses = state.jobs.iter().map(|job| job.status).collect::<Vec<_>>();
+    assert_eq!(
+        statuses,
+        [
+            JobStatus::Pending,
+            JobStatus::Done,
+            JobStatus::Done,
+            JobStatus::Pending
+        ]
+    );
+    assert_eq!(state.jobs[1].path, Some(PathBuf::from("0.png")));
+    assert_eq!(state.jobs[2].path, Some(PathBuf::from("1.png")));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_bounds_tasks_in_flight() -> Result<()> {
+    let running = Arc::new(AtomicUsize::new(0));
+    let peak = Arc::new(AtomicUsize::new(0));
+    let (_, mut tasks) = spawn_bounded(3, 0..10, |item| {
+        let (running, peak) = (running.clone(), peak.clone());
+        async move {
+            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+            peak.fetch_max(now, Ordering::SeqCst);
+            // Uneven durations, so that a new task starts whenever any one ends.
+            tokio::time::sleep(Duration::from_millis(5 + 10 * (item % 3))).await;
+            running.fetch_sub(1, Ordering::SeqCst);
+            item
+        }
+    });
+    let mut done = Vec::new();
+    while let Some(task) = tasks.join_next().await {
+        done.extend(task?);
+    }
+    done.sort();
+    assert_eq!(done, (0..10).collect::<Vec<_>>());
+    assert_eq!(peak.load(Ordering::SeqCst), 3);
+
+    // Tasks not started when the semaphore closes end without output.
+    let (semaphore, mut tasks) = spawn_bounded(1, 0..3, |item| async move { item });
+    semaphore.close();
+    while let Some(task) = tasks.join_next().await {
+        assert_eq!(task?, None);
+    }
+    Ok(())
+}
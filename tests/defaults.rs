@@ -0,0 +1,44 @@
+use getimg::defaults::{ModelDefaults, MAX_SEED};
+use getimg::request::TextToImageRequest;
+use getimg::utils::random_seed;
+
+#[test]
+fn test_model_aware_defaults() {
+    let lcm = ModelDefaults::for_model("lcm-realistic-vision-v5-1");
+    assert_eq!((lcm.width, lcm.height, lcm.steps), (512, 512, 4));
+
+    let sdxl = ModelDefaults::for_model("stable-diffusion-xl-v1-0");
+    assert_eq!((sdxl.width, sdxl.height, sdxl.steps), (1024, 1024, 30));
+
+    let sd = ModelDefaults::for_model("stable-diffusion-v1-5");
+    assert_eq!((sd.width, sd.height, sd.steps), (512, 512, 25));
+    assert_eq!(sd.scheduler, "euler");
+}
+
+#[test]
+fn test_random_seeds_are_in_range() {
+    let seeds = (0..64).map(|_| random_seed()).collect::<Vec<_>>();
+
+    assert!(seeds.iter().all(|seed| (1..=MAX_SEED).contains(seed)));
+    assert!(seeds.iter().any(|seed| *seed != seeds[0]));
+}
+
+#[test]
+fn test_absent_options_are_not_sent() -> anyhow::Result<()> {
+    let request = TextToImageRequest {
+        prompt: "A colorful sunset over the ocean.".to_string(),
+        model: "lcm-realistic-vision-v5-1".to_string(),
+        negative_prompt: None,
+        width: 512,
+        height: 512,
+        steps: 4,
+        output_format: "jpeg".to_string(),
+        seed: Some(42),
+    };
+
+    let json = serde_json::to_value(&request)?;
+
+    assert!(json.get("negative_prompt").is_none());
+    assert_eq!(json["seed"], 42);
+    Ok(())
+}
@@ -0,0 +1,148 @@
+#![cfg(feature = "cli")]
+
+mod common;
+
+use anyhow::Result;
+use clap::Parser;
+use getimg::batch::Runner;
+use getimg::cli::Cli;
+use getimg::client::Client;
+use getimg::config::Profile;
+use getimg::pipeline::Pipeline;
+use getimg::report::{DryRun, Report};
+use getimg::request::{GenerationRequest, ImageToImageRequest};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+fn image_to_image(image: &str) -> GenerationRequest {
+    GenerationRequest::ImageToImage(ImageToImageRequest {
+        model: "lcm-realistic-vision-v5-1".to_string(),
+        prompt: "It's snowy.".to_string(),
+        negative_prompt: None,
+        image: image.to_string(),
+        strength: Some(0.5),
+        steps: 4,
+        output_format: "png".to_string(),
+        seed: Some(5),
+    })
+}
+
+#[test]
+fn test_prepares_redacted_requests() -> Result<()> {
+    let client = Client::new("secret-key", "lcm-realistic-vision-v5-1")
+        .with_base_url("http://localhost:8080");
+    let image = "iVBORw0KGgo".repeat(40);
+    let prepared = client.prepare(&image_to_image(&image))?;
+    assert_eq!(prepared.method, "POST");
+    assert_eq!(
+        prepared.url,
+        "http://localhost:8080/latent-consistency/image-to-image"
+    );
+    assert_eq!(prepared.headers["Authorization"], "Bearer secret-key");
+    assert_eq!(prepared.headers["Content-Type"], "application/json");
+    assert_eq!(prepared.body["image"], image.as_str());
+
+    let redacted = prepared.redacted();
+    assert_eq!(redacted.headers["Authorization"], "Bearer ***");
+    assert_eq!(
+        redacted.body["image"],
+        format!("{}...(408 more characters)", &image[..32])
+    );
+    // Short strings are left alone, even when they look like base64.
+    assert_eq!(redacted.body["prompt"], "It's snowy.");
+    assert_eq!(redacted.body["strength"], 0.5);
+
+    let curl = redacted.to_curl();
+    assert!(curl.starts_with(
+        "curl -X POST 'http://localhost:8080/latent-consistency/image-to-image' \\\n"
+    ));
+    assert!(curl.contains("-H \"Authorization: Bearer $GETIMG_API_KEY\""));
+    assert!(curl.contains("-H 'Content-Type: application/json'"));
+    assert!(curl.contains(r#""prompt":"It'\''s snowy.""#));
+    assert!(!curl.contains("secret-key"));
+    assert!(DryRun::Json.render(&redacted).contains("\"Bearer ***\""));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_sends_prepared_requests() -> Result<()> {
+    let requests = Arc::new(AtomicUsize::new(0));
+    let address = common::mock_api(requests.clone()).await?;
+    let mut client = Client::new("key", "lcm-realistic-vision-v5-1")
+        .with_base_url(format!("http://{}", address));
+
+    let mut report = Report::new("t2i");
+    report.prepare(&client, &common::text_to_image("A fox.", Some(5)))?;
+    report.prepare(&client, &image_to_image("aW1hZ2U="))?;
+    assert_eq!(requests.load(Ordering::SeqCst), 0);
+    assert_eq!(report.requests.len(), 2);
+    assert_eq!(report.seed, Some(5));
+    assert_eq!(report.endpoint, None);
+    assert_eq!(report.model.as_deref(), Some("lcm-realistic-vision-v5-1"));
+
+    let response = client
+        .send(&common::text_to_image("A fox.", Some(5)))
+        .await?;
+    assert_eq!(response.seed, Some(5));
+    assert_eq!(requests.load(Ordering::SeqCst), 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_prepares_independent_pipeline_steps() -> Result<()> {
+    let runner = Runner {
+        client: Client::new("key", "lcm-realistic-vision-v5-1"),
+        model: "lcm-realistic-vision-v5-1".to_string(),
+        profile: Profile::default(),
+        preprocess: common::preprocess(),
+        postprocess: common::postprocess("unused", None),
+        base: PathBuf::new(),
+        concurrency: 1,
+    };
+    let pipeline = Pipeline::from_yaml(
+        r#"
+vars:
+  subject: a lighthouse
+steps:
+  - name: base
+    type: t2i
+    prompt: "{{ subject }} at dawn"
+    seed: 3
+  - name: snowy
+    type: i2i
+    image: "{{ base }}"
+    prompt: "{{ subject }} in the snow"
+  - name: large
+    type: upscale
+    image: "{{ base }}"
+  - name: night
+    type: t2i
+    prompt: "{{ subject }} at night"
+"#,
+    )?;
+    let requests = pipeline.requests(&runner).await?;
+    let names = requests
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["base", "night"]);
+    let GenerationRequest::TextToImage(base) = &requests[0].1 else {
+        panic!("expected a t2i request");
+    };
+    assert_eq!(base.prompt, "a lighthouse at dawn");
+    assert_eq!(base.seed, Some(3));
+    Ok(())
+}
+
+#[test]
+fn test_parses_dry_run_formats() -> Result<()> {
+    let args = Cli::try_parse_from(["getimg", "--dry-run", "t2i", "-p", "A fox."])?;
+    assert_eq!(args.dry_run, Some(DryRun::Json));
+    let args = Cli::try_parse_from(["getimg", "t2i", "-p", "A fox.", "--dry-run=curl"])?;
+    assert_eq!(args.dry_run, Some(DryRun::Curl));
+    let args = Cli::try_parse_from(["getimg", "t2i", "-p", "A fox."])?;
+    assert_eq!(args.dry_run, None);
+    assert!(Cli::try_parse_from(["getimg", "--dry-run=wget", "t2i", "-p", "A fox."]).is_err());
+    Ok(())
+}
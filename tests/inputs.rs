@@ -0,0 +1,200 @@
+#![cfg(feature = "cli")]
+
+mod common;
+
+use anyhow::Result;
+use clap::Parser;
+use getimg::cli::{Cli, InputRun};
+use getimg::client::Client;
+use getimg::config::Profile;
+use getimg::inputs::{self, Input};
+use getimg::report::Report;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+fn write_image(path: &Path) -> Result<()> {
+    std::fs::create_dir_all(path.parent().unwrap())?;
+    image::RgbImage::from_pixel(64, 64, image::Rgb([40, 90, 160])).save(path)?;
+    Ok(())
+}
+
+#[test]
+fn test_expands_directories_and_patterns() -> Result<()> {
+    let root = common::temp_dir("inputs")?;
+    let dir = root.join("sketches");
+    write_image(&dir.join("cabin.png"))?;
+    write_image(&dir.join("cabin_mask.png"))?;
+    write_image(&dir.join("coast/harbor.jpg"))?;
+    write_image(&dir.join(".hidden/boat.png"))?;
+    std::fs::write(dir.join("notes.txt"), "not an image")?;
+
+    let found = inputs::expand(&dir.to_string_lossy(), true)?.unwrap();
+    let relative = found
+        .iter()
+        .map(|input| input.relative.clone())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        relative,
+        [
+            PathBuf::from("cabin.png"),
+            PathBuf::from("coast/harbor.jpg")
+        ]
+    );
+
+    // Commands other than `paint` process masks like any other image.
+    let found = inputs::expand(&dir.to_string_lossy(), false)?.unwrap();
+    assert_eq!(found.len(), 3);
+    assert_eq!(found[1].relative, Path::new("cabin_mask.png"));
+
+    let pattern = format!("{}/**/*.jpg", dir.display());
+    let found = inputs::expand(&pattern, true)?.unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].relative, Path::new("coast/harbor.jpg"));
+
+    assert!(inputs::expand(&dir.join("cabin.png").to_string_lossy(), true)?.is_none());
+    assert!(inputs::expand("-", true)?.is_none());
+    assert!(inputs::expand(&format!("{}/*.webp", dir.display()), true).is_err());
+    std::fs::remove_dir_all(root)?;
+    Ok(())
+}
+
+#[test]
+fn test_pairs_masks_with_images() -> Result<()> {
+    let root = common::temp_dir("inputs-masks")?;
+    write_image(&root.join("sketches/cabin.png"))?;
+    write_image(&root.join("sketches/cabin.mask.png"))?;
+    write_image(&root.join("masks/coast/harbor.png"))?;
+    assert!(inputs::is_mask(Path::new("cabin_mask.png")));
+    assert!(!inputs::is_mask(Path::new("mask.png")));
+
+    let cabin = Input {
+        path: root.join("sketches/cabin.png"),
+        relative: PathBuf::from("cabin.png"),
+    };
+    assert_eq!(
+        inputs::mask_for(&cabin, None),
+        Some(root.join("sketches/cabin.mask.png"))
+    );
+    let harbor = Input {
+        path: root.join("sketches/coast/harbor.jpg"),
+        relative: PathBuf::from("coast/harbor.jpg"),
+    };
+    assert_eq!(inputs::mask_for(&harbor, None), None);
+    assert_eq!(
+        inputs::mask_for(&harbor, Some(&root.join("masks"))),
+        Some(root.join("masks/coast/harbor.png"))
+    );
+    std::fs::remove_dir_all(root)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_mirrors_input_directory() -> Result<()> {
+    let requests = Arc::new(AtomicUsize::new(0));
+    let address = common::mock_api(requests.clone()).await?;
+    let root = common::temp_dir("inputs-run")?;
+    let dir = root.join("sketches");
+    write_image(&dir.join("cabin.png"))?;
+    write_image(&dir.join("coast/harbor.png"))?;
+    write_image(&dir.join("coast/lighthouse.png"))?;
+
+    let image = dir.to_string_lossy();
+    let args = Cli::try_parse_from([
+        "getimg",
+        "i2i",
+        "-p",
+        "A watercolor.",
+        "-i",
+        &image,
+        "-e",
+        "11",
+        "-j",
+        "2",
+        "-o",
+        "png",
+    ])?;
+    let (cmd, inputs) = args.cmd.inputs()?.unwrap();
+    assert_eq!(inputs.len(), 3);
+    let client = Client::new("key", "lcm-realistic-vision-v5-1")
+        .with_base_url(format!("http://{}", address));
+    let postprocess = common::postprocess("{cmd}-{seed}", Some(root.join("out")));
+    let mut report = Report::new("i2i");
+    InputRun {
+        cmd,
+        inputs,
+        count: 1,
+        seeds: args.seeds,
+    }
+    .run(
+        client,
+        "lcm-realistic-vision-v5-1",
+        Profile::default(),
+        postprocess,
+        &mut report,
+    )
+    .await?;
+
+    assert_eq!(requests.load(Ordering::SeqCst), 3);
+    assert_eq!(report.outputs.len(), 3);
+    assert!(report.outputs.iter().all(|output| output.seed == Some(11)));
+    assert_eq!(
+        report.outputs[1].path,
+        Some(root.join("out/i2i-11/coast/harbor.png"))
+    );
+    assert!(root.join("out/i2i-11/cabin.png").is_file());
+    assert!(root.join("out/i2i-11/coast/lighthouse.png").is_file());
+    std::fs::remove_dir_all(root)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_applies_relative_output_dir_once() -> Result<()> {
+    let requests = Arc::new(AtomicUsize::new(0));
+    let address = common::mock_api(requests.clone()).await?;
+    let root = common::temp_dir("inputs-relative")?;
+    write_image(&root.join("sketches/cabin.png"))?;
+    // The only test of this binary relying on the working directory.
+    std::env::set_current_dir(&root)?;
+
+    let args = Cli::try_parse_from([
+        "getimg",
+        "i2i",
+        "-p",
+        "A watercolor.",
+        "-i",
+        "sketches",
+        "-e",
+        "11",
+        "-o",
+        "png",
+    ])?;
+    let (cmd, inputs) = args.cmd.inputs()?.unwrap();
+    let client = Client::new("key", "lcm-realistic-vision-v5-1")
+        .with_base_url(format!("http://{}", address));
+    let postprocess = common::postprocess("{cmd}-{seed}", Some(PathBuf::from("renders")));
+    let mut report = Report::new("i2i");
+    InputRun {
+        cmd,
+        inputs,
+        count: 1,
+        seeds: args.seeds,
+    }
+    .run(
+        client,
+        "lcm-realistic-vision-v5-1",
+        Profile::default(),
+        postprocess,
+        &mut report,
+    )
+    .await?;
+
+    assert_eq!(
+        report.outputs[0].path,
+        Some(PathBuf::from("renders/i2i-11/cabin.png"))
+    );
+    assert!(root.join("renders/i2i-11/cabin.png").is_file());
+    assert!(!root.join("renders/renders").exists());
+    std::fs::remove_dir_all(root)?;
+    Ok(())
+}
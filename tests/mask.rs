@@ -0,0 +1,82 @@
+#![cfg(feature = "image")]
+
+use anyhow::Result;
+use getimg::mask::{Mask, Region, KEEP, PAINT};
+use image::{DynamicImage, Rgba, RgbaImage};
+
+#[test]
+fn test_shapes_mark_areas_for_repainting() {
+    let mask = Mask::new(100, 100)
+        .rectangle(0, 0, 10, 10)
+        .ellipse(50.0, 50.0, 10.0, 5.0)
+        .polygon(&[(80.0, 80.0), (100.0, 80.0), (100.0, 100.0)]);
+
+    assert_eq!(mask.image.get_pixel(5, 5).0[0], PAINT);
+    assert_eq!(mask.image.get_pixel(10, 10).0[0], KEEP);
+    assert_eq!(mask.image.get_pixel(50, 50).0[0], PAINT);
+    assert_eq!(mask.image.get_pixel(50, 58).0[0], KEEP);
+    assert_eq!(mask.image.get_pixel(98, 90).0[0], PAINT);
+    assert_eq!(mask.image.get_pixel(82, 98).0[0], KEEP);
+}
+
+#[test]
+fn test_morphology_and_threshold() {
+    let mask = Mask::new(20, 20).rectangle(5, 5, 10, 10);
+
+    let grown = mask.clone().grow(2);
+    assert_eq!(grown.image.get_pixel(3, 3).0[0], PAINT);
+    assert_eq!(grown.image.get_pixel(2, 2).0[0], KEEP);
+
+    let shrunk = mask.clone().shrink(2);
+    assert_eq!(shrunk.image.get_pixel(6, 6).0[0], KEEP);
+    assert_eq!(shrunk.image.get_pixel(7, 7).0[0], PAINT);
+
+    let inverted = mask.clone().invert();
+    assert_eq!(inverted.image.get_pixel(0, 0).0[0], PAINT);
+
+    let feathered = mask.feather(2.0);
+    let edge = feathered.image.get_pixel(5, 10).0[0];
+    assert!(edge > KEEP && edge < PAINT);
+    assert_eq!(feathered.threshold(128).image.get_pixel(10, 10).0[0], PAINT);
+}
+
+#[test]
+fn test_masks_from_alpha_and_color_key() {
+    let mut image = RgbaImage::from_pixel(4, 4, Rgba([0, 255, 0, 255]));
+    image.put_pixel(0, 0, Rgba([0, 0, 0, 0]));
+    image.put_pixel(3, 3, Rgba([250, 5, 5, 255]));
+    let image = DynamicImage::ImageRgba8(image);
+
+    let alpha = Mask::from_alpha(&image);
+    assert_eq!(alpha.image.get_pixel(0, 0).0[0], PAINT);
+    assert_eq!(alpha.image.get_pixel(1, 1).0[0], KEEP);
+
+    let keyed = Mask::from_color_key(&image, [255, 0, 0], 10);
+    assert_eq!(keyed.image.get_pixel(3, 3).0[0], PAINT);
+    assert_eq!(keyed.image.get_pixel(1, 1).0[0], KEEP);
+}
+
+#[test]
+fn test_validation_and_regions() -> Result<()> {
+    let region: Region = "1,2,3,4".parse()?;
+    assert_eq!(
+        region,
+        Region {
+            x: 1,
+            y: 2,
+            width: 3,
+            height: 4
+        }
+    );
+    assert!("1,2,3".parse::<Region>().is_err());
+
+    let mask = Mask::new(64, 64);
+    assert!(mask.validate(64, 64).is_err());
+    let mask = mask.region(region);
+    assert!(mask.validate(64, 64).is_ok());
+    assert!(mask.validate(32, 64).is_err());
+
+    let decoded = Mask::from_bytes(&mask.to_png()?)?;
+    assert_eq!(decoded, mask);
+    Ok(())
+}
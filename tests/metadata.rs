@@ -0,0 +1,90 @@
+#![cfg(feature = "image")]
+
+mod common;
+
+use anyhow::Result;
+use common::{contains, encode};
+use getimg::metadata::{embed, read, read_parameters, GenerationParams};
+use getimg::request::{GenerationRequest, TextToImageRequest};
+use getimg::response::ToImageResponse;
+use image::{DynamicImage, ImageFormat};
+
+fn params() -> GenerationParams {
+    let request = GenerationRequest::TextToImage(TextToImageRequest {
+        prompt: "A colorful sunset over the ocean.".to_string(),
+        model: "lcm-realistic-vision-v5-1".to_string(),
+        negative_prompt: Some("Disfigured, cartoon, blurry".to_string()),
+        width: 512,
+        height: 512,
+        steps: 4,
+        output_format: "png".to_string(),
+        seed: None,
+    });
+    let response = ToImageResponse {
+        image: String::new(),
+        seed: Some(42),
+        cost: Some(0.0025),
+    };
+    GenerationParams::new(&request, &response)
+}
+
+#[test]
+fn test_a1111_parameters() {
+    assert_eq!(
+        params().to_a1111(),
+        "A colorful sunset over the ocean.\n\
+         Negative prompt: Disfigured, cartoon, blurry\n\
+         Steps: 4, Seed: 42, Size: 512x512, Model: lcm-realistic-vision-v5-1, \
+         Endpoint: latent-consistency/text-to-image, Cost: 0.0025"
+    );
+}
+
+#[test]
+fn test_embeds_png_chunks() -> Result<()> {
+    let png = embed(
+        &encode(DynamicImage::new_rgb8(16, 16), ImageFormat::Png)?,
+        &params(),
+    )?;
+
+    assert!(contains(&png, b"tEXtparameters\0A colorful sunset"));
+    assert!(contains(&png, b"iTXtgetimg\0"));
+    assert!(image::load_from_memory(&png).is_ok());
+
+    let twice = embed(&png, &params())?;
+    assert_eq!(twice, png);
+    Ok(())
+}
+
+#[test]
+fn test_embeds_jpeg_segments() -> Result<()> {
+    let jpeg = embed(
+        &encode(DynamicImage::new_rgb8(16, 16), ImageFormat::Jpeg)?,
+        &params(),
+    )?;
+
+    assert!(contains(&jpeg, b"Exif\0\0MM"));
+    assert!(contains(&jpeg, b"http://ns.adobe.com/xap/1.0/\0"));
+    assert!(contains(&jpeg, b"<getimg:parameters>{&quot;prompt&quot;"));
+    assert!(image::load_from_memory(&jpeg).is_ok());
+    Ok(())
+}
+
+#[test]
+fn test_reads_back_embedded_parameters() -> Result<()> {
+    for format in [ImageFormat::Png, ImageFormat::Jpeg] {
+        let bytes = embed(&encode(DynamicImage::new_rgb8(16, 16), format)?, &params())?;
+
+        let read_back = read(&bytes)?.expect("embedded parameters");
+        assert_eq!(read_back.seed, Some(42));
+        assert_eq!(read_back.cost, Some(0.0025));
+        assert_eq!(read_back.request.command(), "t2i");
+        assert_eq!(
+            read_back.request.prompt(),
+            "A colorful sunset over the ocean."
+        );
+        assert_eq!(read_parameters(&bytes)?, Some(params().to_a1111()));
+    }
+
+    assert!(read(&encode(DynamicImage::new_rgb8(16, 16), ImageFormat::Png)?)?.is_none());
+    Ok(())
+}
@@ -0,0 +1,81 @@
+use anyhow::Result;
+use getimg::output::{escape, extension, render, slug, OutputFields, OutputSettings};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+fn fields() -> OutputFields {
+    OutputFields {
+        cmd: "t2i".to_string(),
+        model: "lcm-realistic-vision-v5-1".to_string(),
+        seed: Some(42),
+        timestamp: UNIX_EPOCH + Duration::from_secs(1_712_188_800),
+        prompt: "A colorful sunset over the ocean.".to_string(),
+        index: 3,
+        ext: "jpg".to_string(),
+    }
+}
+
+#[test]
+fn test_renders_placeholders() -> Result<()> {
+    assert_eq!(
+        render(
+            "{cmd}/{model}/{prompt_slug}-{seed}-{index}-{timestamp}.{ext}",
+            &fields()
+        )?,
+        "t2i/lcm-realistic-vision-v5-1/a-colorful-sunset-over-the-ocean-42-3-20240404-000000.jpg"
+    );
+    assert_eq!(render("{{literal}}", &fields())?, "{literal}");
+    assert_eq!(
+        render(&format!("{}/{{seed}}", escape("out/{draft}")), &fields())?,
+        "out/{draft}/42"
+    );
+    assert!(render("{size}", &fields()).is_err());
+    assert!(render("{cmd", &fields()).is_err());
+    assert!(render("cmd}", &fields()).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_slugs_and_extensions() {
+    assert_eq!(slug("  Hello, World!  "), "hello-world");
+    assert_eq!(slug("!!!"), "untitled");
+    assert!(slug(&"word ".repeat(40)).len() <= 48);
+    assert_eq!(extension(&[0xFF, 0xD8, 0xFF, 0xE0]), "jpg");
+    assert_eq!(extension(b"RIFF\0\0\0\0WEBPVP8 "), "webp");
+    assert_eq!(extension(b"\x89PNG\r\n\x1a\n"), "png");
+}
+
+#[test]
+fn test_paths_never_overwrite() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!(
+        "getimg-output-{}-{}",
+        std::process::id(),
+        SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos()
+    ));
+    let settings = OutputSettings {
+        template: "{cmd}/{seed}".to_string(),
+        dir: Some(dir.clone()),
+    };
+
+    let (first, _) = settings.create(&fields())?;
+    assert_eq!(first, dir.join("t2i").join("42.jpg"));
+    let (second, _) = settings.create(&fields())?;
+    assert_eq!(second, dir.join("t2i").join("42-1.jpg"));
+
+    // Concurrent saves of the same path each claim their own file.
+    let mut paths = std::thread::scope(|scope| {
+        let handles = (0..8)
+            .map(|_| scope.spawn(|| settings.create(&fields()).map(|(path, _)| path)))
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect::<Result<Vec<_>>>()
+    })?;
+    paths.sort();
+    paths.dedup();
+    assert_eq!(paths.len(), 8);
+    assert!(!paths.contains(&first) && !paths.contains(&second));
+
+    std::fs::remove_dir_all(dir)?;
+    Ok(())
+}
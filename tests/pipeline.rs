@@ -0,0 +1,181 @@
+#![cfg(feature = "cli")]
+
+mod common;
+
+use anyhow::Result;
+use getimg::batch::Runner;
+use getimg::client::Client;
+use getimg::config::Profile;
+use getimg::pipeline::{render, Pipeline, PipelineManifest, Var};
+use image::GenericImageView;
+use serde_json::{json, Map, Value};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+const WORKFLOW: &str = r#"
+name: lighthouse
+vars:
+  subject: a lighthouse
+  seed: 42
+steps:
+  - name: base
+    type: t2i
+    prompt: "{{ subject }} at dawn"
+    seed: "{{ seed }}"
+  - name: snowy
+    type: i2i
+    image: "{{ base }}"
+    prompt: "{{ subject }} in the snow"
+    seed: "{{ base.seed }}"
+    steps: 6
+  - name: large
+    type: upscale
+    image: "{{ snowy }}"
+    scale: 1.5
+"#;
+
+#[test]
+fn test_renders_templates() -> Result<()> {
+    let scope = json!({
+        "subject": "a lighthouse",
+        "seed": 42,
+        "base": {"path": "/out/base.png", "seed": 7, "cost": 0.25},
+    });
+    let Value::Object(scope) = scope else {
+        unreachable!();
+    };
+    let rendered = render(
+        &json!({
+            "prompt": "{{ subject }}, seed {{seed}}",
+            "seed": "{{ seed }}",
+            "image": "{{ base }}",
+            "other": ["{{ base.seed }}", 3],
+        }),
+        &scope,
+    )?;
+    assert_eq!(
+        rendered,
+        json!({
+            "prompt": "a lighthouse, seed 42",
+            "seed": 42,
+            "image": "/out/base.png",
+            "other": [7, 3],
+        })
+    );
+    assert!(render(&json!("{{ missing }}"), &scope).is_err());
+    assert!(render(&json!("{{ base.steps }}"), &scope).is_err());
+    assert!(render(&json!("{{ subject"), &Map::new()).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_validates_workflows() -> Result<()> {
+    let pipeline = Pipeline::from_yaml(WORKFLOW)?;
+    assert_eq!(pipeline.name.as_deref(), Some("lighthouse"));
+    assert_eq!(pipeline.steps.len(), 3);
+    assert_eq!(pipeline.steps[2].kind()?, "upscale");
+
+    let error = |yaml: &str| Pipeline::from_yaml(yaml).unwrap_err().to_string();
+    assert_eq!(error("steps: []"), "the pipeline has no steps");
+    assert!(error("steps:\n  - name: a\n    type: upscal\n").contains("unknown type `upscal`"));
+    assert!(error("steps:\n  - name: a b\n    type: t2i\n").contains("invalid step name"));
+    assert!(error(
+        "steps:\n  - name: a\n    type: i2i\n    image: \"{{ b }}\"\n  - name: b\n    type: t2i\n"
+    )
+    .contains("refers to `b`"));
+    assert!(
+        error("steps:\n  - name: a\n    type: t2i\n  - name: a\n    type: t2i\n")
+            .contains("defined twice")
+    );
+    assert!(error("step:\n  - name: a\n").contains("unknown field `step`"));
+
+    let var: Var = "subject=a castle".parse()?;
+    assert_eq!(var.value, json!("a castle"));
+    assert_eq!("seed=7".parse::<Var>()?.value, json!(7));
+    assert!("seed".parse::<Var>().is_err());
+    Ok(())
+}
+
+fn runner(api_url: String, base: PathBuf) -> Runner {
+    let client = Client::new("key", "lcm-realistic-vision-v5-1").with_base_url(api_url);
+    Runner {
+        client,
+        model: "lcm-realistic-vision-v5-1".to_string(),
+        profile: Profile::default(),
+        preprocess: common::preprocess(),
+        postprocess: common::postprocess("unused", None),
+        base,
+        concurrency: 1,
+    }
+}
+
+#[tokio::test]
+async fn test_runs_steps_on_earlier_outputs() -> Result<()> {
+    let requests = Arc::new(AtomicUsize::new(0));
+    let address = common::mock_api(requests.clone()).await?;
+    let dir = common::temp_dir("pipeline")?;
+    let runner = runner(format!("http://{}", address), dir.clone());
+
+    let pipeline = Pipeline::from_yaml(WORKFLOW)?;
+    let mut manifest = PipelineManifest::default();
+    pipeline
+        .run(&runner, &dir.join("run"), &mut manifest)
+        .await?;
+
+    assert_eq!(requests.load(Ordering::SeqCst), 2);
+    assert_eq!(manifest.steps.len(), 3);
+    let base = &manifest.steps[0];
+    assert_eq!(base.path, Some(dir.join("run/base.png")));
+    assert_eq!(base.options["prompt"], "a lighthouse at dawn");
+    let snowy = &manifest.steps[1];
+    assert_eq!(snowy.seed, Some(42));
+    assert_eq!(
+        snowy.options["image"],
+        std::path::absolute(dir.join("run/base.png"))?
+            .display()
+            .to_string()
+    );
+    assert_eq!(manifest.cost(), Some(0.5));
+
+    // The mock API answers with 64x64 images.
+    let large = image::open(dir.join("run/large.png"))?;
+    assert_eq!(large.dimensions(), (96, 96));
+    std::fs::remove_dir_all(dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_stops_at_failing_step() -> Result<()> {
+    let requests = Arc::new(AtomicUsize::new(0));
+    let address = common::mock_api(requests.clone()).await?;
+    let dir = common::temp_dir("pipeline-fail")?;
+    let runner = runner(format!("http://{}", address), dir.clone());
+
+    let pipeline = Pipeline::from_yaml(
+        r#"
+steps:
+  - name: base
+    type: t2i
+    prompt: Please fail.
+  - name: next
+    type: i2i
+    image: "{{ base }}"
+    prompt: A harbor.
+"#,
+    )?;
+    let mut manifest = PipelineManifest::default();
+    let error = pipeline
+        .run(&runner, &dir, &mut manifest)
+        .await
+        .unwrap_err();
+    assert_eq!(
+        format!("{:#}", error),
+        "step `base` failed: the API answered with status 400: bad prompt"
+    );
+    assert_eq!(manifest.steps.len(), 1);
+    assert!(manifest.steps[0].error.is_some());
+    assert_eq!(requests.load(Ordering::SeqCst), 1);
+    std::fs::remove_dir_all(dir)?;
+    Ok(())
+}
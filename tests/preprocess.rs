@@ -0,0 +1,52 @@
+#![cfg(feature = "image")]
+
+mod common;
+
+use anyhow::Result;
+use common::encode;
+use getimg::preprocess::{Fit, OutputFormat, Preprocessor};
+use image::{DynamicImage, ImageFormat, RgbaImage};
+
+#[test]
+fn test_scales_down_and_snaps_large_inputs() -> Result<()> {
+    let source = DynamicImage::new_rgb8(2016, 1512);
+    let bytes = encode(source, ImageFormat::Bmp)?;
+
+    let result = Preprocessor::default().process(&bytes)?;
+
+    assert_eq!((result.width, result.height), (1024, 768));
+    assert_eq!(result.format, OutputFormat::Png);
+    let decoded = image::load_from_memory(&result.data)?;
+    assert_eq!((decoded.width(), decoded.height()), (1024, 768));
+    Ok(())
+}
+
+#[test]
+fn test_pads_and_flattens_alpha() -> Result<()> {
+    let source = RgbaImage::from_pixel(300, 100, image::Rgba([255, 0, 0, 0]));
+    let bytes = encode(DynamicImage::ImageRgba8(source), ImageFormat::Png)?;
+
+    let preprocessor = Preprocessor {
+        fit: Fit::Pad,
+        background: [0, 0, 255],
+        ..Preprocessor::new(512, 512)
+    };
+    let result = preprocessor.process(&bytes)?;
+    let decoded = image::load_from_memory(&result.data)?;
+
+    assert!(!decoded.color().has_alpha());
+    assert_eq!(decoded.to_rgb8().get_pixel(0, 0).0, [0, 0, 255]);
+    assert_eq!(decoded.to_rgb8().get_pixel(256, 256).0, [0, 0, 255]);
+    Ok(())
+}
+
+#[test]
+fn test_target_dimensions_follow_aspect_ratio() {
+    let preprocessor = Preprocessor {
+        width: Some(512),
+        multiple_of: 8,
+        ..Preprocessor::default()
+    };
+
+    assert_eq!(preprocessor.target_dimensions(4032, 3024), (512, 384));
+}
@@ -0,0 +1,101 @@
+#![cfg(feature = "cli")]
+
+use anyhow::Result;
+use clap::Parser;
+use getimg::cli::{Cli, Command};
+use getimg::preview::{self, Protocol};
+use image::{DynamicImage, Rgb, RgbImage};
+use std::collections::HashMap;
+
+fn detect(vars: &[(&str, &str)]) -> Protocol {
+    let vars = vars.iter().copied().collect::<HashMap<_, _>>();
+    Protocol::detect_from(|name| vars.get(name).map(|value| value.to_string()))
+}
+
+fn gradient(width: u32, height: u32) -> DynamicImage {
+    DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |x, y| {
+        Rgb([(x * 4) as u8, (y * 4) as u8, 128])
+    }))
+}
+
+#[test]
+fn test_detects_protocol() {
+    assert_eq!(detect(&[("TERM", "xterm-kitty")]), Protocol::Kitty);
+    assert_eq!(detect(&[("TERM_PROGRAM", "ghostty")]), Protocol::Kitty);
+    assert_eq!(detect(&[("TERM_PROGRAM", "iTerm.app")]), Protocol::Iterm);
+    assert_eq!(detect(&[("LC_TERMINAL", "iTerm2")]), Protocol::Iterm);
+    assert_eq!(detect(&[("TERM", "foot")]), Protocol::Sixel);
+    assert_eq!(detect(&[("TERM", "xterm-256color")]), Protocol::Blocks);
+    assert_eq!(detect(&[]), Protocol::Blocks);
+    // Graphics do not get through tmux.
+    assert_eq!(
+        detect(&[("TERM", "xterm-kitty"), ("TMUX", "/tmp/tmux-0/default,1,0")]),
+        Protocol::Blocks
+    );
+}
+
+#[test]
+fn test_renders_half_blocks() -> Result<()> {
+    let out = String::from_utf8(preview::render(&gradient(64, 32), Protocol::Blocks, 16)?)?;
+    let lines = out.lines().collect::<Vec<_>>();
+    // Scaled to 16 pixels wide and 8 high, two rows of pixels per line.
+    assert_eq!(lines.len(), 4);
+    assert!(lines
+        .iter()
+        .all(|line| line.matches('▀').count() == 16 && line.ends_with("\x1b[0m")));
+    assert!(lines[0].starts_with("\x1b[38;2;") && lines[0].contains(";128;48;2;"));
+
+    // Small images are not enlarged, and an odd last row has no background.
+    let out = String::from_utf8(preview::render(&gradient(3, 3), Protocol::Blocks, 80)?)?;
+    assert_eq!(out.lines().count(), 2);
+    assert_eq!(out.lines().nth(1).unwrap().matches("48;2").count(), 0);
+    Ok(())
+}
+
+#[test]
+fn test_renders_graphics_protocols() -> Result<()> {
+    let image = gradient(400, 200);
+    let kitty = String::from_utf8(preview::render(&image, Protocol::Kitty, 20)?)?;
+    assert!(kitty.starts_with("\x1b_Gf=100,a=T,c=20,m="));
+    assert!(kitty.ends_with("\x1b\\\n"));
+    assert!(kitty.matches("\x1b_G").count() >= 1);
+    assert!(kitty.rsplit("\x1b_G").next().unwrap().contains("m=0;"));
+
+    let iterm = String::from_utf8(preview::render(&image, Protocol::Iterm, 20)?)?;
+    assert!(iterm.starts_with("\x1b]1337;File=inline=1;size="));
+    assert!(iterm.contains(";width=20;preserveAspectRatio=1:iVBORw0KGgo"));
+    assert!(iterm.ends_with("\x07\n"));
+
+    let sixel = String::from_utf8(preview::render(&gradient(12, 14), Protocol::Sixel, 80)?)?;
+    assert!(sixel.starts_with("\x1bPq\"1;1;12;14#0;2;0;0;0"));
+    assert!(sixel.ends_with("\x1b\\\n"));
+    // 14 rows take three bands of six.
+    assert_eq!(sixel.matches('-').count(), 3);
+    Ok(())
+}
+
+#[test]
+fn test_parses_preview_and_view() -> Result<()> {
+    let args = Cli::try_parse_from(["getimg", "--preview", "t2i", "-p", "A fox."])?;
+    assert_eq!(args.preview, Some(Protocol::Auto));
+    let args = Cli::try_parse_from(["getimg", "t2i", "-p", "A fox.", "--preview=sixel"])?;
+    assert_eq!(args.preview, Some(Protocol::Sixel));
+    let args = Cli::try_parse_from(["getimg", "t2i", "-p", "A fox."])?;
+    assert_eq!(args.preview, None);
+
+    let args = Cli::try_parse_from([
+        "getimg",
+        "view",
+        "t2i.png",
+        "--protocol",
+        "kitty",
+        "-w",
+        "40",
+    ])?;
+    let Command::View(view) = &args.cmd else {
+        panic!("expected the view command");
+    };
+    assert_eq!((view.protocol, view.width), (Protocol::Kitty, Some(40)));
+    assert!(!args.cmd.uses_api());
+    Ok(())
+}
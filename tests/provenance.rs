@@ -0,0 +1,77 @@
+#![cfg(feature = "image")]
+
+mod common;
+
+use anyhow::Result;
+use common::encode;
+use getimg::metadata::{self, GenerationParams};
+use getimg::provenance::{label, verify, Manifest, TRAINED_ALGORITHMIC_MEDIA};
+use getimg::request::{GenerationRequest, ImageToImageRequest};
+use getimg::response::ToImageResponse;
+use getimg::utils::rfc3339;
+use image::{DynamicImage, ImageFormat};
+use std::time::{Duration, UNIX_EPOCH};
+
+fn request() -> GenerationRequest {
+    GenerationRequest::ImageToImage(ImageToImageRequest {
+        model: "lcm-realistic-vision-v5-1".to_string(),
+        prompt: "Add a forest in the background.".to_string(),
+        negative_prompt: None,
+        image: "aGVsbG8=".to_string(),
+        strength: Some(0.5),
+        steps: 6,
+        output_format: "png".to_string(),
+        seed: Some(512),
+    })
+}
+
+#[test]
+fn test_manifest_hashes_inputs() {
+    let manifest = Manifest::new(&request());
+
+    assert_eq!(manifest.generator, "getimg");
+    assert_eq!(manifest.action, "c2pa.edited");
+    assert_eq!(manifest.ingredients.len(), 1);
+    assert_eq!(
+        manifest.ingredients[0].hash,
+        "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+    );
+}
+
+#[test]
+fn test_label_round_trips_alongside_parameters() -> Result<()> {
+    let response = ToImageResponse {
+        image: String::new(),
+        seed: Some(512),
+        cost: None,
+    };
+    let manifest = Manifest::new(&request());
+
+    for format in [ImageFormat::Png, ImageFormat::Jpeg] {
+        let image = encode(DynamicImage::new_rgb8(16, 16), format)?;
+        assert!(!verify(&image)?.is_labeled());
+
+        let image = metadata::embed(&image, &GenerationParams::new(&request(), &response))?;
+        let labeled = label(&image, &manifest)?;
+        let relabeled = label(&labeled, &manifest)?;
+        assert_eq!(labeled, relabeled);
+
+        let verification = verify(&labeled)?;
+        assert!(verification.is_labeled());
+        assert_eq!(
+            verification.digital_source_type.as_deref(),
+            Some(TRAINED_ALGORITHMIC_MEDIA)
+        );
+        assert_eq!(verification.manifest.as_ref(), Some(&manifest));
+        assert!(metadata::read(&labeled)?.is_some());
+        assert!(image::load_from_memory(&labeled).is_ok());
+    }
+    Ok(())
+}
+
+#[test]
+fn test_rfc3339() {
+    let time = UNIX_EPOCH + Duration::from_secs(1_711_974_600);
+    assert_eq!(rfc3339(time), "2024-04-01T12:30:00Z");
+    assert_eq!(rfc3339(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+}
@@ -0,0 +1,103 @@
+#![cfg(feature = "cli")]
+
+mod common;
+
+use anyhow::Result;
+use getimg::batch::JobKind;
+use getimg::client::Client;
+use getimg::config::Profile;
+use getimg::repl::{Outcome, Session};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+fn session(api_url: String, dir: PathBuf) -> Session {
+    let client = Client::new("key", "lcm-realistic-vision-v5-1").with_base_url(api_url);
+    Session::new(
+        client,
+        "lcm-realistic-vision-v5-1",
+        Profile::default(),
+        common::preprocess(),
+        common::postprocess("{cmd}-{index}.{ext}", Some(dir)),
+    )
+}
+
+#[test]
+fn test_sets_and_unsets_settings() -> Result<()> {
+    let mut session = session(String::new(), std::env::temp_dir());
+    session.set("steps", "30")?;
+    session.set("negative-prompt", "blurry, 1990s")?;
+    session.set("net", "1")?;
+    session.set("guidance", "7.5")?;
+    assert_eq!(session.settings.steps, Some(30));
+    assert_eq!(
+        session.settings.negative_prompt.as_deref(),
+        Some("blurry, 1990s")
+    );
+    assert_eq!(session.settings.net.as_deref(), Some("1"));
+    assert_eq!(session.settings.guidance, Some(7.5));
+
+    session.unset("steps")?;
+    assert_eq!(session.settings.steps, None);
+
+    assert_eq!(
+        session.set("steps", "many").unwrap_err().to_string(),
+        "invalid value `many` for steps"
+    );
+    assert!(session.set("prompt", "A fox.").is_err());
+    assert!(session.set("sampler", "euler").is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_chains_generations_on_the_latest_image() -> Result<()> {
+    let requests = Arc::new(AtomicUsize::new(0));
+    let address = common::mock_api(requests.clone()).await?;
+    let dir = common::temp_dir("repl")?;
+    let mut session = session(format!("http://{}", address), dir.clone());
+
+    assert!(session.execute("i2i Make it snowy.").await.is_err());
+    assert_eq!(session.execute(":set seed 7").await?, Outcome::Done);
+    let Outcome::Generated(output) = session.execute("t2i A lighthouse.").await? else {
+        panic!("expected an image");
+    };
+    assert_eq!(output.path, Some(dir.join("t2i-0.png")));
+    assert_eq!((output.seed, output.cost), (Some(7), Some(0.25)));
+
+    // Omitting the prompt reuses the previous one, on the latest image.
+    session.execute(":set steps 6").await?;
+    session.execute("i2i").await?;
+    let job = session.transcript.last().unwrap().job.clone().unwrap();
+    assert_eq!(job.kind, JobKind::ImageToImage);
+    assert_eq!(job.prompt, "A lighthouse.");
+    assert_eq!(job.image, Some(dir.join("t2i-0.png").display().to_string()));
+    assert_eq!(job.steps, Some(6));
+    assert_eq!(session.current().unwrap().path, dir.join("i2i-1.png"));
+
+    session.execute(":undo").await?;
+    assert_eq!(session.current().unwrap().path, dir.join("t2i-0.png"));
+    assert!(session.execute("paint A door.").await.is_err());
+    assert!(session.execute("i2i Please fail.").await.is_err());
+    assert_eq!(session.execute(":quit").await?, Outcome::Quit);
+    assert_eq!(requests.load(Ordering::SeqCst), 3);
+
+    let transcript = dir.join("session.jsonl");
+    session
+        .execute(&format!(":save {}", transcript.display()))
+        .await?;
+    let lines = std::fs::read_to_string(&transcript)?
+        .lines()
+        .map(serde_json::from_str)
+        .collect::<Result<Vec<serde_json::Value>, _>>()?;
+    assert_eq!(lines.len(), 9);
+    assert_eq!(lines[0]["input"], "i2i Make it snowy.");
+    assert!(lines[0]["error"].as_str().unwrap().contains("t2i"));
+    assert_eq!(lines[2]["seed"], 7);
+    assert_eq!(lines[2]["job"]["prompt"], "A lighthouse.");
+    assert_eq!(
+        lines[7]["error"],
+        "the API answered with status 400: bad prompt"
+    );
+    std::fs::remove_dir_all(dir)?;
+    Ok(())
+}
@@ -0,0 +1,112 @@
+#![cfg(feature = "cli")]
+
+mod common;
+
+use anyhow::{anyhow, Context, Result};
+use getimg::client::{ApiError, Client};
+use getimg::report::{ErrorKind, OutputRecord, Report};
+use std::path::PathBuf;
+use std::sync::atomic::AtomicUsize;
+use std::sync::Arc;
+
+#[test]
+fn test_reads_api_error_messages() {
+    let error = ApiError::new(
+        402,
+        r#"{"error": {"message": "Not enough credits.", "code": 1}}"#,
+    );
+    assert_eq!(error.message, "Not enough credits.");
+    assert_eq!(
+        ApiError::new(400, r#"{"error": "bad prompt"}"#).message,
+        "bad prompt"
+    );
+    assert_eq!(ApiError::new(502, "Bad Gateway\n").message, "Bad Gateway");
+    assert_eq!(
+        ApiError::new(503, "").to_string(),
+        "the API answered with status 503"
+    );
+}
+
+#[test]
+fn test_maps_errors_to_exit_codes() {
+    let api = |status: u16| ErrorKind::of(&ApiError::new(status, "").into());
+    assert_eq!(api(401), ErrorKind::Auth);
+    assert_eq!(api(429), ErrorKind::RateLimited);
+    assert_eq!(api(422), ErrorKind::InvalidRequest);
+    assert_eq!(api(500), ErrorKind::Server);
+
+    let io = Err::<(), _>(std::io::Error::from(std::io::ErrorKind::NotFound))
+        .context("failed to read input.png")
+        .unwrap_err();
+    assert_eq!(ErrorKind::of(&io), ErrorKind::Io);
+    assert_eq!(ErrorKind::of(&anyhow!("invalid mask")), ErrorKind::Other);
+
+    let wrapped = ErrorKind::Incomplete.wrap(io.context("2 of 4 jobs failed"));
+    assert_eq!(ErrorKind::of(&wrapped), ErrorKind::Incomplete);
+    assert_eq!(
+        format!("{:#}", wrapped),
+        "2 of 4 jobs failed: failed to read input.png: entity not found"
+    );
+    assert_eq!(ErrorKind::Incomplete.exit_code(), 10);
+}
+
+#[tokio::test]
+async fn test_categorizes_api_responses() -> Result<()> {
+    let address = common::mock_api(Arc::new(AtomicUsize::new(0))).await?;
+    let mut client = Client::new("key", "lcm-realistic-vision-v5-1")
+        .with_base_url(format!("http://{}", address));
+
+    let error = client
+        .send(&common::text_to_image("Please fail.", Some(5)))
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.downcast_ref::<ApiError>(),
+        Some(&ApiError {
+            status: 400,
+            message: "bad prompt".to_string()
+        })
+    );
+    assert_eq!(ErrorKind::of(&error), ErrorKind::InvalidRequest);
+
+    client = client.with_base_url("http://127.0.0.1:1");
+    let error = client
+        .send(&common::text_to_image("A lighthouse.", Some(5)))
+        .await
+        .unwrap_err();
+    assert_eq!(ErrorKind::of(&error), ErrorKind::Network);
+    Ok(())
+}
+
+#[test]
+fn test_serializes_reports() -> Result<()> {
+    let mut report = Report::new("t2i");
+    report.request(&common::text_to_image("A lighthouse.", Some(5)));
+    report.output(OutputRecord {
+        index: 0,
+        seed: Some(5),
+        error: Some("bad prompt".to_string()),
+        ..OutputRecord::default()
+    });
+    report.output(OutputRecord {
+        index: 1,
+        path: Some(PathBuf::from("t2i-6.png")),
+        seed: Some(6),
+        cost: Some(0.25),
+        error: None,
+    });
+    report.fail(&ErrorKind::Incomplete.wrap(anyhow!("1 of 2 variations failed")));
+
+    let json = serde_json::to_value(&report)?;
+    assert_eq!(json["ok"], false);
+    assert_eq!(json["endpoint"], "latent-consistency/text-to-image");
+    assert_eq!(json["model"], "lcm-realistic-vision-v5-1");
+    assert_eq!(json["seed"], 6);
+    assert_eq!(json["cost"], 0.25);
+    assert_eq!(json["outputs"][1]["path"], "t2i-6.png");
+    assert_eq!(json["outputs"][0]["error"], "bad prompt");
+    assert_eq!(json["error"]["kind"], "incomplete");
+    assert_eq!(json["error"]["code"], 10);
+    assert_eq!(report.exit_code(), 10);
+    Ok(())
+}
@@ -0,0 +1,116 @@
+#![cfg(feature = "image")]
+
+mod common;
+
+use anyhow::Result;
+use common::{contains, encode};
+use getimg::metadata::{self, GenerationParams};
+use getimg::preprocess;
+use getimg::provenance::{self, Manifest};
+use getimg::response::ToImageResponse;
+use getimg::sanitize::strip;
+use image::{DynamicImage, ImageFormat};
+
+fn segment(marker: u8, payload: &[u8]) -> Vec<u8> {
+    let mut out = vec![0xFF, marker];
+    out.extend_from_slice(&((payload.len() + 2) as u16).to_be_bytes());
+    out.extend_from_slice(payload);
+    out
+}
+
+/// Little-endian EXIF with orientation, camera make and a GPS IFD pointer.
+fn exif() -> Vec<u8> {
+    let mut out = b"Exif\0\0II\x2a\0".to_vec();
+    out.extend_from_slice(&8u32.to_le_bytes());
+    out.extend_from_slice(&3u16.to_le_bytes());
+    for (tag, kind, value) in [(0x010Fu16, 2u16, 0u32), (0x0112, 3, 6), (0x8825, 4, 0)] {
+        out.extend_from_slice(&tag.to_le_bytes());
+        out.extend_from_slice(&kind.to_le_bytes());
+        out.extend_from_slice(&1u32.to_le_bytes());
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+    out.extend_from_slice(&0u32.to_le_bytes());
+    out
+}
+
+#[test]
+fn test_strips_jpeg_and_keeps_orientation() -> Result<()> {
+    let jpeg = encode(DynamicImage::new_rgb8(40, 20), ImageFormat::Jpeg)?;
+    let mut bytes = jpeg[..2].to_vec();
+    bytes.extend(segment(0xE1, &exif()));
+    bytes.extend(segment(0xE1, b"http://ns.adobe.com/xap/1.0/\0<x:xmpmeta/>"));
+    bytes.extend(segment(0xE2, b"ICC_PROFILE\0\x01\x01profile"));
+    bytes.extend(segment(0xFE, b"taken at home"));
+    bytes.extend_from_slice(&jpeg[2..]);
+
+    let sanitized = strip(&bytes)?;
+
+    assert_eq!(
+        sanitized.removed,
+        [
+            "EXIF (GPS location, camera and lens model)",
+            "XMP",
+            "ICC profile",
+            "comment"
+        ]
+    );
+    assert!(!contains(&sanitized.data, b"ns.adobe.com"));
+    assert!(!contains(&sanitized.data, b"ICC_PROFILE"));
+    assert!(!contains(&sanitized.data, b"taken at home"));
+    let decoded = preprocess::decode(&sanitized.data)?;
+    assert_eq!((decoded.width(), decoded.height()), (20, 40));
+    Ok(())
+}
+
+#[test]
+fn test_strips_png_text_and_xmp() -> Result<()> {
+    let request = common::text_to_image("A colorful sunset over the ocean.", Some(42));
+    let response = ToImageResponse {
+        image: String::new(),
+        seed: Some(42),
+        cost: None,
+    };
+    let png = encode(DynamicImage::new_rgb8(16, 16), ImageFormat::Png)?;
+    let png = metadata::embed(&png, &GenerationParams::new(&request, &response))?;
+    let png = provenance::label(&png, &Manifest::new(&request))?;
+
+    let sanitized = strip(&png)?;
+
+    assert_eq!(sanitized.removed.len(), 3);
+    assert!(sanitized.removed.contains(&"text `parameters`".to_string()));
+    assert!(sanitized.removed.contains(&"XMP".to_string()));
+    assert_eq!(metadata::read_parameters(&sanitized.data)?, None);
+    assert!(!provenance::verify(&sanitized.data)?.is_labeled());
+    assert!(image::load_from_memory(&sanitized.data).is_ok());
+    Ok(())
+}
+
+#[test]
+fn test_clean_images_are_unchanged() -> Result<()> {
+    let png = encode(DynamicImage::new_rgb8(16, 16), ImageFormat::Png)?;
+
+    let sanitized = strip(&png)?;
+    assert_eq!(sanitized.data, png);
+    assert!(sanitized.removed.is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_reencodes_other_formats_as_png() -> Result<()> {
+    for format in [ImageFormat::Bmp, ImageFormat::Tiff, ImageFormat::WebP] {
+        let bytes = encode(DynamicImage::new_rgb8(16, 8), format)?;
+
+        let sanitized = strip(&bytes)?;
+        assert!(
+            sanitized.data.starts_with(b"\x89PNG"),
+            "{format:?} is re-encoded"
+        );
+        assert_eq!(
+            image::load_from_memory(&sanitized.data)?.into_rgb8(),
+            image::load_from_memory(&bytes)?.into_rgb8()
+        );
+        assert_eq!(sanitized.removed.len(), 1);
+    }
+    assert!(strip(b"not an image").is_err());
+    Ok(())
+}
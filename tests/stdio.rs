@@ -0,0 +1,65 @@
+#![cfg(feature = "cli")]
+
+use anyhow::Result;
+use clap::Parser;
+use getimg::cli::{Cli, Command};
+use getimg::output::OutputSettings;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[test]
+fn test_reads_prompt_files() -> Result<()> {
+    let path = std::env::temp_dir().join(format!(
+        "getimg-prompt-{}-{}.txt",
+        std::process::id(),
+        SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos()
+    ));
+    std::fs::write(&path, "A lighthouse at dawn.\n")?;
+
+    let args = Cli::try_parse_from([
+        "getimg".as_ref(),
+        "t2i".as_ref(),
+        "--prompt-file".as_ref(),
+        path.as_os_str(),
+    ])?;
+    let Command::TextToImage(mut cmd) = args.cmd else {
+        panic!("expected t2i");
+    };
+    cmd.read_prompt()?;
+    assert_eq!(cmd.prompt, "A lighthouse at dawn.");
+
+    std::fs::write(&path, "  \n")?;
+    assert!(cmd.read_prompt().is_err());
+    std::fs::remove_file(path)?;
+    Ok(())
+}
+
+#[test]
+fn test_parses_stdio_arguments() -> Result<()> {
+    let args = Cli::try_parse_from(["getimg", "-O", "-", "i2i", "-p", "Snow.", "-i", "-"])?;
+    assert_eq!(args.output.as_deref(), Some("-"));
+    let Command::ImageToImage(mut cmd) = args.cmd else {
+        panic!("expected i2i");
+    };
+    assert_eq!(cmd.image, "-");
+    // A prompt given inline is kept as is.
+    cmd.read_prompt()?;
+    assert_eq!(cmd.prompt, "Snow.");
+
+    let Command::ImageToImage(mut cmd) =
+        Cli::try_parse_from(["getimg", "i2i", "-p", "-", "-i", "-"])?.cmd
+    else {
+        panic!("expected i2i");
+    };
+    assert!(cmd.read_prompt().is_err());
+
+    assert!(Cli::try_parse_from(["getimg", "t2i"]).is_err());
+    assert!(Cli::try_parse_from(["getimg", "t2i", "-p", "x", "--prompt-file", "p.txt"]).is_err());
+
+    let settings = OutputSettings {
+        template: "-".to_string(),
+        dir: None,
+    };
+    assert!(settings.is_stdout());
+    assert!(!OutputSettings::default().is_stdout());
+    Ok(())
+}
@@ -0,0 +1,196 @@
+#![cfg(feature = "image")]
+
+mod common;
+
+use anyhow::Result;
+use getimg::request::{ControlNetRequest, GenerationRequest};
+use getimg::sweep::{self, apply, render_grid, Axis};
+use image::{DynamicImage, GenericImageView, Rgb, RgbImage};
+
+#[test]
+fn test_parses_axes() -> Result<()> {
+    let axis: Axis = "steps=4, 8,12".parse()?;
+    assert_eq!(axis.name, "steps");
+    assert_eq!(axis.values, ["4", "8", "12"]);
+
+    let axis: Axis = "negative-prompt=blurry,cartoon".parse()?;
+    assert_eq!(axis.name, "negative_prompt");
+
+    assert!("steps".parse::<Axis>().is_err());
+    assert!("steps=".parse::<Axis>().is_err());
+    assert!("sampler=euler".parse::<Axis>().is_err());
+    Ok(())
+}
+
+#[test]
+fn test_builds_cells_in_grid_order() -> Result<()> {
+    let axes = [
+        "steps=4,8,12".parse::<Axis>()?,
+        "height=512,768".parse::<Axis>()?,
+    ];
+    let cells = sweep::cells(&common::text_to_image("A lighthouse.", Some(9)), &axes)?;
+
+    assert_eq!(cells.len(), 6);
+    assert_eq!(cells[4].position, [1, 1]);
+    assert_eq!(
+        cells[4].values,
+        [
+            ("steps".to_string(), "8".to_string()),
+            ("height".to_string(), "768".to_string())
+        ]
+    );
+    let GenerationRequest::TextToImage(request) = &cells[4].request else {
+        panic!("expected a text-to-image request");
+    };
+    assert_eq!(
+        (request.steps, request.height, request.seed),
+        (8, 768, Some(9))
+    );
+
+    assert!(sweep::cells(&common::text_to_image("A lighthouse.", Some(9)), &[]).is_err());
+    assert!(sweep::cells(
+        &common::text_to_image("A lighthouse.", Some(9)),
+        &["steps=four".parse()?]
+    )
+    .is_err());
+    Ok(())
+}
+
+#[test]
+fn test_rejects_parameters_missing_from_endpoint() -> Result<()> {
+    let mut request = common::text_to_image("A lighthouse.", Some(9));
+    let error = apply(&mut request, "guidance", "7.5").unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "`t2i` requests have no guidance to sweep"
+    );
+
+    let mut request = GenerationRequest::ControlNet(ControlNetRequest {
+        controlnet: "canny-1.1".to_string(),
+        model: "lcm-realistic-vision-v5-1".to_string(),
+        prompt: "A watercolor city.".to_string(),
+        negative_prompt: None,
+        image: String::new(),
+        strength: 1.0,
+        width: 512,
+        height: 512,
+        steps: 25,
+        guidance: 7.5,
+        seed: 1,
+        scheduler: "euler".to_string(),
+        output_format: "png".to_string(),
+    });
+    apply(&mut request, "guidance", "5")?;
+    apply(&mut request, "scheduler", "ddim")?;
+    let GenerationRequest::ControlNet(request) = request else {
+        unreachable!();
+    };
+    assert_eq!(
+        (request.guidance, request.scheduler.as_str()),
+        (5.0, "ddim")
+    );
+    Ok(())
+}
+
+#[test]
+fn test_renders_labeled_grid() -> Result<()> {
+    let axes = [
+        "steps=4,8".parse::<Axis>()?,
+        "height=512,768".parse::<Axis>()?,
+        "model=a,b".parse::<Axis>()?,
+    ];
+    let red = DynamicImage::ImageRgb8(RgbImage::from_pixel(64, 32, Rgb([255, 0, 0])));
+    let mut images = vec![Some(red); 8];
+    images[3] = None;
+
+    let grid = render_grid(&axes, &images, 64);
+
+    // Two panels of a row label column and two cells, with a title row and a header row.
+    let left = "height=768".len() as u32 * 10 + 16;
+    assert_eq!(grid.width(), 2 * (left + 128) + 32);
+    assert_eq!(grid.height(), 2 * 36 + 128);
+
+    // Cells are letterboxed, and the missing cell is a gray box.
+    let (x, y) = (left, 72);
+    assert_eq!(*grid.get_pixel(x + 32, y + 32), Rgb([255, 0, 0]));
+    assert_eq!(*grid.get_pixel(x + 32, y + 4), Rgb([255, 255, 255]));
+    assert_eq!(
+        *grid.get_pixel(x + 64 + 60, y + 64 + 60),
+        Rgb([200, 200, 200])
+    );
+
+    // Labels are drawn in black.
+    let header = grid.view(left, 36, 128, 36).to_image();
+    assert!(header.pixels().any(|pixel| *pixel == Rgb([0, 0, 0])));
+    Ok(())
+}
+
+#[test]
+fn test_renders_cells_smaller_than_labels() -> Result<()> {
+    let axes = [
+        "steps=4,8".parse::<Axis>()?,
+        "height=512,768".parse::<Axis>()?,
+    ];
+    for cell_size in [0, 1, 2, 8] {
+        let grid = render_grid(&axes, &[None, None, None, None], cell_size);
+        assert!(grid.width() > 0 && grid.height() > 0);
+    }
+    Ok(())
+}
+
+#[cfg(feature = "cli")]
+#[tokio::test]
+async fn test_keeps_sweeping_past_cells_that_fail_to_save() -> Result<()> {
+    use clap::Parser;
+    use getimg::cli::{Cli, Command};
+    use getimg::client::Client;
+    use getimg::config::Profile;
+    use getimg::report::Report;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let requests = Arc::new(AtomicUsize::new(0));
+    let address = common::mock_api(requests.clone()).await?;
+    let dir = common::temp_dir("sweep-run")?;
+    let grid = dir.join("grid.png");
+    let args = Cli::try_parse_from([
+        "getimg",
+        "sweep",
+        "-x",
+        "prompt=A lighthouse.,A garbled lighthouse.,A harbor.",
+        "--grid",
+        &grid.to_string_lossy(),
+        "t2i",
+        "-p",
+        "A lighthouse.",
+        "-e",
+        "9",
+    ])?;
+    let Command::Sweep(sweep) = args.cmd else {
+        panic!("expected a sweep");
+    };
+    let client = Client::new("key", "lcm-realistic-vision-v5-1")
+        .with_base_url(format!("http://{}", address));
+    let mut report = Report::new("sweep");
+    let result = sweep
+        .run(
+            client,
+            "lcm-realistic-vision-v5-1",
+            Profile::default(),
+            common::postprocess("{cmd}-{seed}", None),
+            &mut report,
+        )
+        .await;
+
+    assert!(result.unwrap_err().to_string().contains("1 of 3"));
+    assert_eq!(requests.load(Ordering::SeqCst), 3);
+    assert!(image::open(&grid).is_ok());
+    let manifest: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(dir.join("grid.json"))?)?;
+    let cells = manifest["cells"].as_array().unwrap();
+    assert_eq!(cells.len(), 3);
+    assert!(cells[1]["error"].is_string() && cells[1]["path"].is_null());
+    assert!(dir.join("grid/0.png").is_file() && dir.join("grid/2.png").is_file());
+    std::fs::remove_dir_all(dir)?;
+    Ok(())
+}
@@ -0,0 +1,74 @@
+#![cfg(feature = "image")]
+
+mod common;
+
+use anyhow::Result;
+use getimg::client::Client;
+use getimg::defaults::MAX_SEED;
+use getimg::request::{GenerationRequest, SeedMode};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+fn seeds(requests: &[GenerationRequest]) -> Vec<usize> {
+    requests
+        .iter()
+        .filter_map(|request| request.seed())
+        .collect()
+}
+
+#[test]
+fn test_variation_seeds() {
+    let sequential = common::text_to_image("A lighthouse.", Some(MAX_SEED - 1))
+        .variations(3, SeedMode::Sequential);
+    assert_eq!(seeds(&sequential), [MAX_SEED - 1, MAX_SEED, 1]);
+
+    let random =
+        seeds(&common::text_to_image("A lighthouse.", Some(7)).variations(4, SeedMode::Random));
+    assert_eq!(random[0], 7);
+    assert!(random.iter().all(|seed| (1..=MAX_SEED).contains(seed)));
+
+    let unseeded =
+        seeds(&common::text_to_image("A lighthouse.", None).variations(2, SeedMode::Sequential));
+    assert_eq!(unseeded[1], unseeded[0] % MAX_SEED + 1);
+}
+
+#[tokio::test]
+async fn test_sends_variations_concurrently() -> Result<()> {
+    let requests = Arc::new(AtomicUsize::new(0));
+    let address = common::mock_api(requests.clone()).await?;
+    let client = Client::new("key", "lcm-realistic-vision-v5-1")
+        .with_base_url(format!("http://{}", address));
+
+    let responses = client
+        .send_variations(
+            &common::text_to_image("A lighthouse.", Some(10)),
+            4,
+            SeedMode::Sequential,
+        )
+        .await?;
+
+    let seeds = responses
+        .iter()
+        .map(|response| response.seed)
+        .collect::<Vec<_>>();
+    assert_eq!(seeds, [Some(10), Some(11), Some(12), Some(13)]);
+    assert_eq!(requests.load(Ordering::SeqCst), 4);
+
+    let results = client
+        .send_all(&[
+            common::text_to_image("A harbor.", Some(1)),
+            common::text_to_image("Please fail.", Some(2)),
+        ])
+        .await;
+    assert!(results[0].is_ok());
+    assert!(results[1].is_err());
+    assert!(client
+        .send_variations(
+            &common::text_to_image("Please fail.", Some(3)),
+            2,
+            SeedMode::Random
+        )
+        .await
+        .is_err());
+    Ok(())
+}
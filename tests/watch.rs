@@ -0,0 +1,157 @@
+#![cfg(feature = "cli")]
+
+mod common;
+
+use anyhow::Result;
+use getimg::batch::{Job, JobKind, Runner};
+use getimg::client::Client;
+use getimg::config::Profile;
+use getimg::watch::{self, WatchState, Watcher};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+fn write_image(path: &Path, shade: u8) -> Result<()> {
+    image::RgbImage::from_pixel(64, 64, image::Rgb([shade, 90, 160])).save(path)?;
+    Ok(())
+}
+
+fn watcher(api_url: String, dir: &Path, prompt: &str) -> Watcher {
+    let client = Client::new("key", "lcm-realistic-vision-v5-1").with_base_url(api_url);
+    let out = dir.with_file_name(format!(
+        "{}-out",
+        dir.file_name().unwrap().to_string_lossy()
+    ));
+    Watcher {
+        runner: Arc::new(Runner {
+            client,
+            model: "lcm-realistic-vision-v5-1".to_string(),
+            profile: Profile::default(),
+            preprocess: common::preprocess(),
+            postprocess: common::postprocess("unused", None),
+            base: PathBuf::new(),
+            concurrency: 1,
+        }),
+        job: Job {
+            kind: JobKind::ImageToImage,
+            id: None,
+            prompt: prompt.to_string(),
+            model: None,
+            image: None,
+            mask_image: None,
+            seed: Some(7),
+            output: None,
+            negative_prompt: None,
+            width: None,
+            height: None,
+            steps: None,
+            guidance: None,
+            scheduler: None,
+            strength: None,
+            image_guidance: None,
+            output_format: Some("png".to_string()),
+            net: None,
+        },
+        dir: dir.to_path_buf(),
+        state_path: out.join(watch::STATE_FILE),
+        out,
+    }
+}
+
+#[test]
+fn test_selects_image_files() {
+    assert!(watch::is_image(Path::new("sketches/cabin.png")));
+    assert!(watch::is_image(Path::new("sketches/cabin.JPG")));
+    assert!(!watch::is_image(Path::new("sketches/notes.txt")));
+    assert!(!watch::is_image(Path::new("sketches/.cabin.png")));
+    assert!(!watch::is_image(Path::new("sketches/cabin")));
+    assert_eq!(
+        watch::out_dir(Path::new("shared/sketches")),
+        Path::new("shared/sketches-out")
+    );
+    assert_eq!(
+        watch::out_dir(Path::new("sketches/")),
+        Path::new("sketches-out")
+    );
+}
+
+#[tokio::test]
+async fn test_skips_processed_files_on_restart() -> Result<()> {
+    let requests = Arc::new(AtomicUsize::new(0));
+    let address = common::mock_api(requests.clone()).await?;
+    let root = common::temp_dir("watch")?;
+    let dir = root.join("sketches");
+    std::fs::create_dir_all(&dir)?;
+    write_image(&dir.join("cabin.png"), 10)?;
+    write_image(&dir.join("harbor.png"), 20)?;
+    std::fs::write(dir.join("notes.txt"), "not an image")?;
+    let watcher = watcher(format!("http://{}", address), &dir, "A watercolor.");
+
+    let mut state = WatchState::open(&watcher.state_path)?;
+    let records = watcher.run(&mut state, true).await?;
+    assert_eq!(requests.load(Ordering::SeqCst), 2);
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0].path, Some(root.join("sketches-out/cabin.png")));
+    assert_eq!(records[1].seed, Some(7));
+    assert!(root.join("sketches-out/harbor.png").is_file());
+
+    // A restarted watcher only processes new and modified files.
+    let mut state = WatchState::open(&watcher.state_path)?;
+    assert_eq!(state.files.len(), 2);
+    assert!(watcher.pending(&state)?.is_empty());
+    write_image(&dir.join("harbor.png"), 30)?;
+    write_image(&dir.join("lighthouse.png"), 40)?;
+    let records = watcher.run(&mut state, true).await?;
+    assert_eq!(records.len(), 2);
+    assert_eq!(requests.load(Ordering::SeqCst), 4);
+    std::fs::remove_dir_all(root)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_records_failures_for_retry() -> Result<()> {
+    let requests = Arc::new(AtomicUsize::new(0));
+    let address = common::mock_api(requests.clone()).await?;
+    let root = common::temp_dir("watch-fail")?;
+    let dir = root.join("sketches");
+    std::fs::create_dir_all(&dir)?;
+    write_image(&dir.join("cabin.png"), 10)?;
+    let watcher = watcher(format!("http://{}", address), &dir, "Please fail.");
+
+    let mut state = WatchState::default();
+    let records = watcher.run(&mut state, true).await?;
+    assert_eq!(
+        records[0].error.as_deref(),
+        Some("the API answered with status 400: bad prompt")
+    );
+    let state = WatchState::open(&watcher.state_path)?;
+    assert!(state.files["cabin.png"].error.is_some());
+    assert_eq!(watcher.pending(&state)?, [dir.join("cabin.png")]);
+    std::fs::remove_dir_all(root)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_processes_new_files() -> Result<()> {
+    let requests = Arc::new(AtomicUsize::new(0));
+    let address = common::mock_api(requests.clone()).await?;
+    let root = common::temp_dir("watch-new")?;
+    let dir = root.join("sketches");
+    std::fs::create_dir_all(&dir)?;
+    let watcher = watcher(format!("http://{}", address), &dir, "A watercolor.");
+
+    let drop_dir = dir.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        write_image(&drop_dir.join("cabin.png"), 10).unwrap();
+    });
+    let mut state = WatchState::default();
+    // The watcher runs until interrupted, so it is stopped once the file had time to settle.
+    let _ = tokio::time::timeout(Duration::from_secs(3), watcher.run(&mut state, false)).await;
+    assert_eq!(requests.load(Ordering::SeqCst), 1);
+    assert!(root.join("sketches-out/cabin.png").is_file());
+    assert!(state.files["cabin.png"].error.is_none());
+    std::fs::remove_dir_all(root)?;
+    Ok(())
+}
@@ -0,0 +1,86 @@
+#![cfg(feature = "image")]
+
+use anyhow::Result;
+use getimg::watermark::{Payload, Watermarker};
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImageView};
+
+const PAYLOAD: Payload = Payload {
+    team_id: 4242,
+    generation_id: 0xDEAD_BEEF,
+};
+
+fn jpeg(image: &DynamicImage, quality: u8) -> Result<DynamicImage> {
+    let mut bytes = Vec::new();
+    image
+        .to_rgb8()
+        .write_with_encoder(JpegEncoder::new_with_quality(&mut bytes, quality))?;
+    Ok(image::load_from_memory(&bytes)?)
+}
+
+fn marked() -> Result<(DynamicImage, DynamicImage)> {
+    let original = image::open("out/generated_image.png")?;
+    let marked = Watermarker::default().embed(&original, PAYLOAD);
+    Ok((original, marked))
+}
+
+#[test]
+fn test_detects_intact_watermark() -> Result<()> {
+    let (original, marked) = marked()?;
+
+    let detection = Watermarker::default().detect(&marked).unwrap();
+
+    assert_eq!(detection.payload, PAYLOAD);
+    assert!(detection.confidence > 0.8);
+    assert_eq!(marked.dimensions(), original.dimensions());
+    let error = original
+        .to_rgb8()
+        .pixels()
+        .zip(marked.to_rgb8().pixels())
+        .flat_map(|(a, b)| a.0.into_iter().zip(b.0).map(|(a, b)| a.abs_diff(b) as f64))
+        .sum::<f64>()
+        / (original.width() * original.height() * 3) as f64;
+    assert!(error < 3.0, "mean absolute error {}", error);
+    Ok(())
+}
+
+#[test]
+fn test_survives_jpeg_recompression() -> Result<()> {
+    let (_, marked) = marked()?;
+
+    let detection = Watermarker::default().detect(&jpeg(&marked, 70)?).unwrap();
+
+    assert_eq!(detection.payload, PAYLOAD);
+    Ok(())
+}
+
+#[test]
+fn test_survives_resizing() -> Result<()> {
+    let (_, marked) = marked()?;
+    let watermarker = Watermarker::default();
+
+    for scale in [0.5, 0.75, 1.5] {
+        let resized = marked.resize_exact(
+            (marked.width() as f64 * scale) as u32,
+            (marked.height() as f64 * scale) as u32,
+            FilterType::Lanczos3,
+        );
+        let detection = watermarker.detect(&jpeg(&resized, 85)?).unwrap();
+        assert_eq!(detection.payload, PAYLOAD, "scale {}", scale);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_ignores_unmarked_or_foreign_images() -> Result<()> {
+    let (original, marked) = marked()?;
+
+    assert_eq!(Watermarker::default().detect(&original), None);
+    let other_key = Watermarker {
+        key: 1,
+        ..Watermarker::default()
+    };
+    assert_eq!(other_key.detect(&marked), None);
+    Ok(())
+}